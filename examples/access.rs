@@ -0,0 +1,66 @@
+use rvfs::dentry::{path_walk, LookUpFlags};
+use rvfs::file::{vfs_access, vfs_close_file, vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::info::ProcessCred;
+use rvfs::inode::{AccessMode, Mode};
+use rvfs::link::vfs_symlink;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::StatFlags;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+
+    let file = vfs_open_file::<FakeFSC>(
+        "/secret",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o600),
+    )
+    .unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    vfs_symlink::<FakeFSC>("/secret", "/link").unwrap();
+    // F_OK only checks the existence of the file
+    vfs_access::<FakeFSC>("/secret", AccessMode::F_OK, StatFlags::empty()).unwrap();
+    assert!(vfs_access::<FakeFSC>("/none", AccessMode::F_OK, StatFlags::empty()).is_err());
+    // the file isn't opened by access
+    let lookup = path_walk::<FakeFSC>("/secret", LookUpFlags::empty()).unwrap();
+    assert!(lookup.mnt.super_block.find_file(&lookup.dentry).is_none());
+
+    // the real user isn't root, but the effective user is
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::new(1000, 1000, 0, 0);
+    let rw = AccessMode::R_OK | AccessMode::W_OK;
+    assert!(vfs_access::<FakeFSC>("/secret", rw, StatFlags::empty()).is_err());
+    vfs_access::<FakeFSC>("/secret", rw, StatFlags::AT_EACCESS).unwrap();
+    // root can't execute a file without execute bits
+    assert!(vfs_access::<FakeFSC>("/secret", AccessMode::X_OK, StatFlags::AT_EACCESS).is_err());
+    // the symlink itself can be read by others
+    let r = AccessMode::R_OK;
+    assert!(vfs_access::<FakeFSC>("/link", r, StatFlags::empty()).is_err());
+    vfs_access::<FakeFSC>("/link", r, StatFlags::AT_SYMLINK_NOFOLLOW).unwrap();
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::default();
+
+    // read-only mount
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/ro", Mode::from_bits_truncate(0o777)).unwrap();
+    do_mount::<FakeFSC>("", "/ro", "tmpfs", MountFlags::MNT_READ_ONLY, None).unwrap();
+    vfs_access::<FakeFSC>("/ro", AccessMode::R_OK, StatFlags::empty()).unwrap();
+    assert!(vfs_access::<FakeFSC>("/ro", AccessMode::W_OK, StatFlags::empty()).is_err());
+
+    // noexec mount
+    vfs_mkdir::<FakeFSC>("/noexec", Mode::from_bits_truncate(0o777)).unwrap();
+    do_mount::<FakeFSC>("", "/noexec", "tmpfs", MountFlags::MNT_NO_EXEC, None).unwrap();
+    vfs_open_file::<FakeFSC>(
+        "/noexec/run",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o755),
+    )
+    .unwrap();
+    vfs_access::<FakeFSC>("/noexec/run", AccessMode::R_OK, StatFlags::empty()).unwrap();
+    assert!(vfs_access::<FakeFSC>("/noexec/run", AccessMode::X_OK, StatFlags::empty()).is_err());
+    // the directory can still be searched
+    vfs_access::<FakeFSC>("/noexec", AccessMode::X_OK, StatFlags::empty()).unwrap();
+    println!("access ok");
+}
@@ -1,5 +1,6 @@
 use rvfs::dentry::vfs_truncate;
-use rvfs::file::{vfs_mkdir, vfs_open_file, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::stat::{
     vfs_getattr, vfs_getxattr, vfs_listxattr, vfs_removexattr, vfs_setxattr, StatFlags,
 };
@@ -10,7 +11,7 @@ fn main() {
     println!("init vfs");
     let rootfs = mount_rootfs();
     init_process_info(rootfs);
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
     vfs_setxattr::<FakeFSC>("/tmp", "type", "dir".as_bytes()).unwrap();
     vfs_setxattr::<FakeFSC>("/tmp", "target", "mount".as_bytes()).unwrap();
     listattr("/tmp");
@@ -27,7 +28,7 @@ fn main() {
     vfs_open_file::<FakeFSC>(
         "/tmp/f1",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     vfs_truncate::<FakeFSC>("/tmp/f1", 10).is_ok().then(|| {
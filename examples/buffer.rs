@@ -0,0 +1,204 @@
+use rvfs::buffer_cache::{sb_bread, BufferCache, BUFFER_CACHE};
+use rvfs::info::VfsError;
+use rvfs::superblock::{Device, SuperBlock};
+use std::sync::{Arc, Mutex};
+
+const BLOCK: usize = 512;
+
+/// a disk in memory, recording the blocks read and written
+#[derive(Debug)]
+struct MemDisk {
+    data: Mutex<Vec<u8>>,
+    reads: Mutex<Vec<usize>>,
+    writes: Mutex<Vec<usize>>,
+}
+
+impl MemDisk {
+    fn new(blocks: usize) -> Arc<Self> {
+        Arc::new(MemDisk {
+            data: Mutex::new((0..blocks * BLOCK).map(|i| (i / BLOCK) as u8).collect()),
+            reads: Mutex::new(Vec::new()),
+            writes: Mutex::new(Vec::new()),
+        })
+    }
+    fn reads(&self) -> usize {
+        self.reads.lock().unwrap().len()
+    }
+    fn take_writes(&self) -> Vec<usize> {
+        core::mem::take(&mut self.writes.lock().unwrap())
+    }
+}
+
+impl Device for MemDisk {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, VfsError> {
+        self.reads.lock().unwrap().push(offset / BLOCK);
+        buf.copy_from_slice(&self.data.lock().unwrap()[offset..offset + buf.len()]);
+        Ok(buf.len())
+    }
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, VfsError> {
+        self.writes.lock().unwrap().push(offset / BLOCK);
+        self.data.lock().unwrap()[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn size(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+}
+
+/// a disk looking into the global cache during the I/O
+#[derive(Debug)]
+struct CacheDisk(Arc<MemDisk>);
+
+impl Device for CacheDisk {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, VfsError> {
+        BUFFER_CACHE.len();
+        self.0.read(buf, offset)
+    }
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, VfsError> {
+        BUFFER_CACHE.len();
+        self.0.write(buf, offset)
+    }
+    fn size(&self) -> usize {
+        self.0.size()
+    }
+}
+
+fn main() {
+    env_logger::init();
+    let disk = MemDisk::new(16);
+    let dev: Arc<dyn Device> = disk.clone();
+    let cache = BufferCache::new(4 * BLOCK);
+
+    // the block is read from the device only once
+    let bh = cache.bread(&dev, 3, BLOCK).unwrap();
+    assert_eq!(bh.access_inner().data[0], 3);
+    cache.brelse(bh);
+    let bh = cache.bread(&dev, 3, BLOCK).unwrap();
+    cache.brelse(bh);
+    assert_eq!(disk.reads(), 1);
+    // getblk doesn't read the device
+    let bh = cache.getblk(&dev, 4, BLOCK).unwrap();
+    assert!(!bh.is_uptodate());
+    cache.brelse(bh);
+    assert_eq!(disk.reads(), 1);
+    // the block must be in the device
+    assert_eq!(
+        cache.bread(&dev, 16, BLOCK).unwrap_err(),
+        "Invalid argument"
+    );
+    assert_eq!(
+        cache.getblk(&dev, usize::MAX / 2, BLOCK).unwrap_err(),
+        "Invalid argument"
+    );
+    assert_eq!(
+        cache.getblk(&dev, usize::MAX, BLOCK).unwrap_err(),
+        "Invalid argument"
+    );
+
+    // the dirty buffers are written back in the order they were marked dirty
+    for block in [2, 0, 1] {
+        let bh = cache.bread(&dev, block, BLOCK).unwrap();
+        bh.access_inner().data.fill(0xaa);
+        cache.mark_dirty(&bh);
+        cache.brelse(bh);
+    }
+    // marking again doesn't change the order
+    let bh = cache.bread(&dev, 2, BLOCK).unwrap();
+    cache.mark_dirty(&bh);
+    assert!(bh.is_dirty());
+    assert!(disk.take_writes().is_empty());
+    cache.sync_dirty_buffers(Some(&dev)).unwrap();
+    assert_eq!(disk.take_writes(), vec![2, 0, 1]);
+    assert!(!bh.is_dirty());
+    assert_eq!(disk.data.lock().unwrap()[2 * BLOCK], 0xaa);
+    cache.brelse(bh);
+    // the clean buffers are not written again
+    cache.sync_dirty_buffers(None).unwrap();
+    assert!(disk.take_writes().is_empty());
+
+    // the least recently used buffer is evicted when the budget is exceeded
+    let cache = BufferCache::new(4 * BLOCK);
+    for block in 0..4 {
+        let bh = cache.bread(&dev, block, BLOCK).unwrap();
+        cache.brelse(bh);
+    }
+    let reads = disk.reads();
+    let bh = cache.bread(&dev, 0, BLOCK).unwrap();
+    cache.brelse(bh);
+    assert_eq!(disk.reads(), reads);
+    let bh = cache.bread(&dev, 4, BLOCK).unwrap();
+    cache.brelse(bh);
+    assert_eq!(cache.used(), 4 * BLOCK);
+    // block 1 is evicted, block 0 is still cached
+    let bh = cache.bread(&dev, 0, BLOCK).unwrap();
+    cache.brelse(bh);
+    assert_eq!(disk.reads(), reads + 1);
+    let bh = cache.bread(&dev, 1, BLOCK).unwrap();
+    cache.brelse(bh);
+    assert_eq!(disk.reads(), reads + 2);
+
+    // the buffers in use are not evicted, the cache grows beyond the budget
+    let held = (8..14)
+        .map(|block| cache.bread(&dev, block, BLOCK).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(cache.len(), 6);
+    assert_eq!(cache.used(), 6 * BLOCK);
+    held.into_iter().for_each(|bh| cache.brelse(bh));
+    assert_eq!(cache.used(), 4 * BLOCK);
+
+    // evicting a dirty buffer writes back the buffers dirtied before it first
+    let cache = BufferCache::new(3 * BLOCK);
+    let bhs = [5, 6, 7]
+        .iter()
+        .map(|&block| cache.bread(&dev, block, BLOCK).unwrap())
+        .collect::<Vec<_>>();
+    // dirty order: 7, 6, 5
+    for bh in bhs.iter().rev() {
+        cache.mark_dirty(bh);
+    }
+    bhs.into_iter().for_each(|bh| cache.brelse(bh));
+    // touch 5 and 7, block 6 is the least recently used
+    for block in [5, 7] {
+        let bh = cache.bread(&dev, block, BLOCK).unwrap();
+        cache.brelse(bh);
+    }
+    let bh = cache.bread(&dev, 8, BLOCK).unwrap();
+    assert_eq!(disk.take_writes(), vec![7, 6]);
+    cache.brelse(bh);
+    cache.sync_dirty_buffers(Some(&dev)).unwrap();
+    assert_eq!(disk.take_writes(), vec![5]);
+
+    // the clean and unused buffers are dropped by invalidate
+    cache.invalidate_buffers(&dev);
+    assert_eq!(cache.len(), 0);
+    assert_eq!(cache.used(), 0);
+
+    // the global cache is used through the super block
+    let sb = SuperBlock {
+        device: Some(dev.clone()),
+        block_size: BLOCK as u32,
+        ..SuperBlock::empty()
+    };
+    let bh = sb_bread(&sb, 9).unwrap();
+    assert_eq!(bh.access_inner().data[0], 9);
+    BUFFER_CACHE.brelse(bh);
+    let reads = disk.reads();
+    BUFFER_CACHE.brelse(sb_bread(&sb, 9).unwrap());
+    assert_eq!(disk.reads(), reads);
+    assert_eq!(
+        sb_bread(&SuperBlock::empty(), 0).unwrap_err(),
+        "No such device"
+    );
+
+    // the device is accessed without the lock of the cache
+    let cache_dev: Arc<dyn Device> = Arc::new(CacheDisk(disk.clone()));
+    let bh = BUFFER_CACHE.bread(&cache_dev, 10, BLOCK).unwrap();
+    bh.access_inner().data.fill(0xbb);
+    BUFFER_CACHE.mark_dirty(&bh);
+    BUFFER_CACHE.brelse(bh);
+    disk.take_writes();
+    BUFFER_CACHE.sync_dirty_buffers(Some(&cache_dev)).unwrap();
+    assert_eq!(disk.take_writes(), vec![10]);
+    assert_eq!(disk.data.lock().unwrap()[10 * BLOCK], 0xbb);
+    println!("buffer ok");
+}
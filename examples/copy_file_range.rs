@@ -0,0 +1,212 @@
+use rvfs::dentry::{DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{
+    vfs_clone_file, vfs_clone_file_range, vfs_copy_file_range, vfs_llseek, vfs_mkdir,
+    vfs_open_file, vfs_pread, vfs_pwrite, vfs_sendfile, File, FileOps, OpenFlags, OpenMode,
+    SeekFrom,
+};
+use rvfs::inode::{Inode, Mode};
+use rvfs::mmap::{vfs_fault, vfs_mmap, vfs_munmap, MapFlags, ProtFlags};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::page_cache::PAGE_SIZE;
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::vfs_getattr_by_file;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::{Arc, Mutex, Weak};
+
+const BLOCK: usize = 4096;
+
+static DISK: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static REMAP: Mutex<Option<(u64, u64, usize)>> = Mutex::new(None);
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+fn read(file: &Arc<File>) -> Vec<u8> {
+    let mut buf = vec![0; 16 * BLOCK];
+    let len = vfs_pread::<FakeFSC>(file.clone(), &mut buf, 0).unwrap();
+    buf.truncate(len);
+    buf
+}
+
+fn blocks(file: &Arc<File>) -> u64 {
+    vfs_getattr_by_file(file.clone()).unwrap().st_blocks
+}
+
+fn pos(file: &Arc<File>) -> u64 {
+    vfs_llseek(file.clone(), SeekFrom::Current(0)).unwrap()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let rw = OpenFlags::O_CREAT | OpenFlags::O_RDWR;
+
+    // a sparse file, the hole is copied as a hole by tmpfs
+    let src = open("/tmp/src", rw);
+    vfs_pwrite::<FakeFSC>(src.clone(), b"hello", 0).unwrap();
+    vfs_pwrite::<FakeFSC>(src.clone(), b"world", 4 * BLOCK as u64).unwrap();
+    let dst = open("/tmp/dst", rw);
+    let copy = |src: &Arc<File>, src_off, dst: &Arc<File>, dst_off, len| {
+        vfs_copy_file_range::<FakeFSC>(src.clone(), src_off, dst.clone(), dst_off, len)
+    };
+    assert_eq!(copy(&src, 0, &dst, 0, 1 << 20), Ok(4 * BLOCK + 5));
+    assert_eq!(read(&dst), read(&src));
+    assert_eq!(blocks(&dst), 2 * 8);
+    // the offsets of the files are not changed
+    assert_eq!((pos(&src), pos(&dst)), (0, 0));
+    // the copy stops at the end of src
+    assert_eq!(copy(&src, 4 * BLOCK as u64 + 2, &dst, 1, 100), Ok(3));
+    assert_eq!(&read(&dst)[..5], b"hrldo");
+    assert_eq!(copy(&src, 1 << 20, &dst, 0, 100), Ok(0));
+    // the ranges of the same file can't overlap
+    assert_eq!(copy(&src, 0, &src, 2, 5), Err("Invalid argument"));
+    assert_eq!(copy(&src, 0, &src, 2 * BLOCK as u64, 5), Ok(5));
+    assert_eq!(&read(&src)[2 * BLOCK..2 * BLOCK + 5], b"hello");
+
+    // the dirty page of a shared mapping is copied
+    let prot = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+    let vma = vfs_mmap(dst.clone(), 0, BLOCK, prot, MapFlags::MAP_SHARED, 0).unwrap();
+    let page = vfs_fault(&vma, 0, true).unwrap();
+    unsafe {
+        std::slice::from_raw_parts_mut(page as *mut u8, PAGE_SIZE)[..3].copy_from_slice(b"abc")
+    };
+    let other = open("/tmp/other", rw);
+    assert_eq!(copy(&dst, 0, &other, 0, 3), Ok(3));
+    assert_eq!(read(&other), b"abc");
+    // and the mapping sees the copied data
+    assert_eq!(copy(&src, 0, &dst, 0, 5), Ok(5));
+    assert_eq!(
+        unsafe { std::slice::from_raw_parts(page as *const u8, 5) },
+        b"hello"
+    );
+    vfs_munmap(vma).unwrap();
+
+    // the files of different filesystems
+    let root_file = open("/root_file", rw);
+    vfs_pwrite::<FakeFSC>(root_file.clone(), b"root data", 0).unwrap();
+    assert_eq!(
+        copy(&root_file, 0, &dst, 0, 9),
+        Err("Invalid cross-device link")
+    );
+    // sendfile copies them through a buffer and advances the offsets
+    let out = open("/tmp/out", rw);
+    assert_eq!(
+        vfs_sendfile::<FakeFSC>(out.clone(), root_file.clone(), None, 4),
+        Ok(4)
+    );
+    assert_eq!(
+        vfs_sendfile::<FakeFSC>(out.clone(), root_file.clone(), None, 100),
+        Ok(5)
+    );
+    assert_eq!(read(&out), b"root data");
+    assert_eq!((pos(&root_file), pos(&out)), (9, 9));
+    // the offset given is used and updated instead of the offset of in_file
+    let mut offset = 5;
+    assert_eq!(
+        vfs_sendfile::<FakeFSC>(out.clone(), root_file.clone(), Some(&mut offset), 4),
+        Ok(4)
+    );
+    assert_eq!((offset, pos(&root_file), pos(&out)), (9, 9, 13));
+    assert_eq!(read(&out), b"root datadata");
+    // the files on the same filesystem are copied by tmpfs
+    let sent = open("/tmp/sent", rw);
+    assert_eq!(
+        vfs_sendfile::<FakeFSC>(sent.clone(), src.clone(), None, 1 << 20),
+        Ok(4 * BLOCK + 5)
+    );
+    assert_eq!(read(&sent), read(&src));
+    assert_eq!(blocks(&sent), blocks(&src));
+    let append = open("/tmp/sent", OpenFlags::O_RDWR | OpenFlags::O_APPEND);
+    assert_eq!(
+        vfs_sendfile::<FakeFSC>(append.clone(), src.clone(), None, 1),
+        Err("Invalid argument")
+    );
+
+    // tmpfs can't share the blocks
+    let clone = open("/tmp/clone", rw);
+    assert_eq!(
+        vfs_clone_file::<FakeFSC>(src.clone(), clone.clone()),
+        Err("Operation not supported")
+    );
+    assert_eq!(read(&clone), b"");
+    // the range must be aligned, except the tail of the file
+    let clone_range = |src_off, dst_off, len| {
+        vfs_clone_file_range::<FakeFSC>(src.clone(), src_off, clone.clone(), dst_off, len)
+    };
+    assert_eq!(clone_range(1, 0, BLOCK), Err("Invalid argument"));
+    assert_eq!(clone_range(0, 0, 5), Err("Invalid argument"));
+    assert_eq!(clone_range(0, 0, 5 * BLOCK), Err("Invalid argument"));
+    assert_eq!(
+        clone_range(0, BLOCK as u64, BLOCK),
+        Err("Operation not supported")
+    );
+    assert_eq!(
+        vfs_clone_file::<FakeFSC>(root_file.clone(), clone.clone()),
+        Err("Invalid cross-device link")
+    );
+
+    // the access of the files is checked
+    let rdonly = open("/tmp/dst", OpenFlags::O_RDONLY);
+    assert_eq!(copy(&src, 0, &rdonly, 0, 1), Err("Bad file descriptor"));
+    assert_eq!(copy(&src, 0, &append, 0, 1), Err("Bad file descriptor"));
+    let dir = open("/tmp", OpenFlags::O_RDONLY);
+    assert_eq!(copy(&dir, 0, &dst, 0, 1), Err("Is a directory"));
+
+    // a filesystem without copy_file_range is copied with read and write
+    let inode = Inode::empty();
+    inode.access_inner().mode = Mode::S_FILE | Mode::from_bits_truncate(0o644);
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode,
+        DirEntryOps::empty(),
+        Weak::new(),
+        "disk",
+    );
+    let mut f_ops = FileOps::empty();
+    f_ops.write = |_, buf, offset| {
+        let mut disk = DISK.lock().unwrap();
+        let end = offset as usize + buf.len();
+        if disk.len() < end {
+            disk.resize(end, 0);
+        }
+        disk[offset as usize..end].copy_from_slice(buf);
+        Ok(buf.len())
+    };
+    let mut reflink_ops = f_ops.clone();
+    reflink_ops.remap_file_range = |_, src_off, _, dst_off, len| {
+        *REMAP.lock().unwrap() = Some((src_off, dst_off, len));
+        Ok(len)
+    };
+    let dentry = Arc::new(dentry);
+    let file = |f_ops| {
+        Arc::new(File::new(
+            dentry.clone(),
+            src.f_mnt.clone(),
+            OpenFlags::O_RDWR,
+            OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+            f_ops,
+        ))
+    };
+    let disk = file(f_ops);
+    assert_eq!(copy(&src, 0, &disk, 1, 1 << 20), Ok(4 * BLOCK + 5));
+    assert_eq!(DISK.lock().unwrap()[1..], read(&src)[..]);
+    // the filesystem sharing the blocks clones the range
+    let reflink = file(reflink_ops);
+    assert_eq!(
+        vfs_clone_file_range::<FakeFSC>(src.clone(), 0, reflink.clone(), 8 * BLOCK as u64, 0),
+        Ok(4 * BLOCK + 5)
+    );
+    assert_eq!(
+        *REMAP.lock().unwrap(),
+        Some((0, 8 * BLOCK as u64, 4 * BLOCK + 5))
+    );
+    println!("copy_file_range ok");
+}
@@ -1,5 +1,6 @@
 use rvfs::dentry::Dirent64Iterator;
-use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
 use std::sync::Arc;
 
@@ -8,35 +9,35 @@ fn main() {
     println!("init vfs");
     let rootfs = mount_rootfs();
     init_process_info(rootfs);
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE).unwrap();
-    let tmp = vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDWR, FileMode::FMODE_READ).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    let tmp = vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     vfs_open_file::<FakeFSC>(
         "/tmp/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     vfs_open_file::<FakeFSC>(
         "./tmp/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     let a_txt = vfs_open_file::<FakeFSC>(
         "./a.txt",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
 
-    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
+    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     readdir(root);
     // we set the cwd to /tmp
     PROCESS_FS_CONTEXT.lock().cwd = tmp.f_dentry.clone();
     let file = vfs_open_file::<FakeFSC>(
         "f2",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("file:{file:#?}");
@@ -44,20 +45,19 @@ fn main() {
     // f1 and f2
     readdir(tmp);
 
-    let file_ = vfs_open_file::<FakeFSC>("./f2", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
-    assert!(Arc::ptr_eq(&file, &file_));
-    let a_txt_ =
-        vfs_open_file::<FakeFSC>("../a.txt", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
-    assert!(Arc::ptr_eq(&a_txt, &a_txt_));
+    let file_ = vfs_open_file::<FakeFSC>("./f2", OpenFlags::O_RDWR, Mode::empty()).unwrap();
+    assert!(Arc::ptr_eq(&file.f_dentry, &file_.f_dentry));
+    let a_txt_ = vfs_open_file::<FakeFSC>("../a.txt", OpenFlags::O_RDWR, Mode::empty()).unwrap();
+    assert!(Arc::ptr_eq(&a_txt.f_dentry, &a_txt_.f_dentry));
 
-    vfs_mkdir::<FakeFSC>("./dir", FileMode::FMODE_WRITE).unwrap();
-    let dir = vfs_open_file::<FakeFSC>("./dir", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("./dir", Mode::from_bits_truncate(0o755)).unwrap();
+    let dir = vfs_open_file::<FakeFSC>("./dir", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
 
     PROCESS_FS_CONTEXT.lock().cwd = dir.f_dentry.clone();
 
     let a_txt__ =
-        vfs_open_file::<FakeFSC>("../../a.txt", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
-    assert!(Arc::ptr_eq(&a_txt, &a_txt__));
+        vfs_open_file::<FakeFSC>("../../a.txt", OpenFlags::O_RDWR, Mode::empty()).unwrap();
+    assert!(Arc::ptr_eq(&a_txt.f_dentry, &a_txt__.f_dentry));
 }
 
 fn readdir(dir: Arc<File>) {
@@ -1,5 +1,6 @@
 use rvfs::dentry::vfs_rmdir;
-use rvfs::file::{vfs_mkdir, vfs_open_file, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 
 fn main() {
@@ -10,20 +11,20 @@ fn main() {
     vfs_rmdir::<FakeFSC>("/")
         .is_err()
         .then(|| println!("rmdir / failed"));
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE)
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755))
         .is_ok()
         .then(|| println!("mkdir /tmp success"));
     vfs_rmdir::<FakeFSC>("/tmp").unwrap();
-    vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDWR, FileMode::FMODE_WRITE)
+    vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDWR, Mode::empty())
         .is_err()
         .then(|| println!("open /tmp failed"));
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE)
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755))
         .is_ok()
         .then(|| println!("mkdir /tmp success"));
     vfs_open_file::<FakeFSC>(
         "/tmp/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .is_ok()
     .then(|| println!("create /tmp/f1 success"));
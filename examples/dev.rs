@@ -1,10 +1,9 @@
 use rvfs::dentry::{vfs_rmdir, Dirent64Iterator};
 use rvfs::devfs::DEVFS_TYPE;
 use rvfs::file::{
-    vfs_llseek, vfs_mkdir, vfs_mknod, vfs_open_file, vfs_readdir, File, FileMode, OpenFlags,
-    SeekFrom,
+    vfs_llseek, vfs_mkdir, vfs_mknod, vfs_open_file, vfs_readdir, File, OpenFlags, SeekFrom,
 };
-use rvfs::inode::InodeMode;
+use rvfs::inode::Mode;
 use rvfs::link::{vfs_readlink, vfs_symlink};
 use rvfs::mount::{do_mount, MountFlags};
 use rvfs::stat::{vfs_getattr, StatFlags};
@@ -17,15 +16,15 @@ fn main() {
     let mnt = mount_rootfs();
     init_process_info(mnt);
     register_filesystem(DEVFS_TYPE).unwrap();
-    vfs_mkdir::<FakeFSC>("/dev", FileMode::FMODE_RDWR).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
     let _dev_mnt =
         do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::MNT_NO_DEV, None).unwrap();
     // println!("dev_mnt: {dev_mnt:#?}");
 
-    vfs_mkdir::<FakeFSC>("/dev/d0", FileMode::FMODE_RDWR).unwrap();
-    vfs_mkdir::<FakeFSC>("/dev/d1", FileMode::FMODE_RDWR).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev/d0", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev/d1", Mode::from_bits_truncate(0o755)).unwrap();
     println!("test vfs_open_file");
-    let dev = vfs_open_file::<FakeFSC>("/dev", OpenFlags::O_RDWR, FileMode::FMODE_RDWR).unwrap();
+    let dev = vfs_open_file::<FakeFSC>("/dev", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     // println!("dev: {dev:#?}");
     readdir(dev.clone());
     println!("test vfs_symlink");
@@ -39,7 +38,12 @@ fn main() {
     assert_eq!(r, len);
     println!("readlink: {:?}", String::from_utf8(buf).unwrap());
 
-    vfs_mknod::<FakeFSC>("./dev/tty", InodeMode::S_CHARDEV, FileMode::FMODE_RDWR, 9).unwrap();
+    vfs_mknod::<FakeFSC>(
+        "./dev/tty",
+        Mode::S_CHARDEV | Mode::from_bits_truncate(0o666),
+        9,
+    )
+    .unwrap();
     vfs_llseek(dev.clone(), SeekFrom::Start(0)).unwrap();
     readdir(dev.clone());
 
@@ -0,0 +1,63 @@
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{vfs_mkdir, vfs_mknod, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::{major, makedev, minor, register_filesystem};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    register_filesystem(DEVFS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::empty(), None).unwrap();
+    vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_mknod::<FakeFSC>(
+        "/dev/sda1",
+        Mode::S_BLKDEV | Mode::from_bits_truncate(0o660),
+        makedev(8, 1),
+    )
+    .unwrap();
+
+    // every super block has its own device number
+    let root_dev = vfs_getattr::<FakeFSC>("/", StatFlags::empty())
+        .unwrap()
+        .st_dev;
+    let tmp_dev = vfs_getattr::<FakeFSC>("/tmp", StatFlags::empty())
+        .unwrap()
+        .st_dev;
+    let dev_dev = vfs_getattr::<FakeFSC>("/dev", StatFlags::empty())
+        .unwrap()
+        .st_dev;
+    println!("rootfs: {root_dev:#x}, tmpfs: {tmp_dev:#x}, devfs: {dev_dev:#x}");
+    assert_ne!(root_dev, tmp_dev);
+    assert_ne!(root_dev, dev_dev);
+    assert_ne!(tmp_dev, dev_dev);
+    for dev in [root_dev, tmp_dev, dev_dev] {
+        assert_eq!(major(dev as u32), 0);
+        assert_ne!(minor(dev as u32), 0);
+    }
+
+    // files in the same file system share the device number
+    let stat = vfs_getattr::<FakeFSC>("/tmp/f1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_dev, tmp_dev);
+    assert_eq!(stat.st_rdev, 0);
+
+    // the device file reports its own device number in st_rdev
+    let stat = vfs_getattr::<FakeFSC>("/dev/sda1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_dev, dev_dev);
+    assert_eq!(major(stat.st_rdev as u32), 8);
+    assert_eq!(minor(stat.st_rdev as u32), 1);
+    println!("device number ok");
+}
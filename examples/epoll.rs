@@ -0,0 +1,291 @@
+use rvfs::dentry::{DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_open_file, vfs_read, vfs_write, File, FileExtOps, FileOps,
+    OpenFlags, OpenMode,
+};
+use rvfs::info::{ProcessCred, ProcessFs, ProcessFsInfo, VfsTime, WaitQueue};
+use rvfs::inode::{Inode, Mode};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::pipe::vfs_pipe2;
+use rvfs::poll::{
+    vfs_poll, Epoll, EpollCtlOp, EpollEvent, EpollEvents, PollEvents, PollWaitQueue, PollWaiter,
+};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, StrResult};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+use std::time::Duration;
+
+/// the wait queue of the kernel
+#[derive(Default)]
+struct CondvarQueue {
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+impl WaitQueue for CondvarQueue {
+    fn wait_event(&self, cond: &mut dyn FnMut() -> bool) -> StrResult<()> {
+        let mut guard = self.lock.lock().unwrap();
+        while !cond() {
+            guard = self.cond.wait(guard).unwrap();
+        }
+        Ok(())
+    }
+    fn wake_up(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.cond.notify_all();
+    }
+}
+
+/// the process sleeping on the condvar
+struct Task;
+
+impl ProcessFs for Task {
+    fn get_fs_info() -> ProcessFsInfo {
+        FakeFSC::get_fs_info()
+    }
+    fn check_nested_link() -> bool {
+        FakeFSC::check_nested_link()
+    }
+    fn update_link_data() {
+        FakeFSC::update_link_data()
+    }
+    fn max_link_count() -> u32 {
+        FakeFSC::max_link_count()
+    }
+    fn current_time() -> VfsTime {
+        FakeFSC::current_time()
+    }
+    fn update_umask(umask: Mode) {
+        FakeFSC::update_umask(umask)
+    }
+    fn get_cred() -> ProcessCred {
+        FakeFSC::get_cred()
+    }
+    fn new_wait_queue() -> Box<dyn WaitQueue> {
+        Box::<CondvarQueue>::default()
+    }
+}
+
+/// a waiter counting the wakeups
+#[derive(Default)]
+struct Counter(AtomicUsize);
+
+impl PollWaiter for Counter {
+    fn wake_up(&self, _events: PollEvents) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// the device readable when the interrupt comes
+static DEV_READY: AtomicBool = AtomicBool::new(false);
+static DEV_WAIT: PollWaitQueue = PollWaitQueue::new();
+
+fn interrupt() {
+    DEV_READY.store(true, Ordering::SeqCst);
+    DEV_WAIT.notify(PollEvents::POLLIN);
+}
+
+fn new_device(mnt: &Arc<File>) -> Arc<File> {
+    let inode = Inode::empty();
+    inode.access_inner().mode = Mode::S_CHARDEV | Mode::from_bits_truncate(0o644);
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode,
+        DirEntryOps::empty(),
+        Weak::new(),
+        "dev",
+    );
+    let file = File::new(
+        Arc::new(dentry),
+        mnt.f_mnt.clone(),
+        OpenFlags::O_RDONLY,
+        OpenMode::FMODE_READ,
+        FileOps::empty(),
+    );
+    file.access_inner().f_ops_ext = FileExtOps {
+        is_ready_read: |_| DEV_READY.load(Ordering::SeqCst),
+        is_ready_write: |_| false,
+        poll_wait: |_, waiter| DEV_WAIT.add(waiter),
+        ..FileExtOps::empty()
+    };
+    Arc::new(file)
+}
+
+fn wait(epoll: &Arc<Epoll>, block: bool) -> Vec<EpollEvent> {
+    let mut events = [EpollEvent::new(EpollEvents::empty(), 0); 8];
+    let len = epoll.wait(&mut events, block).unwrap();
+    events[..len].to_vec()
+}
+
+fn read(file: &Arc<File>) -> Vec<u8> {
+    let mut buf = vec![0; 100];
+    let len = vfs_read::<Task>(file.clone(), &mut buf).unwrap();
+    buf.truncate(len);
+    buf
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<Task>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<Task>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let all = PollEvents::POLLIN | PollEvents::POLLOUT;
+    let ev_in = EpollEvents::EPOLLIN;
+
+    // the regular file is always ready
+    let file = vfs_open_file::<Task>(
+        "/tmp/f",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    assert_eq!(vfs_poll(file.clone(), all, None), all);
+
+    // the pipe is readable with data, and the waiter is woken up
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    let counter = Arc::new(Counter::default());
+    assert_eq!(
+        vfs_poll(r.clone(), all, Some(counter.clone())),
+        PollEvents::empty()
+    );
+    assert_eq!(vfs_poll(w.clone(), all, None), PollEvents::POLLOUT);
+    vfs_write::<Task>(w.clone(), b"data").unwrap();
+    assert!(counter.0.load(Ordering::SeqCst) > 0);
+    assert_eq!(vfs_poll(r.clone(), all, None), PollEvents::POLLIN);
+
+    // level-triggered, the file is reported until it isn't ready
+    let epoll = Epoll::new::<Task>();
+    epoll
+        .ctl(EpollCtlOp::Add, r.clone(), EpollEvent::new(ev_in, 1))
+        .unwrap();
+    epoll
+        .ctl(
+            EpollCtlOp::Add,
+            file.clone(),
+            EpollEvent::new(EpollEvents::EPOLLOUT, 2),
+        )
+        .unwrap();
+    assert_eq!(
+        wait(&epoll, false),
+        [
+            EpollEvent::new(ev_in, 1),
+            EpollEvent::new(EpollEvents::EPOLLOUT, 2)
+        ]
+    );
+    assert_eq!(wait(&epoll, false).len(), 2);
+    assert_eq!(read(&r), b"data");
+    assert_eq!(
+        wait(&epoll, false),
+        [EpollEvent::new(EpollEvents::EPOLLOUT, 2)]
+    );
+    assert_eq!(
+        epoll.ctl(EpollCtlOp::Add, file.clone(), EpollEvent::new(ev_in, 2)),
+        Err("File exists")
+    );
+    epoll
+        .ctl(EpollCtlOp::Del, file.clone(), EpollEvent::new(ev_in, 0))
+        .unwrap();
+    assert_eq!(
+        epoll.ctl(EpollCtlOp::Del, file.clone(), EpollEvent::new(ev_in, 0)),
+        Err("No such file or directory")
+    );
+    assert_eq!(wait(&epoll, false), []);
+
+    // edge-triggered, the file is reported once for every change
+    let et = EpollEvent::new(ev_in | EpollEvents::EPOLLET, 3);
+    epoll.ctl(EpollCtlOp::Mod, r.clone(), et).unwrap();
+    vfs_write::<Task>(w.clone(), b"a").unwrap();
+    assert_eq!(wait(&epoll, false), [EpollEvent::new(ev_in, 3)]);
+    assert_eq!(wait(&epoll, false), []);
+    vfs_write::<Task>(w.clone(), b"b").unwrap();
+    assert_eq!(wait(&epoll, false), [EpollEvent::new(ev_in, 3)]);
+    // the space freed by the read isn't an event of the read end
+    let mut buf = [0u8; 1];
+    assert_eq!(vfs_read::<Task>(r.clone(), &mut buf), Ok(1));
+    assert_eq!(wait(&epoll, false), []);
+    assert_eq!(read(&r), b"b");
+
+    // one-shot, the file is reported once until it is modified
+    let oneshot = EpollEvent::new(ev_in | EpollEvents::EPOLLONESHOT, 4);
+    epoll.ctl(EpollCtlOp::Mod, r.clone(), oneshot).unwrap();
+    vfs_write::<Task>(w.clone(), b"c").unwrap();
+    assert_eq!(wait(&epoll, false), [EpollEvent::new(ev_in, 4)]);
+    vfs_write::<Task>(w.clone(), b"d").unwrap();
+    assert_eq!(wait(&epoll, false), []);
+    epoll.ctl(EpollCtlOp::Mod, r.clone(), oneshot).unwrap();
+    assert_eq!(wait(&epoll, false), [EpollEvent::new(ev_in, 4)]);
+    assert_eq!(read(&r), b"cd");
+
+    // the waiter sleeps until the writer comes
+    epoll
+        .ctl(EpollCtlOp::Mod, r.clone(), EpollEvent::new(ev_in, 5))
+        .unwrap();
+    let writer = {
+        let w = w.clone();
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            vfs_write::<Task>(w, b"e").unwrap();
+        })
+    };
+    assert_eq!(wait(&epoll, true), [EpollEvent::new(ev_in, 5)]);
+    writer.join().unwrap();
+    assert_eq!(read(&r), b"e");
+
+    // the device notifies the waiters
+    let dev = new_device(&file);
+    epoll
+        .ctl(EpollCtlOp::Add, dev.clone(), EpollEvent::new(ev_in, 6))
+        .unwrap();
+    assert_eq!(wait(&epoll, false), []);
+    let irq = thread::spawn(|| {
+        thread::sleep(Duration::from_millis(50));
+        interrupt();
+    });
+    assert_eq!(wait(&epoll, true), [EpollEvent::new(ev_in, 6)]);
+    irq.join().unwrap();
+    epoll
+        .ctl(EpollCtlOp::Del, dev, EpollEvent::new(ev_in, 0))
+        .unwrap();
+
+    // the reader is hung up without writer, the writer gets an error without reader
+    vfs_close_file::<Task>(w).unwrap();
+    assert_eq!(
+        wait(&epoll, false),
+        [EpollEvent::new(ev_in | EpollEvents::EPOLLHUP, 5)]
+    );
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    epoll
+        .ctl(
+            EpollCtlOp::Add,
+            w.clone(),
+            EpollEvent::new(EpollEvents::empty(), 7),
+        )
+        .unwrap();
+    vfs_close_file::<Task>(r).unwrap();
+    assert!(wait(&epoll, false).contains(&EpollEvent::new(EpollEvents::EPOLLERR, 7)));
+    assert_eq!(epoll.len(), 2);
+
+    // the epoll doesn't keep the closed file open, the reader sees the hang up
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    epoll
+        .ctl(
+            EpollCtlOp::Add,
+            w.clone(),
+            EpollEvent::new(EpollEvents::EPOLLOUT, 8),
+        )
+        .unwrap();
+    assert_eq!(epoll.len(), 3);
+    vfs_close_file::<Task>(w).unwrap();
+    assert!(vfs_poll(r.clone(), all, None).contains(PollEvents::POLLHUP));
+    assert_eq!(read(&r), b"");
+    assert!(wait(&epoll, false).iter().all(|event| event.data != 8));
+    assert_eq!(epoll.len(), 2);
+    println!("epoll ok");
+}
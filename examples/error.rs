@@ -1,5 +1,6 @@
 use rvfs::dentry::Dirent64Iterator;
-use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::stat::vfs_getattr_by_file;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 use std::sync::Arc;
@@ -8,27 +9,27 @@ fn main() {
     env_logger::init();
     let mnt = mount_rootfs();
     init_process_info(mnt);
-    vfs_mkdir::<FakeFSC>("/fs", FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/fs", Mode::from_bits_truncate(0o755)).unwrap();
     vfs_open_file::<FakeFSC>(
         "/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     vfs_open_file::<FakeFSC>(
         "/fddd",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     vfs_open_file::<FakeFSC>(
         "/123123",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
 
-    let root = vfs_open_file::<FakeFSC>(".", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
+    let root = vfs_open_file::<FakeFSC>(".", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
 
     let stat = vfs_getattr_by_file(root.clone()).unwrap();
     println!("stat: {stat:#?}");
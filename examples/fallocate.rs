@@ -0,0 +1,168 @@
+use rvfs::file::{
+    vfs_fallocate, vfs_llseek, vfs_mkdir, vfs_open_file, vfs_pread, vfs_pwrite, File, OpenFlags,
+    SeekFrom,
+};
+use rvfs::inode::{FallocFlags, Mode};
+use rvfs::link::vfs_unlink;
+use rvfs::mmap::{vfs_fault, vfs_mmap, MapFlags, ProtFlags};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::page_cache::PAGE_SIZE;
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr_by_file, vfs_statfs};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::Arc;
+
+const GB: usize = 1024 * 1024 * 1024;
+const BLOCK: usize = 4096;
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+/// (st_size, st_blocks)
+fn stat(file: &Arc<File>) -> (u64, u64) {
+    let stat = vfs_getattr_by_file(file.clone()).unwrap();
+    (stat.st_size, stat.st_blocks)
+}
+
+fn read(file: &Arc<File>, offset: usize, len: usize) -> Vec<u8> {
+    let mut buf = vec![0; len];
+    let len = vfs_pread::<FakeFSC>(file.clone(), &mut buf, offset as u64).unwrap();
+    buf.truncate(len);
+    buf
+}
+
+fn fallocate(file: &Arc<File>, mode: FallocFlags, offset: usize, len: usize) {
+    vfs_fallocate(file.clone(), mode, offset, len).unwrap()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let used = || vfs_statfs::<FakeFSC>("/tmp").unwrap().f_blocks;
+    let used_before = used();
+
+    // the write far beyond the end of file only allocates one block
+    let file = open("/tmp/sparse", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    vfs_pwrite::<FakeFSC>(file.clone(), b"x", GB as u64).unwrap();
+    assert_eq!(stat(&file), (GB as u64 + 1, 8));
+    assert_eq!(used(), used_before + 1);
+    assert_eq!(read(&file, GB - 2, 4), b"\0\0x");
+
+    // the data and the holes
+    assert_eq!(seek(&file, SeekFrom::Data(0)), Ok(GB as u64));
+    assert_eq!(seek(&file, SeekFrom::Hole(0)), Ok(0));
+    assert_eq!(seek(&file, SeekFrom::Hole(GB as u64)), Ok(GB as u64 + 1));
+    assert_eq!(
+        seek(&file, SeekFrom::Data(GB as u64 + 1)),
+        Err("No such device or address")
+    );
+    assert_eq!(
+        vfs_llseek(file.clone(), SeekFrom::Current(0)),
+        Ok(GB as u64 + 1)
+    );
+
+    // allocate the blocks without changing the data
+    fallocate(&file, FallocFlags::empty(), 0, 2 * BLOCK);
+    assert_eq!(stat(&file), (GB as u64 + 1, 3 * 8));
+    assert_eq!(seek(&file, SeekFrom::Data(0)), Ok(0));
+    assert_eq!(seek(&file, SeekFrom::Hole(0)), Ok(2 * BLOCK as u64));
+    assert_eq!(read(&file, 0, 4), [0; 4]);
+    // the space beyond the end of file
+    fallocate(&file, FallocFlags::FALLOC_FL_KEEP_SIZE, GB + 1, BLOCK);
+    assert_eq!(stat(&file), (GB as u64 + 1, 4 * 8));
+    // the blocks are allocated already, only the size changes
+    fallocate(&file, FallocFlags::empty(), GB + 1, BLOCK);
+    assert_eq!(stat(&file), (GB as u64 + BLOCK as u64 + 1, 4 * 8));
+    // the data is freed with the file
+    drop(file);
+    vfs_unlink::<FakeFSC>("/tmp/sparse").unwrap();
+    assert_eq!(used(), used_before);
+
+    // punch a hole, the partial blocks are zeroed
+    let file = open("/tmp/f1", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    vfs_pwrite::<FakeFSC>(file.clone(), &[b'a'; 3 * BLOCK], 0).unwrap();
+    let keep = FallocFlags::FALLOC_FL_KEEP_SIZE;
+    assert_eq!(
+        vfs_fallocate(file.clone(), FallocFlags::FALLOC_FL_PUNCH_HOLE, 0, 1),
+        Err("Operation not supported")
+    );
+    // the mapped page is updated in place
+    let rw = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+    let vma = vfs_mmap(file.clone(), 0, 3 * BLOCK, rw, MapFlags::MAP_SHARED, 0).unwrap();
+    let page = vfs_fault(&vma, 0, false).unwrap();
+    fallocate(
+        &file,
+        FallocFlags::FALLOC_FL_PUNCH_HOLE | keep,
+        100,
+        2 * BLOCK,
+    );
+    assert_eq!(stat(&file), (3 * BLOCK as u64, 2 * 8));
+    let data = read(&file, 0, 3 * BLOCK);
+    assert!(data[..100].iter().all(|&c| c == b'a'));
+    assert!(data[100..100 + 2 * BLOCK].iter().all(|&c| c == 0));
+    assert!(data[100 + 2 * BLOCK..].iter().all(|&c| c == b'a'));
+    let page = unsafe { std::slice::from_raw_parts(page as *const u8, PAGE_SIZE) };
+    assert_eq!(vfs_fault(&vma, 0, false), Ok(page.as_ptr() as usize));
+    assert_eq!(&page[98..102], b"aa\0\0");
+    assert_eq!(seek(&file, SeekFrom::Hole(0)), Ok(BLOCK as u64));
+    assert_eq!(
+        seek(&file, SeekFrom::Data(BLOCK as u64)),
+        Ok(2 * BLOCK as u64)
+    );
+
+    // zero a range and extend the file
+    fallocate(&file, FallocFlags::FALLOC_FL_ZERO_RANGE, 3 * BLOCK - 10, 20);
+    assert_eq!(stat(&file), (3 * BLOCK as u64 + 10, 3 * 8));
+    assert_eq!(
+        read(&file, 3 * BLOCK - 12, 100),
+        b"aa\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0"
+    );
+
+    // remove a block from the middle
+    vfs_pwrite::<FakeFSC>(file.clone(), &[b'b'; BLOCK], BLOCK as u64).unwrap();
+    let collapse = FallocFlags::FALLOC_FL_COLLAPSE_RANGE;
+    assert_eq!(
+        vfs_fallocate(file.clone(), collapse, 1, BLOCK),
+        Err("Invalid argument")
+    );
+    assert_eq!(
+        vfs_fallocate(file.clone(), collapse, 2 * BLOCK, 2 * BLOCK),
+        Err("Invalid argument")
+    );
+    assert_eq!(
+        vfs_fallocate(file.clone(), collapse | keep, 0, BLOCK),
+        Err("Invalid argument")
+    );
+    fallocate(&file, collapse, BLOCK, BLOCK);
+    assert_eq!(stat(&file).0, 2 * BLOCK as u64 + 10);
+    let data = read(&file, 0, 3 * BLOCK);
+    assert_eq!(data.len(), 2 * BLOCK + 10);
+    assert_eq!(data[99], b'a');
+    assert_eq!(data[BLOCK + 100], b'a');
+    assert_eq!(data[2 * BLOCK - 1], 0);
+    // the mapping follows the data
+    let page = vfs_fault(&vma, BLOCK, false).unwrap();
+    assert_eq!(unsafe { *(page as *const u8).add(100) }, b'a');
+
+    // the access of the file is checked
+    assert_eq!(
+        vfs_fallocate(file.clone(), FallocFlags::empty(), 0, 0),
+        Err("Invalid argument")
+    );
+    let rdonly = open("/tmp/f1", OpenFlags::O_RDONLY);
+    assert_eq!(
+        vfs_fallocate(rdonly, FallocFlags::empty(), 0, 1),
+        Err("Bad file descriptor")
+    );
+    println!("fallocate ok");
+}
+
+fn seek(file: &Arc<File>, whence: SeekFrom) -> Result<u64, &'static str> {
+    vfs_llseek(file.clone(), whence)
+}
@@ -0,0 +1,130 @@
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_mknod, vfs_open_file, vfs_read, vfs_write, File, OpenFlags,
+};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::{makedev, register_filesystem};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, StrResult};
+use std::sync::Arc;
+use std::thread;
+
+fn open(name: &str, flags: OpenFlags) -> StrResult<Arc<File>> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644))
+}
+
+fn read(file: &Arc<File>, len: usize) -> StrResult<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let len = vfs_read::<FakeFSC>(file.clone(), &mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn mkfifo(name: &str) {
+    vfs_mknod::<FakeFSC>(name, Mode::S_FIFO | Mode::from_bits_truncate(0o644), 0).unwrap();
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    register_filesystem(DEVFS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::empty(), None).unwrap();
+    let nonblock = OpenFlags::O_NONBLOCK;
+
+    mkfifo("/tmp/fifo");
+    let stat = vfs_getattr::<FakeFSC>("/tmp/fifo", StatFlags::empty()).unwrap();
+    assert_eq!(
+        stat.st_mode,
+        (Mode::S_FIFO | Mode::from_bits_truncate(0o644)).bits()
+    );
+    assert_eq!(stat.st_size, 0);
+    // the non-blocking writer fails without reader
+    assert_eq!(
+        open("/tmp/fifo", OpenFlags::O_WRONLY | nonblock).unwrap_err(),
+        "No such device or address"
+    );
+    // the non-blocking reader doesn't wait for a writer, and sees EOF
+    let r = open("/tmp/fifo", OpenFlags::O_RDONLY | nonblock).unwrap();
+    assert!(r.is_pipe());
+    assert_eq!(read(&r, 10), Ok(vec![]));
+    let w = open("/tmp/fifo", OpenFlags::O_WRONLY | nonblock).unwrap();
+    assert_eq!(read(&r, 10), Err("Resource temporarily unavailable"));
+    // all the opened files share the pipe
+    let w2 = open("/tmp/fifo", OpenFlags::O_WRONLY).unwrap();
+    vfs_write::<FakeFSC>(w.clone(), b"hello ").unwrap();
+    vfs_write::<FakeFSC>(w2.clone(), b"fifo").unwrap();
+    assert_eq!(read(&r, 100), Ok(b"hello fifo".to_vec()));
+    vfs_close_file::<FakeFSC>(w).unwrap();
+    vfs_close_file::<FakeFSC>(w2).unwrap();
+    assert_eq!(read(&r, 10), Ok(vec![]));
+    vfs_close_file::<FakeFSC>(r).unwrap();
+
+    // the reader and the writer wait for each other
+    let reader = thread::spawn(|| {
+        let r = open("/tmp/fifo", OpenFlags::O_RDONLY).unwrap();
+        let mut data = Vec::new();
+        loop {
+            let buf = read(&r, 100).unwrap();
+            if buf.is_empty() {
+                break;
+            }
+            data.extend(buf);
+        }
+        vfs_close_file::<FakeFSC>(r).unwrap();
+        data
+    });
+    let w = open("/tmp/fifo", OpenFlags::O_WRONLY).unwrap();
+    vfs_write::<FakeFSC>(w.clone(), &[1; 1000]).unwrap();
+    vfs_close_file::<FakeFSC>(w).unwrap();
+    assert_eq!(reader.join().unwrap(), vec![1; 1000]);
+
+    // O_RDWR never waits, the data is gone with the last file
+    let rw = open("/tmp/fifo", OpenFlags::O_RDWR | nonblock).unwrap();
+    vfs_write::<FakeFSC>(rw.clone(), b"lost").unwrap();
+    vfs_close_file::<FakeFSC>(rw).unwrap();
+    let rw = open("/tmp/fifo", OpenFlags::O_RDWR | nonblock).unwrap();
+    assert_eq!(read(&rw, 10), Err("Resource temporarily unavailable"));
+    vfs_close_file::<FakeFSC>(rw).unwrap();
+
+    // the FIFO of rootfs and devfs
+    for name in ["/fifo", "/dev/fifo"] {
+        mkfifo(name);
+        let rw = open(name, OpenFlags::O_RDWR).unwrap();
+        vfs_write::<FakeFSC>(rw.clone(), b"data").unwrap();
+        assert_eq!(read(&rw, 10), Ok(b"data".to_vec()));
+        vfs_close_file::<FakeFSC>(rw).unwrap();
+    }
+
+    // mknod creates the regular file and the device of tmpfs too
+    vfs_mknod::<FakeFSC>("/tmp/file", Mode::from_bits_truncate(0o644), 0).unwrap();
+    let file = open("/tmp/file", OpenFlags::O_RDWR).unwrap();
+    assert!(!file.is_pipe());
+    assert_eq!(vfs_write::<FakeFSC>(file, b"regular"), Ok(7));
+    assert_eq!(
+        vfs_getattr::<FakeFSC>("/tmp/file", StatFlags::empty())
+            .unwrap()
+            .st_size,
+        7
+    );
+    let dev = makedev(8, 1);
+    vfs_mknod::<FakeFSC>(
+        "/tmp/sda1",
+        Mode::S_BLKDEV | Mode::from_bits_truncate(0o660),
+        dev,
+    )
+    .unwrap();
+    let stat = vfs_getattr::<FakeFSC>("/tmp/sda1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_rdev, dev as u64);
+    assert_eq!(
+        vfs_mknod::<FakeFSC>("/tmp/dir", Mode::S_DIR | Mode::from_bits_truncate(0o755), 0),
+        Err("Invalid argument")
+    );
+    println!("fifo ok");
+}
@@ -0,0 +1,199 @@
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_open_file, vfs_pread, vfs_pwritev2, vfs_write, File, OpenFlags,
+    RwfFlags,
+};
+use rvfs::info::{ProcessCred, ProcessFs, ProcessFsInfo, VfsTime, WaitQueue};
+use rvfs::inode::Mode;
+use rvfs::link::vfs_unlink;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::{
+    register_filesystem, vfs_freeze_super, vfs_thaw_super, SuperBlock, SuperBlockOps,
+    SB_FREEZE_COMPLETE, SB_FREEZE_WRITE, SB_UNFROZEN,
+};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, StrResult};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+static SYNC_FS: AtomicUsize = AtomicUsize::new(0);
+static FREEZE_FS: AtomicUsize = AtomicUsize::new(0);
+static UNFREEZE_FS: AtomicUsize = AtomicUsize::new(0);
+static FAIL: AtomicBool = AtomicBool::new(false);
+static SLEEPS: AtomicUsize = AtomicUsize::new(0);
+
+/// the wait queue of the kernel, counting the tasks put to sleep
+#[derive(Default)]
+struct CondvarQueue {
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+impl WaitQueue for CondvarQueue {
+    fn wait_event(&self, cond: &mut dyn FnMut() -> bool) -> StrResult<()> {
+        let mut guard = self.lock.lock().unwrap();
+        while !cond() {
+            SLEEPS.fetch_add(1, Ordering::SeqCst);
+            guard = self.cond.wait(guard).unwrap();
+        }
+        Ok(())
+    }
+    fn wake_up(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.cond.notify_all();
+    }
+}
+
+/// the process sleeping on the condvar
+struct Task;
+
+impl ProcessFs for Task {
+    fn get_fs_info() -> ProcessFsInfo {
+        FakeFSC::get_fs_info()
+    }
+    fn check_nested_link() -> bool {
+        FakeFSC::check_nested_link()
+    }
+    fn update_link_data() {
+        FakeFSC::update_link_data()
+    }
+    fn max_link_count() -> u32 {
+        FakeFSC::max_link_count()
+    }
+    fn current_time() -> VfsTime {
+        FakeFSC::current_time()
+    }
+    fn update_umask(umask: Mode) {
+        FakeFSC::update_umask(umask)
+    }
+    fn get_cred() -> ProcessCred {
+        FakeFSC::get_cred()
+    }
+    fn new_wait_queue() -> Box<dyn WaitQueue> {
+        Box::<CondvarQueue>::default()
+    }
+}
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+
+    let file = open("/tmp/f1", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    vfs_write::<FakeFSC>(file.clone(), b"hello").unwrap();
+    let sb = file.f_mnt.super_block.clone();
+
+    // only a frozen filesystem can be thawed
+    assert_eq!(vfs_thaw_super(sb.clone()), Err("Invalid argument"));
+    // the freezer sleeps until the writer in progress is done
+    let guard = sb.start_write();
+    let freezer = {
+        let sb = sb.clone();
+        thread::spawn(move || vfs_freeze_super::<Task>(sb).unwrap())
+    };
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(sb.writers.frozen(), SB_FREEZE_WRITE);
+    assert_eq!(SLEEPS.load(Ordering::SeqCst), 1);
+    drop(guard);
+    freezer.join().unwrap();
+    assert_eq!(sb.writers.frozen(), SB_FREEZE_COMPLETE);
+    assert_eq!(sb.access_inner().dirty_inode.len(), 0);
+    assert_eq!(
+        vfs_freeze_super::<FakeFSC>(sb.clone()),
+        Err("Device or resource busy")
+    );
+
+    // the writer doesn't wait with RWF_NOWAIT
+    assert_eq!(
+        vfs_pwritev2::<FakeFSC>(file.clone(), &[b"x"], 0, RwfFlags::RWF_NOWAIT),
+        Err("Resource temporarily unavailable")
+    );
+    // the readers are not blocked
+    let mut buf = [0u8; 5];
+    assert_eq!(vfs_pread::<FakeFSC>(file.clone(), &mut buf, 0), Ok(5));
+    assert_eq!(&buf, b"hello");
+
+    // the writers wait until the filesystem is thawed
+    let done = Arc::new(AtomicUsize::new(0));
+    let handles = [
+        {
+            let (file, done) = (file.clone(), done.clone());
+            thread::spawn(move || {
+                vfs_write::<FakeFSC>(file, b" world").unwrap();
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+        },
+        {
+            let done = done.clone();
+            thread::spawn(move || {
+                vfs_close_file::<FakeFSC>(open("/tmp/f2", OpenFlags::O_CREAT)).unwrap();
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+        },
+        {
+            let done = done.clone();
+            thread::spawn(move || {
+                vfs_mkdir::<FakeFSC>("/tmp/d", Mode::from_bits_truncate(0o755)).unwrap();
+                done.fetch_add(1, Ordering::SeqCst);
+            })
+        },
+    ];
+    thread::sleep(Duration::from_millis(200));
+    assert_eq!(done.load(Ordering::SeqCst), 0);
+    // the writers sleep instead of spinning
+    assert_eq!(SLEEPS.load(Ordering::SeqCst), 4);
+    vfs_thaw_super(sb.clone()).unwrap();
+    assert_eq!(sb.writers.frozen(), SB_UNFROZEN);
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    assert_eq!(done.load(Ordering::SeqCst), 3);
+    let mut buf = [0u8; 11];
+    assert_eq!(vfs_pread::<FakeFSC>(file.clone(), &mut buf, 0), Ok(11));
+    assert_eq!(&buf, b"hello world");
+    vfs_unlink::<FakeFSC>("/tmp/f2").unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+
+    // the filesystem is synced before freeze_fs
+    let sb = Arc::new(SuperBlock {
+        super_block_ops: SuperBlockOps {
+            sync_fs: |_| {
+                SYNC_FS.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            freeze_fs: |_| {
+                assert_eq!(SYNC_FS.load(Ordering::SeqCst), 1);
+                if FAIL.load(Ordering::SeqCst) {
+                    return Err("I/O error");
+                }
+                FREEZE_FS.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            unfreeze_fs: |_| {
+                UNFREEZE_FS.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            ..SuperBlockOps::empty()
+        },
+        ..SuperBlock::empty()
+    });
+    vfs_freeze_super::<FakeFSC>(sb.clone()).unwrap();
+    assert_eq!(FREEZE_FS.load(Ordering::SeqCst), 1);
+    vfs_thaw_super(sb.clone()).unwrap();
+    assert_eq!(UNFREEZE_FS.load(Ordering::SeqCst), 1);
+
+    // the writers are let in again if freeze_fs fails
+    SYNC_FS.store(0, Ordering::SeqCst);
+    FAIL.store(true, Ordering::SeqCst);
+    assert_eq!(vfs_freeze_super::<FakeFSC>(sb.clone()), Err("I/O error"));
+    assert_eq!(sb.writers.frozen(), SB_UNFROZEN);
+    assert!(sb.try_start_write().is_some());
+    assert_eq!(sb.writers.count(), 0);
+    println!("freeze ok");
+}
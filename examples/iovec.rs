@@ -0,0 +1,165 @@
+use rvfs::file::{
+    vfs_close_file, vfs_fsync, vfs_llseek, vfs_mkdir, vfs_open_file, vfs_preadv2, vfs_pwritev2,
+    vfs_readv, vfs_writev, File, OpenFlags, RwfFlags, SeekFrom,
+};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::Arc;
+use std::thread;
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+fn pos(file: &Arc<File>) -> u64 {
+    vfs_llseek(file.clone(), SeekFrom::Current(0)).unwrap()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+
+    // writev and readv use the file offset
+    let file = open("/tmp/f1", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    assert_eq!(
+        vfs_writev::<FakeFSC>(file.clone(), &[b"hello", b" ", b"world"]),
+        Ok(11)
+    );
+    assert_eq!(pos(&file), 11);
+    vfs_llseek(file.clone(), SeekFrom::Start(0)).unwrap();
+    let (mut a, mut b) = ([0u8; 3], [0u8; 4]);
+    assert_eq!(
+        vfs_readv::<FakeFSC>(file.clone(), &mut [&mut a, &mut b]),
+        Ok(7)
+    );
+    assert_eq!((&a, &b), (b"hel", b"lo w"));
+    // the read stops at the end of file
+    let mut c = [0u8; 8];
+    assert_eq!(
+        vfs_readv::<FakeFSC>(file.clone(), &mut [&mut a, &mut c]),
+        Ok(4)
+    );
+    assert_eq!(&a, b"orl");
+    assert_eq!(c[0], b'd');
+    assert_eq!(pos(&file), 11);
+
+    // preadv2 and pwritev2 with an offset don't change the file offset
+    let mut buf = [0u8; 5];
+    assert_eq!(
+        vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut buf], 6, RwfFlags::empty()),
+        Ok(5)
+    );
+    assert_eq!(&buf, b"world");
+    vfs_pwritev2::<FakeFSC>(file.clone(), &[b"H"], 0, RwfFlags::empty()).unwrap();
+    assert_eq!(pos(&file), 11);
+    // -1 means the current file offset
+    vfs_llseek(file.clone(), SeekFrom::Start(0)).unwrap();
+    assert_eq!(
+        vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut buf], -1, RwfFlags::empty()),
+        Ok(5)
+    );
+    assert_eq!(&buf, b"Hello");
+    assert_eq!(pos(&file), 5);
+    assert_eq!(
+        vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut buf], -2, RwfFlags::empty()),
+        Err("Invalid argument")
+    );
+
+    // RWF_APPEND appends the data even without O_APPEND
+    assert_eq!(
+        vfs_pwritev2::<FakeFSC>(file.clone(), &[b"!", b"?"], 0, RwfFlags::RWF_APPEND),
+        Ok(2)
+    );
+    assert_eq!(pos(&file), 5);
+    vfs_pwritev2::<FakeFSC>(file.clone(), &[b"#"], -1, RwfFlags::RWF_APPEND).unwrap();
+    assert_eq!(pos(&file), 14);
+    let mut data = [0u8; 16];
+    assert_eq!(
+        vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut data], 0, RwfFlags::empty()),
+        Ok(14)
+    );
+    assert_eq!(&data[..14], b"Hello world!?#");
+    // the data is synced with RWF_DSYNC
+    vfs_pwritev2::<FakeFSC>(file.clone(), &[b"."], 14, RwfFlags::RWF_DSYNC).unwrap();
+    // the data is written even if the sync fails, the error is reported by the next fsync
+    let mut f_ops = file.f_ops.clone();
+    f_ops.fsync = |_, _| Err("I/O error");
+    let bad = Arc::new(File::new(
+        file.f_dentry.clone(),
+        file.f_mnt.clone(),
+        OpenFlags::O_RDWR,
+        file.f_mode,
+        f_ops,
+    ));
+    assert_eq!(
+        vfs_pwritev2::<FakeFSC>(bad.clone(), &[b"."], 15, RwfFlags::RWF_SYNC),
+        Ok(1)
+    );
+    assert_eq!(
+        vfs_pwritev2::<FakeFSC>(bad.clone(), &[b""], 16, RwfFlags::RWF_DSYNC),
+        Err("I/O error")
+    );
+    assert_eq!(vfs_fsync(file.clone()), Err("I/O error"));
+    assert_eq!(vfs_fsync(file.clone()), Ok(()));
+
+    // RWF_NOWAIT doesn't wait for the others
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    {
+        let _guard = inode.write_lock.lock();
+        assert_eq!(
+            vfs_pwritev2::<FakeFSC>(file.clone(), &[b"x"], 0, RwfFlags::RWF_NOWAIT),
+            Err("Resource temporarily unavailable")
+        );
+    }
+    {
+        let _guard = file.lock_pos();
+        assert_eq!(
+            vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut buf], -1, RwfFlags::RWF_NOWAIT),
+            Err("Resource temporarily unavailable")
+        );
+        // an explicit offset doesn't need the file offset
+        assert_eq!(
+            vfs_preadv2::<FakeFSC>(file.clone(), &mut [&mut buf], 0, RwfFlags::RWF_NOWAIT),
+            Ok(5)
+        );
+    }
+    vfs_pwritev2::<FakeFSC>(file.clone(), &[b"x"], 0, RwfFlags::RWF_NOWAIT).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+
+    // the buffers of a writev are not split by the other appenders
+    let (threads, count) = (4, 100);
+    let handles = (0..threads)
+        .map(|i| {
+            thread::spawn(move || {
+                let file = open(
+                    "/tmp/log",
+                    OpenFlags::O_CREAT | OpenFlags::O_WRONLY | OpenFlags::O_APPEND,
+                );
+                let tag = [b'a' + i as u8; 4];
+                for _ in 0..count {
+                    vfs_writev::<FakeFSC>(file.clone(), &[&tag, &tag, b"\n"]).unwrap();
+                }
+                vfs_close_file::<FakeFSC>(file).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    let file = open("/tmp/log", OpenFlags::O_RDONLY);
+    let (mut head, mut tail) = ([0u8; 4], [0u8; 5]);
+    let mut records = 0;
+    while vfs_readv::<FakeFSC>(file.clone(), &mut [&mut head, &mut tail]).unwrap() > 0 {
+        assert!(head.iter().chain(&tail[..4]).all(|&c| c == head[0]));
+        assert_eq!(tail[4], b'\n');
+        records += 1;
+    }
+    assert_eq!(records, threads * count);
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    println!("iovec ok");
+}
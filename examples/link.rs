@@ -1,5 +1,6 @@
 use rvfs::dentry::Dirent64Iterator;
-use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, vfs_write_file, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, vfs_write_file, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::link::{vfs_link, vfs_readlink, vfs_symlink, vfs_unlink};
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 
@@ -11,30 +12,24 @@ fn main() {
     // let lookup_data = path_walk::<FakeFSC>("/", LookUpFlags::DIRECTORY).unwrap();
     // println!("lookup_data: {:#?}", lookup_data);
     println!("mkdir /tmp");
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE).unwrap();
-    let file0 = vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDWR, FileMode::FMODE_READ).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    let file0 = vfs_open_file::<FakeFSC>("/tmp", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     println!("file: {file0:#?}");
     let file = vfs_open_file::<FakeFSC>(
         "/tmp/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_READ | FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("file: {file:#?}");
     vfs_link::<FakeFSC>("/tmp/f1", "/tmp/f2").unwrap();
     println!("link ok ......");
-    let file_f2 = vfs_open_file::<FakeFSC>(
-        "/tmp/f2",
-        OpenFlags::O_RDWR,
-        FileMode::FMODE_READ | FileMode::FMODE_WRITE,
-    )
-    .unwrap();
+    let file_f2 = vfs_open_file::<FakeFSC>("/tmp/f2", OpenFlags::O_RDWR, Mode::empty()).unwrap();
     println!("file: {file_f2:#?}");
 
     vfs_symlink::<FakeFSC>("/tmp", "/tmp/f3").unwrap();
     println!("symlink ok ......");
-    let file =
-        vfs_open_file::<FakeFSC>("/tmp/f3", OpenFlags::O_RDWR, FileMode::FMODE_READ).unwrap();
+    let file = vfs_open_file::<FakeFSC>("/tmp/f3", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     println!("file: {file:#?}");
 
     println!("--------------------------------------");
@@ -1,5 +1,6 @@
 use rvfs::dentry::{path_walk, LookUpFlags};
-use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_read_file, vfs_write_file, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_read_file, vfs_write_file, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::link::{vfs_link, vfs_symlink, vfs_unlink};
 use rvfs::mount::{do_mount, MountFlags};
 use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
@@ -18,7 +19,7 @@ fn main() {
 
     println!("--------------------------------------");
     println!("mkdir /tmp");
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
     println!("mkdir /tmp ok ......");
 
     println!("--------------------------------------");
@@ -36,7 +37,7 @@ fn main() {
     let file = vfs_open_file::<FakeFSC>(
         "/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE | FileMode::FMODE_READ,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("test create file /f1 ok ......");
@@ -65,7 +66,7 @@ fn main() {
     println!("----------------------------------------");
     println!("mkdir /tmp/tt1, it should in tmpfs root dir");
 
-    vfs_mkdir::<FakeFSC>("/tmp/tt1", FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp/tt1", Mode::from_bits_truncate(0o755)).unwrap();
     println!("mkdir /tmp/tt1 ok ......");
 
     let temp_find = path_walk::<FakeFSC>("/tmp/tt1", LookUpFlags::DIRECTORY).unwrap();
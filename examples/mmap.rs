@@ -0,0 +1,240 @@
+use rvfs::dentry::{vfs_truncate_by_file, DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_open_file, vfs_pread, vfs_write, File, FileOps, OpenFlags,
+    OpenMode,
+};
+use rvfs::inode::{DirtyFlags, Inode, Mode};
+use rvfs::mmap::{
+    generic_file_mmap, vfs_fault, vfs_mmap, vfs_msync, vfs_munmap, MapFlags, MsyncFlags, ProtFlags,
+    VmArea,
+};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::page_cache::{generic_file_fsync, AddressSpaceOps, PAGE_SIZE};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+static DISK: Mutex<Vec<u8>> = Mutex::new(Vec::new());
+static READ_PAGE: AtomicUsize = AtomicUsize::new(0);
+static WRITE_PAGE: AtomicUsize = AtomicUsize::new(0);
+
+const START: usize = 0x1000_0000;
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+fn map(file: &Arc<File>, len: usize, prot: ProtFlags, flags: MapFlags) -> VmArea {
+    vfs_mmap(file.clone(), START, len, prot, flags, 0).unwrap()
+}
+
+/// the page returned by the page fault
+fn page<'a>(addr: usize) -> &'a mut [u8] {
+    unsafe { std::slice::from_raw_parts_mut(addr as *mut u8, PAGE_SIZE) }
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs.clone());
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+
+    // a file of two pages and a half
+    let file = open("/tmp/f1", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    let data = (0..5 * PAGE_SIZE / 2)
+        .map(|i| (i / PAGE_SIZE) as u8 + 1)
+        .collect::<Vec<_>>();
+    vfs_write::<FakeFSC>(file.clone(), &data).unwrap();
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let rw = ProtFlags::PROT_READ | ProtFlags::PROT_WRITE;
+
+    // the pages are served from the file
+    let vma = map(&file, data.len(), rw, MapFlags::MAP_SHARED);
+    assert_eq!(vma.vm_end - vma.vm_start, 3 * PAGE_SIZE);
+    let p0 = vfs_fault(&vma, START, false).unwrap();
+    assert!(page(p0).iter().all(|&c| c == 1));
+    let p2 = vfs_fault(&vma, START + 2 * PAGE_SIZE + 10, false).unwrap();
+    assert_eq!(page(p2)[PAGE_SIZE / 2 - 1], 3);
+    // the tail of the last page is zero
+    assert_eq!(page(p2)[PAGE_SIZE / 2], 0);
+    // the address outside of the mapping or the file
+    assert_eq!(
+        vfs_fault(&vma, START + 3 * PAGE_SIZE, false),
+        Err("Bad address")
+    );
+    let far = map(&file, 8 * PAGE_SIZE, rw, MapFlags::MAP_SHARED);
+    assert_eq!(
+        vfs_fault(&far, START + 4 * PAGE_SIZE, false),
+        Err("Bad address")
+    );
+    vfs_munmap(far).unwrap();
+
+    // all shared mappings see the same page
+    let other = map(&file, PAGE_SIZE, rw, MapFlags::MAP_SHARED);
+    assert_eq!(vfs_fault(&other, START, false), Ok(p0));
+    // the write to the mapping is seen by read
+    let p0 = vfs_fault(&vma, START, true).unwrap();
+    page(p0)[..5].copy_from_slice(b"hello");
+    let mut buf = [0u8; 6];
+    vfs_pread::<FakeFSC>(file.clone(), &mut buf, 0).unwrap();
+    assert_eq!(&buf, b"hello\x01");
+    assert_eq!(inode.mapping.nr_dirty(), 1);
+    // the write to the file is seen by the mapping
+    vfs_write::<FakeFSC>(file.clone(), b"world").unwrap();
+    assert_eq!(&page(p2)[PAGE_SIZE / 2..PAGE_SIZE / 2 + 5], b"world");
+    vfs_munmap(other).unwrap();
+    assert_eq!(inode.mapping.nr_dirty(), 0);
+
+    // msync writes the dirty pages back
+    page(vfs_fault(&vma, START + PAGE_SIZE, true).unwrap())[0] = b'!';
+    assert_eq!(inode.mapping.nr_dirty(), 1);
+    assert_eq!(
+        vfs_msync(&vma, START + 1, PAGE_SIZE, MsyncFlags::MS_SYNC),
+        Err("Invalid argument")
+    );
+    vfs_msync(&vma, START, PAGE_SIZE, MsyncFlags::MS_SYNC).unwrap();
+    assert_eq!(inode.mapping.nr_dirty(), 0);
+    vfs_pread::<FakeFSC>(file.clone(), &mut buf[..1], PAGE_SIZE as u64).unwrap();
+    assert_eq!(buf[0], b'!');
+    // the pages can be mapped by the kernel
+    assert_eq!(p0 % PAGE_SIZE, 0);
+    assert_eq!(p2 % PAGE_SIZE, 0);
+    // the truncated page stays valid until it is unmapped
+    vfs_truncate_by_file(file.clone(), PAGE_SIZE).unwrap();
+    assert_eq!(inode.mapping.nr_pages(), 1);
+    assert_eq!(&page(p2)[PAGE_SIZE / 2..PAGE_SIZE / 2 + 5], b"world");
+    page(p2)[0] = b'?';
+    assert_eq!(
+        vfs_fault(&vma, START + 2 * PAGE_SIZE, false),
+        Err("Bad address")
+    );
+    vfs_munmap(vma).unwrap();
+
+    // the private mapping is never written back
+    let private = map(&file, PAGE_SIZE, rw, MapFlags::MAP_PRIVATE);
+    vfs_fault(&private, START, true).unwrap();
+    assert_eq!(inode.mapping.nr_dirty(), 0);
+    vfs_munmap(private).unwrap();
+    // a read only mapping can't be written
+    let ro = map(&file, PAGE_SIZE, ProtFlags::PROT_READ, MapFlags::MAP_SHARED);
+    assert_eq!(vfs_fault(&ro, START, true), Err("Bad address"));
+    vfs_munmap(ro).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+
+    // the access of the file is checked
+    let rdonly = open("/tmp/f1", OpenFlags::O_RDONLY);
+    assert_eq!(
+        vfs_mmap(
+            rdonly.clone(),
+            START,
+            PAGE_SIZE,
+            rw,
+            MapFlags::MAP_SHARED,
+            0
+        )
+        .unwrap_err(),
+        "Permission denied"
+    );
+    // a private writable mapping of a read only file is fine
+    map(&rdonly, PAGE_SIZE, rw, MapFlags::MAP_PRIVATE);
+    let flags = MapFlags::MAP_SHARED | MapFlags::MAP_PRIVATE;
+    let prot = ProtFlags::PROT_READ;
+    let mmap = |file: &Arc<File>, start, len, flags| {
+        vfs_mmap(file.clone(), start, len, prot, flags, 0).unwrap_err()
+    };
+    assert_eq!(mmap(&rdonly, START, PAGE_SIZE, flags), "Invalid argument");
+    assert_eq!(
+        mmap(&rdonly, START, 0, MapFlags::MAP_SHARED),
+        "Invalid argument"
+    );
+    assert_eq!(
+        mmap(&rdonly, START + 1, 1, MapFlags::MAP_SHARED),
+        "Invalid argument"
+    );
+    let wronly = open("/tmp/f1", OpenFlags::O_WRONLY);
+    assert_eq!(
+        mmap(&wronly, START, 1, MapFlags::MAP_SHARED),
+        "Permission denied"
+    );
+    let dir = open("/tmp", OpenFlags::O_RDONLY);
+    assert_eq!(mmap(&dir, START, 1, MapFlags::MAP_SHARED), "No such device");
+
+    // an executable is mapped from the rootfs
+    let elf = open("/elf", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    vfs_write::<FakeFSC>(elf.clone(), b"\x7fELF").unwrap();
+    let prot = ProtFlags::PROT_READ | ProtFlags::PROT_EXEC;
+    let text = vfs_mmap(
+        elf.clone(),
+        START,
+        PAGE_SIZE,
+        prot,
+        MapFlags::MAP_PRIVATE,
+        0,
+    )
+    .unwrap();
+    assert_eq!(
+        &page(vfs_fault(&text, START, false).unwrap())[..4],
+        b"\x7fELF"
+    );
+    vfs_munmap(text).unwrap();
+
+    // a filesystem mapping the file through the page cache
+    *DISK.lock().unwrap() = vec![7; 2 * PAGE_SIZE];
+    let mut inode = Inode::empty();
+    inode.mapping.a_ops = AddressSpaceOps {
+        readpage: |_, index, buf| {
+            READ_PAGE.fetch_add(1, Ordering::SeqCst);
+            let start = index * PAGE_SIZE;
+            buf.copy_from_slice(&DISK.lock().unwrap()[start..start + PAGE_SIZE]);
+            Ok(())
+        },
+        writepage: |_, index, buf| {
+            WRITE_PAGE.fetch_add(1, Ordering::SeqCst);
+            let start = index * PAGE_SIZE;
+            DISK.lock().unwrap()[start..start + buf.len()].copy_from_slice(buf);
+            Ok(())
+        },
+        ..AddressSpaceOps::empty()
+    };
+    let mut f_ops = FileOps::empty();
+    f_ops.mmap = generic_file_mmap;
+    f_ops.fsync = generic_file_fsync;
+    inode.access_inner().mode = Mode::S_FILE | Mode::from_bits_truncate(0o644);
+    inode.access_inner().file_size = 2 * PAGE_SIZE;
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode.clone(),
+        DirEntryOps::empty(),
+        Weak::new(),
+        "disk",
+    );
+    let file = Arc::new(File::new(
+        Arc::new(dentry),
+        rootfs,
+        OpenFlags::O_RDWR,
+        OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+        f_ops,
+    ));
+    let vma = map(&file, 2 * PAGE_SIZE, rw, MapFlags::MAP_SHARED);
+    let p1 = vfs_fault(&vma, START + PAGE_SIZE, false).unwrap();
+    assert_eq!(vfs_fault(&vma, START + PAGE_SIZE + 1, false), Ok(p1));
+    assert_eq!(READ_PAGE.load(Ordering::SeqCst), 1);
+    assert!(page(p1).iter().all(|&c| c == 7));
+    // page_mkwrite dirties the page and the inode
+    vfs_fault(&vma, START + PAGE_SIZE, true).unwrap();
+    page(p1)[0] = 8;
+    assert!(inode
+        .access_inner()
+        .dirty
+        .contains(DirtyFlags::I_DIRTY_PAGES));
+    vfs_munmap(vma).unwrap();
+    assert_eq!(WRITE_PAGE.load(Ordering::SeqCst), 1);
+    assert_eq!(DISK.lock().unwrap()[PAGE_SIZE], 8);
+    println!("mmap ok");
+}
@@ -0,0 +1,45 @@
+use rvfs::file::{vfs_open_file, vfs_umask, OpenFlags, OpenMode};
+use rvfs::inode::Mode;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+
+    // the default umask is 0o022
+    let file = vfs_open_file::<FakeFSC>(
+        "/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_WRONLY,
+        Mode::from_bits_truncate(0o666),
+    )
+    .unwrap();
+    assert_eq!(file.f_mode, OpenMode::FMODE_WRITE);
+    let stat = vfs_getattr::<FakeFSC>("/f1", StatFlags::empty()).unwrap();
+    let mode = Mode::from_bits_truncate(stat.st_mode);
+    println!("f1 mode: {:o}", stat.st_mode);
+    assert!(mode.is_file());
+    assert_eq!(mode.file_type(), Mode::S_FILE);
+    assert_eq!(mode.permission().bits(), 0o644);
+
+    let old = vfs_umask::<FakeFSC>(Mode::S_IRWXG | Mode::S_IRWXO);
+    assert_eq!(old.bits(), 0o022);
+    let file = vfs_open_file::<FakeFSC>(
+        "/f2",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::S_ISUID | Mode::from_bits_truncate(0o777),
+    )
+    .unwrap();
+    assert_eq!(file.f_mode, OpenMode::FMODE_READ | OpenMode::FMODE_WRITE);
+    let stat = vfs_getattr::<FakeFSC>("/f2", StatFlags::empty()).unwrap();
+    println!("f2 mode: {:o}", stat.st_mode);
+    assert_eq!(stat.st_mode, 0o104700);
+
+    let mode = Mode::S_FIFO | Mode::from_bits_truncate(0o600);
+    assert!(mode.is_fifo());
+    assert!(!mode.is_file());
+    let mode = Mode::S_SYMLINK | Mode::from_bits_truncate(0o777);
+    assert!(mode.is_symlink());
+    assert!(!mode.is_file() && !mode.is_dir());
+}
@@ -0,0 +1,106 @@
+use rvfs::dentry::{vfs_rename, vfs_rmdir};
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{vfs_close_file, vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::link::vfs_link;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, KStat, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn stat(name: &str) -> KStat {
+    vfs_getattr::<FakeFSC>(name, StatFlags::empty()).unwrap()
+}
+
+fn mkdir(name: &str) {
+    vfs_mkdir::<FakeFSC>(name, Mode::from_bits_truncate(0o755)).unwrap();
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    register_filesystem(DEVFS_TYPE).unwrap();
+
+    // the root dir has "." and ".."
+    assert_eq!(stat("/").st_nlink, 2);
+    mkdir("/tmp");
+    mkdir("/dev");
+    assert_eq!(stat("/").st_nlink, 4);
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::empty(), None).unwrap();
+
+    for base in ["/tmp", "/dev"] {
+        assert_eq!(stat(base).st_nlink, 2);
+        let a = format!("{base}/a");
+        let b = format!("{base}/b");
+        mkdir(&a);
+        mkdir(&b);
+        mkdir(&format!("{a}/sub"));
+        assert_eq!(stat(base).st_nlink, 4);
+        assert_eq!(stat(&a).st_nlink, 3);
+        assert_eq!(stat(&format!("{a}/sub")).st_nlink, 2);
+        vfs_rmdir::<FakeFSC>(&format!("{a}/sub")).unwrap();
+        assert_eq!(stat(&a).st_nlink, 2);
+        vfs_rmdir::<FakeFSC>(&b).unwrap();
+        assert_eq!(stat(base).st_nlink, 3);
+    }
+
+    // a regular file doesn't change the link count of the dir
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/a/file",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    assert_eq!(stat("/tmp/a").st_nlink, 2);
+
+    // move a dir to another dir
+    mkdir("/tmp/b");
+    mkdir("/tmp/a/sub");
+    vfs_rename::<FakeFSC>("/tmp/a/sub", "/tmp/b/sub").unwrap();
+    assert_eq!(stat("/tmp/a").st_nlink, 2);
+    assert_eq!(stat("/tmp/b").st_nlink, 3);
+    assert_eq!(stat("/tmp").st_nlink, 4);
+    // ".." of the moved dir is the new parent
+    assert_eq!(stat("/tmp/b/sub/..").st_ino, stat("/tmp/b").st_ino);
+    assert!(vfs_getattr::<FakeFSC>("/tmp/a/sub", StatFlags::empty()).is_err());
+
+    // rename in the same dir doesn't change the link count
+    vfs_rename::<FakeFSC>("/tmp/b/sub", "/tmp/b/sub2").unwrap();
+    assert_eq!(stat("/tmp/b").st_nlink, 3);
+    assert_eq!(stat("/tmp/b/sub2/..").st_ino, stat("/tmp/b").st_ino);
+
+    // replace an empty dir, the parent loses a sub dir
+    mkdir("/tmp/b/empty");
+    assert_eq!(stat("/tmp/b").st_nlink, 4);
+    vfs_rename::<FakeFSC>("/tmp/b/sub2", "/tmp/b/empty").unwrap();
+    assert_eq!(stat("/tmp/b").st_nlink, 3);
+    // replace an empty dir in another dir
+    mkdir("/tmp/a/empty");
+    vfs_rename::<FakeFSC>("/tmp/b/empty", "/tmp/a/empty").unwrap();
+    assert_eq!(stat("/tmp/a").st_nlink, 3);
+    assert_eq!(stat("/tmp/b").st_nlink, 2);
+    assert_eq!(stat("/tmp/a/empty/..").st_ino, stat("/tmp/a").st_ino);
+    vfs_rmdir::<FakeFSC>("/tmp/a/empty").unwrap();
+    assert_eq!(stat("/tmp/a").st_nlink, 2);
+    assert_eq!(stat("/tmp").st_nlink, 4);
+
+    // replace a file with other hard links
+    vfs_link::<FakeFSC>("/tmp/a/file", "/tmp/b/link").unwrap();
+    assert_eq!(stat("/tmp/a/file").st_nlink, 2);
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/b/other",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    vfs_rename::<FakeFSC>("/tmp/b/other", "/tmp/b/link").unwrap();
+    assert_eq!(stat("/tmp/a/file").st_nlink, 1);
+    assert_eq!(stat("/tmp/b/link").st_nlink, 1);
+    println!("nlink ok");
+}
@@ -0,0 +1,98 @@
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_open_file, vfs_read_file, vfs_readdir, vfs_write_file, OpenFlags,
+};
+use rvfs::inode::Mode;
+use rvfs::link::vfs_symlink;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn open(name: &str, flags: OpenFlags) -> Result<(), &'static str> {
+    let file = vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644))?;
+    vfs_close_file::<FakeFSC>(file)
+}
+
+fn size(name: &str) -> u64 {
+    vfs_getattr::<FakeFSC>(name, StatFlags::empty())
+        .unwrap()
+        .st_size
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_write_file::<FakeFSC>(file.clone(), b"hello", 0).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    assert_eq!(size("/tmp/f1"), 5);
+
+    // O_TRUNC only truncates a file opened for writing
+    open("/tmp/f1", OpenFlags::O_RDONLY | OpenFlags::O_TRUNC).unwrap();
+    assert_eq!(size("/tmp/f1"), 5);
+    let file = vfs_open_file::<FakeFSC>("/tmp/f1", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
+    assert!(vfs_write_file::<FakeFSC>(file.clone(), b"x", 0).is_err());
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    open("/tmp/f1", OpenFlags::O_WRONLY | OpenFlags::O_TRUNC).unwrap();
+    assert_eq!(size("/tmp/f1"), 0);
+    let file = vfs_open_file::<FakeFSC>("/tmp/f1", OpenFlags::O_RDWR, Mode::empty()).unwrap();
+    let mut buf = [0u8; 5];
+    assert_eq!(vfs_read_file::<FakeFSC>(file.clone(), &mut buf, 0), Ok(0));
+    vfs_close_file::<FakeFSC>(file).unwrap();
+
+    // O_CREAT | O_EXCL fails if the name exists, even for a dangling symlink
+    let excl = OpenFlags::O_CREAT | OpenFlags::O_EXCL | OpenFlags::O_RDWR;
+    assert_eq!(open("/tmp/f1", excl), Err("File exists"));
+    vfs_symlink::<FakeFSC>("/tmp/target", "/tmp/dangling").unwrap();
+    assert_eq!(open("/tmp/dangling", excl), Err("File exists"));
+    assert!(vfs_getattr::<FakeFSC>("/tmp/target", StatFlags::empty()).is_err());
+    // O_CREAT without O_EXCL creates the target of the symlink
+    open("/tmp/dangling", OpenFlags::O_CREAT | OpenFlags::O_RDWR).unwrap();
+    assert_eq!(size("/tmp/target"), 0);
+    // O_NOFOLLOW doesn't open a symlink
+    assert_eq!(
+        open("/tmp/dangling", OpenFlags::O_RDONLY | OpenFlags::O_NOFOLLOW),
+        Err("Too many levels of symbolic links")
+    );
+
+    // directories
+    vfs_mkdir::<FakeFSC>("/tmp/dir", Mode::from_bits_truncate(0o755)).unwrap();
+    assert_eq!(open("/tmp/dir", OpenFlags::O_RDWR), Err("Is a directory"));
+    assert_eq!(open("/tmp/dir", OpenFlags::O_WRONLY), Err("Is a directory"));
+    assert_eq!(
+        open("/tmp/dir", OpenFlags::O_CREAT | OpenFlags::O_RDONLY),
+        Err("Is a directory")
+    );
+    assert_eq!(
+        open("/tmp/f1", OpenFlags::O_DIRECTORY | OpenFlags::O_RDONLY),
+        Err("Not a directory")
+    );
+    assert_eq!(
+        open("/tmp/f2", OpenFlags::O_CREAT | OpenFlags::O_DIRECTORY),
+        Err("Invalid argument")
+    );
+    let dir = vfs_open_file::<FakeFSC>(
+        "/tmp",
+        OpenFlags::O_DIRECTORY | OpenFlags::O_RDONLY,
+        Mode::empty(),
+    )
+    .unwrap();
+    assert!(vfs_readdir(dir.clone(), &mut [0; 0]).unwrap() > 0);
+    vfs_close_file::<FakeFSC>(dir).unwrap();
+
+    // the symlink loop is detected
+    vfs_symlink::<FakeFSC>("/tmp/loop2", "/tmp/loop1").unwrap();
+    vfs_symlink::<FakeFSC>("/tmp/loop1", "/tmp/loop2").unwrap();
+    assert!(open("/tmp/loop1", OpenFlags::O_CREAT | OpenFlags::O_RDWR).is_err());
+    println!("open ok");
+}
@@ -1,5 +1,6 @@
 use rvfs::dentry::{Dirent64Iterator, LookUpFlags};
-use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, FileMode, OpenFlags};
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_readdir, File, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::mount::{do_mount, MountFlags};
 use rvfs::path::{vfs_lookup_path, ParsePathType};
 use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
@@ -13,9 +14,9 @@ fn main() {
     env_logger::init();
     let mnt = mount_rootfs();
     init_process_info(mnt);
-    vfs_mkdir::<FakeFSC>("/fs", FileMode::FMODE_WRITE).unwrap();
-    vfs_mkdir::<FakeFSC>("/fs/tmpfs", FileMode::FMODE_WRITE).unwrap();
-    let file = vfs_open_file::<FakeFSC>("/fs/", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/fs", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/fs/tmpfs", Mode::from_bits_truncate(0o755)).unwrap();
+    let file = vfs_open_file::<FakeFSC>("/fs/", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
 
     readdir(file);
 
@@ -29,7 +30,7 @@ fn main() {
     let file = vfs_open_file::<FakeFSC>(
         "/fs/tmpfs/f1",
         OpenFlags::O_RDWR | OpenFlags::O_CREAT,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("file: {file:#?}");
@@ -42,7 +43,7 @@ fn main() {
     );
     println!("path: {path:#?}");
 
-    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDWR, FileMode::FMODE_WRITE).unwrap();
+    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
 
     readdir(root.clone());
 
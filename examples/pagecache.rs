@@ -0,0 +1,168 @@
+use rvfs::dentry::{vfs_truncate_by_file, DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{vfs_fsync, vfs_pread, vfs_pwrite, File, FileOps, OpenFlags, OpenMode};
+use rvfs::info::VfsError;
+use rvfs::inode::{Inode, Mode};
+use rvfs::page_cache::{
+    generic_file_fsync, generic_file_read, generic_file_write, AddressSpaceOps, PAGE_SIZE,
+};
+use rvfs::superblock::Device;
+use rvfs::{init_process_info, mount_rootfs};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+/// a disk in memory, counting the reads and writes
+#[derive(Debug)]
+struct MemDisk {
+    data: Mutex<Vec<u8>>,
+    reads: AtomicUsize,
+    writes: AtomicUsize,
+}
+
+impl Device for MemDisk {
+    fn read(&self, buf: &mut [u8], offset: usize) -> Result<usize, VfsError> {
+        self.reads.fetch_add(1, Ordering::SeqCst);
+        let data = self.data.lock().unwrap();
+        let len = buf.len().min(data.len().saturating_sub(offset));
+        buf[..len].copy_from_slice(&data[offset..offset + len]);
+        Ok(len)
+    }
+    fn write(&self, buf: &[u8], offset: usize) -> Result<usize, VfsError> {
+        self.writes.fetch_add(1, Ordering::SeqCst);
+        let mut data = self.data.lock().unwrap();
+        if data.len() < offset + buf.len() {
+            data.resize(offset + buf.len(), 0);
+        }
+        data[offset..offset + buf.len()].copy_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn size(&self) -> usize {
+        self.data.lock().unwrap().len()
+    }
+}
+
+fn disk_readpage(inode: Arc<Inode>, index: usize, buf: &mut [u8]) -> Result<(), &'static str> {
+    let dev = inode.blk_dev.as_ref().unwrap();
+    dev.read(buf, index * PAGE_SIZE).map_err(|_| "I/O error")?;
+    Ok(())
+}
+
+fn disk_writepage(inode: Arc<Inode>, index: usize, buf: &[u8]) -> Result<(), &'static str> {
+    let dev = inode.blk_dev.as_ref().unwrap();
+    dev.write(buf, index * PAGE_SIZE).map_err(|_| "I/O error")?;
+    Ok(())
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs.clone());
+
+    // a file of 3 pages on the disk
+    let disk = Arc::new(MemDisk {
+        data: Mutex::new(
+            (0..3 * PAGE_SIZE)
+                .map(|i| (i / PAGE_SIZE) as u8 + 1)
+                .collect(),
+        ),
+        reads: AtomicUsize::new(0),
+        writes: AtomicUsize::new(0),
+    });
+    let mut inode = Inode::empty();
+    inode.blk_dev = Some(disk.clone());
+    inode.mapping.a_ops = AddressSpaceOps {
+        readpage: disk_readpage,
+        writepage: disk_writepage,
+        ..AddressSpaceOps::empty()
+    };
+    inode.inode_ops.truncate = |_| Ok(());
+    let mut f_ops = FileOps::empty();
+    f_ops.read = generic_file_read;
+    f_ops.write = generic_file_write;
+    f_ops.fsync = generic_file_fsync;
+    inode.file_ops = f_ops.clone();
+    inode.access_inner().mode = Mode::S_FILE | Mode::from_bits_truncate(0o644);
+    inode.access_inner().file_size = 3 * PAGE_SIZE;
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode.clone(),
+        DirEntryOps::empty(),
+        Weak::new(),
+        "disk",
+    );
+    let file = Arc::new(File::new(
+        Arc::new(dentry),
+        rootfs,
+        OpenFlags::O_RDWR,
+        OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+        f_ops,
+    ));
+    let reads = || disk.reads.load(Ordering::SeqCst);
+    let writes = || disk.writes.load(Ordering::SeqCst);
+
+    // the page is read from the disk only once
+    let mut buf = [0u8; 8];
+    vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, 10).unwrap();
+    assert_eq!(buf, [1; 8]);
+    vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, 100).unwrap();
+    assert_eq!(reads(), 1);
+    // a read across two pages
+    vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, PAGE_SIZE as u64 - 4).unwrap();
+    assert_eq!(buf, [1, 1, 1, 1, 2, 2, 2, 2]);
+    assert_eq!(reads(), 2);
+    assert_eq!(inode.mapping.nr_pages(), 2);
+    // nothing is read beyond the end of file
+    assert_eq!(
+        vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, 3 * PAGE_SIZE as u64),
+        Ok(0)
+    );
+
+    // the writes stay in the cache until fsync
+    vfs_pwrite::<rvfs::FakeFSC>(file.clone(), b"hello", 1).unwrap();
+    vfs_pwrite::<rvfs::FakeFSC>(file.clone(), b"world", 2 * PAGE_SIZE as u64).unwrap();
+    assert_eq!(writes(), 0);
+    assert_eq!(inode.mapping.nr_dirty(), 2);
+    // the partial write of an uncached page reads it first
+    assert_eq!(reads(), 3);
+    vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, 0).unwrap();
+    assert_eq!(&buf, b"\x01hello\x01\x01");
+    assert_eq!(disk.data.lock().unwrap()[1], 1);
+    vfs_fsync(file.clone()).unwrap();
+    assert_eq!(inode.mapping.nr_dirty(), 0);
+    assert_eq!(writes(), 2);
+    assert_eq!(&disk.data.lock().unwrap()[1..6], b"hello");
+    assert_eq!(
+        &disk.data.lock().unwrap()[2 * PAGE_SIZE..2 * PAGE_SIZE + 5],
+        b"world"
+    );
+    // the clean pages are not written again
+    vfs_fsync(file.clone()).unwrap();
+    assert_eq!(writes(), 2);
+
+    // a write beyond the end of file doesn't read the disk
+    vfs_pwrite::<rvfs::FakeFSC>(file.clone(), b"tail", 4 * PAGE_SIZE as u64).unwrap();
+    assert_eq!(reads(), 3);
+    assert_eq!(inode.access_inner().file_size, 4 * PAGE_SIZE + 4);
+
+    // truncate drops the pages beyond the new size and the dirty data in them,
+    // the zeroed tail of the last page is dirty
+    vfs_truncate_by_file(file.clone(), PAGE_SIZE + 2).unwrap();
+    assert_eq!(inode.mapping.nr_pages(), 2);
+    assert_eq!(inode.mapping.nr_dirty(), 1);
+    // the data beyond the new size reads as zero after growing the file again
+    vfs_truncate_by_file(file.clone(), 2 * PAGE_SIZE).unwrap();
+    vfs_pread::<rvfs::FakeFSC>(file.clone(), &mut buf, PAGE_SIZE as u64).unwrap();
+    assert_eq!(buf, [2, 2, 0, 0, 0, 0, 0, 0]);
+    vfs_fsync(file.clone()).unwrap();
+    assert_eq!(writes(), 3);
+    assert_eq!(
+        &disk.data.lock().unwrap()[PAGE_SIZE..PAGE_SIZE + 4],
+        &[2, 2, 0, 0]
+    );
+    // the page dropped from the cache reads the zeroed data from the disk
+    inode.mapping.access_pages().clear();
+    vfs_pread::<rvfs::FakeFSC>(file, &mut buf, PAGE_SIZE as u64).unwrap();
+    assert_eq!(buf, [2, 2, 0, 0, 0, 0, 0, 0]);
+    println!("pagecache ok");
+}
@@ -0,0 +1,64 @@
+use rvfs::dentry::vfs_rmdir;
+use rvfs::file::{vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::link::{vfs_symlink, vfs_unlink};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::path::{vfs_d_path, vfs_getcwd, vfs_realpath};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    assert_eq!(vfs_getcwd::<FakeFSC>().unwrap(), "/");
+
+    vfs_mkdir::<FakeFSC>("/mnt", Mode::from_bits_truncate(0o755)).unwrap();
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    let tmpfs = do_mount::<FakeFSC>("", "/mnt", "tmpfs", MountFlags::MNT_NO_DEV, None).unwrap();
+    vfs_mkdir::<FakeFSC>("/mnt/d1", Mode::from_bits_truncate(0o755)).unwrap();
+    let file = vfs_open_file::<FakeFSC>(
+        "/mnt/d1/f1",
+        OpenFlags::O_RDWR | OpenFlags::O_CREAT,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    // the path crosses the mount point
+    let path = vfs_d_path::<FakeFSC>(file.f_mnt.clone(), file.f_dentry.clone()).unwrap();
+    println!("d_path: {path}");
+    assert_eq!(path, "/mnt/d1/f1");
+    let path = vfs_d_path::<FakeFSC>(tmpfs.clone(), tmpfs.root.clone()).unwrap();
+    assert_eq!(path, "/mnt");
+
+    // change the cwd to /mnt/d1
+    let d1 = vfs_open_file::<FakeFSC>("/mnt/d1", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
+    PROCESS_FS_CONTEXT.lock().cwd = d1.f_dentry.clone();
+    PROCESS_FS_CONTEXT.lock().cmnt = d1.f_mnt.clone();
+    let cwd = vfs_getcwd::<FakeFSC>().unwrap();
+    println!("cwd: {cwd}");
+    assert_eq!(cwd, "/mnt/d1");
+
+    // realpath resolves symlinks, "." and ".."
+    vfs_symlink::<FakeFSC>("/mnt/d1", "/link").unwrap();
+    vfs_symlink::<FakeFSC>("../d1/f1", "/mnt/d1/rel").unwrap();
+    let path = vfs_realpath::<FakeFSC>("/link/./f1").unwrap();
+    assert_eq!(path, "/mnt/d1/f1");
+    let path = vfs_realpath::<FakeFSC>("rel").unwrap();
+    assert_eq!(path, "/mnt/d1/f1");
+    let path = vfs_realpath::<FakeFSC>("../..").unwrap();
+    assert_eq!(path, "/");
+    assert!(vfs_realpath::<FakeFSC>("/link/f2").is_err());
+
+    // the file has been deleted
+    vfs_unlink::<FakeFSC>("/mnt/d1/f1").unwrap();
+    let path = vfs_d_path::<FakeFSC>(file.f_mnt.clone(), file.f_dentry.clone()).unwrap();
+    println!("d_path: {path}");
+    assert_eq!(path, "/mnt/d1/f1 (deleted)");
+
+    vfs_unlink::<FakeFSC>("/mnt/d1/rel").unwrap();
+    vfs_rmdir::<FakeFSC>("/mnt/d1").unwrap();
+    assert!(vfs_getcwd::<FakeFSC>().is_err());
+    let path = vfs_d_path::<FakeFSC>(d1.f_mnt.clone(), d1.f_dentry.clone()).unwrap();
+    assert_eq!(path, "/mnt/d1 (deleted)");
+}
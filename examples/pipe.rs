@@ -0,0 +1,334 @@
+use rvfs::dentry::{DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{
+    vfs_close_file, vfs_llseek, vfs_mkdir, vfs_open_file, vfs_pread, vfs_preadv2, vfs_pwrite,
+    vfs_pwritev2, vfs_read, vfs_write, File, FileOps, OpenFlags, OpenMode, RwfFlags, SeekFrom,
+};
+use rvfs::info::{ProcessCred, ProcessFs, ProcessFsInfo, VfsTime, WaitQueue};
+use rvfs::inode::{Inode, Mode};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::pipe::{
+    vfs_pipe2, vfs_pipe_get_size, vfs_pipe_set_size, vfs_splice, vfs_tee, SpliceFlags, PIPE_BUF,
+};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, StrResult, PROCESS_FS_CONTEXT};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, Weak};
+use std::thread;
+
+const EAGAIN: &str = "Resource temporarily unavailable";
+
+static SIGPIPE: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+/// the pipe spliced to and from the file
+static SPLICE_PIPE: Mutex<Option<Arc<File>>> = Mutex::new(None);
+
+/// the wait queue of the kernel
+#[derive(Default)]
+struct CondvarQueue {
+    lock: Mutex<()>,
+    cond: Condvar,
+}
+
+impl WaitQueue for CondvarQueue {
+    fn wait_event(&self, cond: &mut dyn FnMut() -> bool) -> StrResult<()> {
+        let mut guard = self.lock.lock().unwrap();
+        while !cond() {
+            if INTERRUPT.load(Ordering::SeqCst) {
+                return Err("Interrupted system call");
+            }
+            guard = self.cond.wait(guard).unwrap();
+        }
+        Ok(())
+    }
+    fn wake_up(&self) {
+        let _guard = self.lock.lock().unwrap();
+        self.cond.notify_all();
+    }
+}
+
+/// the process sleeping on the condvar
+struct Task;
+
+impl ProcessFs for Task {
+    fn get_fs_info() -> ProcessFsInfo {
+        FakeFSC::get_fs_info()
+    }
+    fn check_nested_link() -> bool {
+        FakeFSC::check_nested_link()
+    }
+    fn update_link_data() {
+        FakeFSC::update_link_data()
+    }
+    fn max_link_count() -> u32 {
+        FakeFSC::max_link_count()
+    }
+    fn current_time() -> VfsTime {
+        FakeFSC::current_time()
+    }
+    fn update_umask(umask: Mode) {
+        FakeFSC::update_umask(umask)
+    }
+    fn get_cred() -> ProcessCred {
+        FakeFSC::get_cred()
+    }
+    fn new_wait_queue() -> Box<dyn WaitQueue> {
+        Box::<CondvarQueue>::default()
+    }
+    fn send_sigpipe() {
+        SIGPIPE.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+fn read(file: &Arc<File>, len: usize) -> StrResult<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let len = vfs_read::<Task>(file.clone(), &mut buf)?;
+    buf.truncate(len);
+    Ok(buf)
+}
+
+fn write(file: &Arc<File>, buf: &[u8]) -> StrResult<usize> {
+    vfs_write::<Task>(file.clone(), buf)
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<Task>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<Task>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+
+    // the data comes out in order
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    assert!(r.is_pipe());
+    assert_eq!(write(&w, b"hello "), Ok(6));
+    assert_eq!(write(&w, b"world"), Ok(5));
+    assert_eq!(read(&r, 100), Ok(b"hello world".to_vec()));
+    assert_eq!(
+        vfs_llseek(r.clone(), SeekFrom::Start(0)),
+        Err("Illegal seek")
+    );
+    // the positional I/O is refused, the stream isn't touched
+    let mut buf = [0u8; 1];
+    assert_eq!(vfs_pwrite::<Task>(w.clone(), b"x", 0), Err("Illegal seek"));
+    assert_eq!(
+        vfs_pwritev2::<Task>(w.clone(), &[b"x"], 0, RwfFlags::empty()),
+        Err("Illegal seek")
+    );
+    assert_eq!(
+        vfs_pwritev2::<Task>(w.clone(), &[b"y"], -1, RwfFlags::empty()),
+        Ok(1)
+    );
+    assert_eq!(
+        vfs_pread::<Task>(r.clone(), &mut buf, 0),
+        Err("Illegal seek")
+    );
+    assert_eq!(
+        vfs_preadv2::<Task>(r.clone(), &mut [&mut buf], 0, RwfFlags::empty()),
+        Err("Illegal seek")
+    );
+    assert_eq!(read(&r, 100), Ok(b"y".to_vec()));
+    assert_eq!(vfs_pipe_get_size(r.clone()), Ok(16 * 4096));
+    assert_eq!(
+        vfs_pipe2::<Task>(OpenFlags::O_APPEND).unwrap_err(),
+        "Invalid argument"
+    );
+
+    // the reader waits for the writer
+    let reader = {
+        let r = r.clone();
+        thread::spawn(move || {
+            let mut data = Vec::new();
+            loop {
+                let buf = read(&r, 7000).unwrap();
+                if buf.is_empty() {
+                    break;
+                }
+                data.extend(buf);
+            }
+            data
+        })
+    };
+    let data = (0..200 * 1024).map(|i| i as u8).collect::<Vec<_>>();
+    // the writer waits for the reader
+    assert_eq!(write(&w, &data), Ok(data.len()));
+    // the reader sees EOF after the writer is closed
+    vfs_close_file::<Task>(w).unwrap();
+    assert_eq!(reader.join().unwrap(), data);
+
+    // the writer gets EPIPE and SIGPIPE without reader
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    vfs_close_file::<Task>(r).unwrap();
+    assert_eq!(write(&w, b"x"), Err("Broken pipe"));
+    assert_eq!(SIGPIPE.load(Ordering::SeqCst), 1);
+    assert!((w.access_inner().f_ops_ext.is_ready_exception)(w.clone()));
+
+    // the non-blocking pipe
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::O_NONBLOCK).unwrap();
+    assert!(!(r.access_inner().f_ops_ext.is_ready_read)(r.clone()));
+    assert_eq!(read(&r, 1), Err(EAGAIN));
+    assert_eq!(write(&w, &vec![1; 1 << 20]), Ok(16 * 4096));
+    assert_eq!(write(&w, b"x"), Err(EAGAIN));
+    assert!(!(w.access_inner().f_ops_ext.is_ready_write)(w.clone()));
+    // the small write is atomic
+    assert_eq!(read(&r, 100).unwrap().len(), 100);
+    assert_eq!(write(&w, &[2; 200]), Err(EAGAIN));
+    assert_eq!(read(&r, 3996).unwrap().len(), 3996);
+    assert_eq!(write(&w, &[2; PIPE_BUF + 1]), Ok(PIPE_BUF));
+    // the size can't be smaller than the data
+    assert_eq!(
+        vfs_pipe_set_size::<Task>(w.clone(), 4096),
+        Err("Device or resource busy")
+    );
+    assert_eq!(vfs_pipe_set_size::<Task>(w.clone(), 1 << 20), Ok(1 << 20));
+    assert_eq!(write(&w, &[2; 100]), Ok(100));
+    vfs_close_file::<Task>(w).unwrap();
+    assert_eq!(read(&r, 1 << 20).unwrap().len(), 16 * 4096 + 100);
+    assert_eq!(read(&r, 1), Ok(vec![]));
+    assert!((r.access_inner().f_ops_ext.is_hang_up)(r.clone()));
+
+    // the size of the pipe is a power of two pages
+    let (r, w) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    assert_eq!(vfs_pipe_set_size::<Task>(r.clone(), 0), Ok(4096));
+    assert_eq!(vfs_pipe_set_size::<Task>(r.clone(), 5000), Ok(8192));
+    assert_eq!(vfs_pipe_get_size(w.clone()), Ok(8192));
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::new(1000, 1000, 1000, 1000);
+    assert_eq!(
+        vfs_pipe_set_size::<Task>(r.clone(), 2 << 20),
+        Err("Operation not permitted")
+    );
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::default();
+    assert_eq!(vfs_pipe_set_size::<Task>(r.clone(), 2 << 20), Ok(2 << 20));
+    let file = vfs_open_file::<Task>(
+        "/tmp/f",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    assert_eq!(vfs_pipe_get_size(file.clone()), Err("Bad file descriptor"));
+
+    // a signal interrupts the sleep
+    INTERRUPT.store(true, Ordering::SeqCst);
+    assert_eq!(read(&r, 1), Err("Interrupted system call"));
+    INTERRUPT.store(false, Ordering::SeqCst);
+
+    // splice a file to the pipe
+    vfs_write::<Task>(file.clone(), b"0123456789").unwrap();
+    let flags = SpliceFlags::empty();
+    let mut off = 2;
+    assert_eq!(
+        vfs_splice::<Task>(file.clone(), Some(&mut off), w.clone(), None, 5, flags),
+        Ok(5)
+    );
+    assert_eq!(off, 7);
+    assert_eq!(read(&r, 100), Ok(b"23456".to_vec()));
+    // the offset of the file is used and advanced
+    vfs_llseek(file.clone(), SeekFrom::Start(8)).unwrap();
+    assert_eq!(
+        vfs_splice::<Task>(file.clone(), None, w.clone(), None, 100, flags),
+        Ok(2)
+    );
+    assert_eq!(vfs_llseek(file.clone(), SeekFrom::Current(0)), Ok(10));
+    // splice the pipe to a file
+    let mut off = 0;
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), None, file.clone(), Some(&mut off), 100, flags),
+        Ok(2)
+    );
+    let mut buf = [0; 10];
+    vfs_pread::<Task>(file.clone(), &mut buf, 0).unwrap();
+    assert_eq!(&buf, b"8923456789");
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), Some(&mut off), file.clone(), None, 1, flags),
+        Err("Illegal seek")
+    );
+    assert_eq!(
+        vfs_splice::<Task>(file.clone(), None, file.clone(), None, 1, flags),
+        Err("Invalid argument")
+    );
+
+    // the pipe isn't locked during the I/O of the file
+    let inode = Inode::empty();
+    inode.access_inner().mode = Mode::S_FILE | Mode::from_bits_truncate(0o644);
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode,
+        DirEntryOps::empty(),
+        Weak::new(),
+        "dev",
+    );
+    let mut f_ops = FileOps::empty();
+    f_ops.read = |_, buf, _| {
+        let pipe = SPLICE_PIPE.lock().unwrap().clone().unwrap();
+        assert!(vfs_pipe_get_size(pipe).is_ok());
+        buf.fill(b'x');
+        Ok(buf.len())
+    };
+    f_ops.write = |_, buf, _| {
+        let pipe = SPLICE_PIPE.lock().unwrap().clone().unwrap();
+        assert!(vfs_pipe_get_size(pipe).is_ok());
+        Ok(buf.len())
+    };
+    let dev = Arc::new(File::new(
+        Arc::new(dentry),
+        file.f_mnt.clone(),
+        OpenFlags::O_RDWR,
+        OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+        f_ops,
+    ));
+    *SPLICE_PIPE.lock().unwrap() = Some(w.clone());
+    assert_eq!(
+        vfs_splice::<Task>(dev.clone(), Some(&mut 0), w.clone(), None, 3, flags),
+        Ok(3)
+    );
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), None, dev.clone(), Some(&mut 0), 2, flags),
+        Ok(2)
+    );
+    assert_eq!(read(&r, 100), Ok(b"x".to_vec()));
+    *SPLICE_PIPE.lock().unwrap() = None;
+
+    // move the buffers between the pipes
+    let (r2, w2) = vfs_pipe2::<Task>(OpenFlags::empty()).unwrap();
+    write(&w, b"hello world").unwrap();
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), None, w2.clone(), None, 5, flags),
+        Ok(5)
+    );
+    assert_eq!(read(&r2, 100), Ok(b"hello".to_vec()));
+    // tee duplicates the data
+    assert_eq!(vfs_tee::<Task>(r.clone(), w2.clone(), 100, flags), Ok(6));
+    assert_eq!(read(&r2, 100), Ok(b" world".to_vec()));
+    // the shared page isn't changed by the writer
+    write(&w, b"!").unwrap();
+    assert_eq!(read(&r, 100), Ok(b" world!".to_vec()));
+    assert_eq!(
+        vfs_tee::<Task>(r.clone(), w.clone(), 1, flags),
+        Err("Invalid argument")
+    );
+    let nonblock = SpliceFlags::SPLICE_F_NONBLOCK;
+    assert_eq!(
+        vfs_tee::<Task>(r.clone(), w2.clone(), 1, nonblock),
+        Err(EAGAIN)
+    );
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), None, w2.clone(), None, 1, nonblock),
+        Err(EAGAIN)
+    );
+    // the end of the pipe
+    vfs_close_file::<Task>(w).unwrap();
+    assert_eq!(
+        vfs_splice::<Task>(r.clone(), None, w2.clone(), None, 1, flags),
+        Ok(0)
+    );
+    vfs_close_file::<Task>(r2).unwrap();
+    assert_eq!(
+        vfs_splice::<Task>(file.clone(), Some(&mut 0), w2.clone(), None, 1, flags),
+        Err("Broken pipe")
+    );
+    assert_eq!(SIGPIPE.load(Ordering::SeqCst), 2);
+    println!("pipe ok");
+}
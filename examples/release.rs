@@ -1,4 +1,5 @@
-use rvfs::file::{vfs_close_file, vfs_open_file, FileMode, OpenFlags};
+use rvfs::file::{vfs_close_file, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 
 fn main() {
@@ -8,7 +9,7 @@ fn main() {
     let file = vfs_open_file::<FakeFSC>(
         "/f1",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_RDWR,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("file: {file:#?}");
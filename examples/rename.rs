@@ -1,7 +1,8 @@
 use rvfs::dentry::{vfs_rename, Dirent64Iterator};
 use rvfs::file::{
-    vfs_mkdir, vfs_open_file, vfs_read_file, vfs_readdir, vfs_write_file, File, FileMode, OpenFlags,
+    vfs_mkdir, vfs_open_file, vfs_read_file, vfs_readdir, vfs_write_file, File, OpenFlags,
 };
+use rvfs::inode::Mode;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 use std::sync::Arc;
 
@@ -13,13 +14,13 @@ fn main() {
     let file1 = vfs_open_file::<FakeFSC>(
         "/file1",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_WRITE | FileMode::FMODE_READ,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     let file2 = vfs_open_file::<FakeFSC>(
         "/file2",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_WRITE | FileMode::FMODE_READ,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
     println!("file1: {file1:#?}");
@@ -29,7 +30,7 @@ fn main() {
 
     println!("--------------------rename /file1 to /file3----------------------");
     vfs_rename::<FakeFSC>("/file1", "/file3").unwrap();
-    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDONLY, FileMode::FMODE_READ).unwrap();
+    let root = vfs_open_file::<FakeFSC>("/", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     // println!("root: {:#?}", root);
 
     readdir(root.clone());
@@ -42,11 +43,11 @@ fn main() {
     vfs_read_file::<FakeFSC>(file2, &mut buf, 0).unwrap();
     println!("buf: {:?}", core::str::from_utf8(&buf)); //"world"
 
-    vfs_mkdir::<FakeFSC>("/tmp", FileMode::FMODE_WRITE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
     let _file3 = vfs_open_file::<FakeFSC>(
         "/tmp/file3",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_WRITE | FileMode::FMODE_READ,
+        Mode::from_bits_truncate(0o644),
     );
     // println!("file3: {:#?}", file3);
     println!("--------------------rename /tmp to /tmptmp----------------------");
@@ -54,8 +55,7 @@ fn main() {
 
     readdir(root);
     // println!("file3: {:#?}", file3);
-    let tmp =
-        vfs_open_file::<FakeFSC>("/tmptmp", OpenFlags::O_RDONLY, FileMode::FMODE_READ).unwrap();
+    let tmp = vfs_open_file::<FakeFSC>("/tmptmp", OpenFlags::O_RDONLY, Mode::empty()).unwrap();
     readdir(tmp);
 }
 
@@ -0,0 +1,114 @@
+use rvfs::dentry::{vfs_rename2, RenameFlag};
+use rvfs::file::{
+    vfs_close_file, vfs_mkdir, vfs_open_file, vfs_read_file, vfs_write_file, OpenFlags,
+};
+use rvfs::info::ProcessCred;
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, KStat, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
+
+fn stat(name: &str) -> KStat {
+    vfs_getattr::<FakeFSC>(name, StatFlags::empty()).unwrap()
+}
+
+fn create(name: &str, data: &[u8]) {
+    let file = vfs_open_file::<FakeFSC>(
+        name,
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_write_file::<FakeFSC>(file.clone(), data, 0).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+}
+
+fn read(name: &str) -> Vec<u8> {
+    let file = vfs_open_file::<FakeFSC>(name, OpenFlags::O_RDONLY, Mode::empty()).unwrap();
+    let mut buf = [0u8; 16];
+    let len = vfs_read_file::<FakeFSC>(file.clone(), &mut buf, 0).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    buf[..len].to_vec()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    create("/tmp/f1", b"one");
+    create("/tmp/f2", b"two");
+    vfs_mkdir::<FakeFSC>("/tmp/d1", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp/d2", Mode::from_bits_truncate(0o755)).unwrap();
+
+    // the invalid combinations
+    for flags in [
+        RenameFlag::RENAME_EXCHANGE | RenameFlag::RENAME_NOREPLACE,
+        RenameFlag::RENAME_EXCHANGE | RenameFlag::RENAME_WHITEOUT,
+    ] {
+        assert_eq!(
+            vfs_rename2::<FakeFSC>("/tmp/f1", "/tmp/f2", flags),
+            Err("Invalid argument")
+        );
+    }
+
+    // RENAME_NOREPLACE doesn't overwrite the target
+    assert_eq!(
+        vfs_rename2::<FakeFSC>("/tmp/f1", "/tmp/f2", RenameFlag::RENAME_NOREPLACE),
+        Err("File exists")
+    );
+    assert_eq!(read("/tmp/f2"), b"two");
+    vfs_rename2::<FakeFSC>("/tmp/f1", "/tmp/f3", RenameFlag::RENAME_NOREPLACE).unwrap();
+    assert_eq!(read("/tmp/f3"), b"one");
+    assert!(vfs_getattr::<FakeFSC>("/tmp/f1", StatFlags::empty()).is_err());
+
+    // RENAME_EXCHANGE swaps the two files
+    let (ino2, ino3) = (stat("/tmp/f2").st_ino, stat("/tmp/f3").st_ino);
+    vfs_rename2::<FakeFSC>("/tmp/f2", "/tmp/f3", RenameFlag::RENAME_EXCHANGE).unwrap();
+    assert_eq!(read("/tmp/f2"), b"one");
+    assert_eq!(read("/tmp/f3"), b"two");
+    assert_eq!(stat("/tmp/f2").st_ino, ino3);
+    assert_eq!(stat("/tmp/f3").st_ino, ino2);
+    // the target must exist
+    assert_eq!(
+        vfs_rename2::<FakeFSC>("/tmp/f2", "/tmp/f4", RenameFlag::RENAME_EXCHANGE),
+        Err("No such file or directory")
+    );
+
+    // exchange a file in /tmp with a dir in /tmp/d1
+    vfs_mkdir::<FakeFSC>("/tmp/d1/sub", Mode::from_bits_truncate(0o755)).unwrap();
+    assert_eq!(stat("/tmp").st_nlink, 4);
+    assert_eq!(stat("/tmp/d1").st_nlink, 3);
+    vfs_rename2::<FakeFSC>("/tmp/f2", "/tmp/d1/sub", RenameFlag::RENAME_EXCHANGE).unwrap();
+    assert!(Mode::from_bits_truncate(stat("/tmp/f2").st_mode).is_dir());
+    assert_eq!(read("/tmp/d1/sub"), b"one");
+    assert_eq!(stat("/tmp").st_nlink, 5);
+    assert_eq!(stat("/tmp/d1").st_nlink, 2);
+    assert_eq!(stat("/tmp/f2/..").st_ino, stat("/tmp").st_ino);
+
+    // RENAME_WHITEOUT needs privilege
+    create("/tmp/f5", b"five");
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::new(1000, 1000, 1000, 1000);
+    assert_eq!(
+        vfs_rename2::<FakeFSC>("/tmp/f5", "/tmp/f6", RenameFlag::RENAME_WHITEOUT),
+        Err("Operation not permitted")
+    );
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::default();
+
+    // RENAME_WHITEOUT leaves a character device with device number 0
+    vfs_rename2::<FakeFSC>("/tmp/f5", "/tmp/f6", RenameFlag::RENAME_WHITEOUT).unwrap();
+    assert_eq!(read("/tmp/f6"), b"five");
+    let whiteout = stat("/tmp/f5");
+    assert!(Mode::from_bits_truncate(whiteout.st_mode).is_chr());
+    assert_eq!(whiteout.st_rdev, 0);
+    assert_ne!(whiteout.st_ino, stat("/tmp/f6").st_ino);
+    // the moved dentry leaves the old dir in the cache
+    vfs_rename2::<FakeFSC>("/tmp/f6", "/tmp/d2/f6", RenameFlag::RENAME_WHITEOUT).unwrap();
+    assert_eq!(read("/tmp/d2/f6"), b"five");
+    assert!(Mode::from_bits_truncate(stat("/tmp/f6").st_mode).is_chr());
+    println!("rename2 ok");
+}
@@ -0,0 +1,95 @@
+use rvfs::dentry::{vfs_rename, vfs_rename2, RenameFlag};
+use rvfs::file::{vfs_close_file, vfs_mkdir, vfs_open_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn mkdir(name: &str) {
+    vfs_mkdir::<FakeFSC>(name, Mode::from_bits_truncate(0o755)).unwrap();
+}
+
+fn create(name: &str) {
+    let file = vfs_open_file::<FakeFSC>(
+        name,
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+}
+
+fn exists(name: &str) -> bool {
+    vfs_getattr::<FakeFSC>(name, StatFlags::empty()).is_ok()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    mkdir("/tmp");
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    mkdir("/tmp/a");
+    mkdir("/tmp/a/b");
+    mkdir("/tmp/a/b/c");
+    mkdir("/tmp/d");
+    create("/f1");
+
+    // a dir can't be moved into its own subtree
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/tmp/a", "/tmp/a/b/c/a"),
+        Err("Invalid argument")
+    );
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/tmp/a", "/tmp/a/x"),
+        Err("Invalid argument")
+    );
+    assert_eq!(
+        vfs_rename2::<FakeFSC>("/tmp/a/b/c", "/tmp/a", RenameFlag::RENAME_EXCHANGE),
+        Err("Invalid argument")
+    );
+    assert!(exists("/tmp/a/b/c"));
+
+    // the target dir must be empty
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/tmp/d", "/tmp/a"),
+        Err("Directory not empty")
+    );
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/tmp/a/b/c", "/tmp/a"),
+        Err("Directory not empty")
+    );
+
+    // rename can't cross the mount point
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/f1", "/tmp/f1"),
+        Err("Invalid cross-device link")
+    );
+    assert_eq!(
+        vfs_rename::<FakeFSC>("/tmp", "/tmp2"),
+        Err("Device or resource busy")
+    );
+
+    // the dentry is moved to the new parent in the cache
+    vfs_rename::<FakeFSC>("/tmp/a/b", "/tmp/d/b").unwrap();
+    assert!(!exists("/tmp/a/b"));
+    assert!(!exists("/tmp/a/b/c"));
+    assert!(exists("/tmp/d/b/c"));
+    vfs_rename::<FakeFSC>("/tmp/d/b/c", "/tmp/c").unwrap();
+    assert!(exists("/tmp/c"));
+    assert!(!exists("/tmp/d/b/c"));
+    // the replaced target leaves the cache
+    create("/tmp/d/f");
+    create("/tmp/g");
+    let ino = vfs_getattr::<FakeFSC>("/tmp/g", StatFlags::empty())
+        .unwrap()
+        .st_ino;
+    vfs_rename::<FakeFSC>("/tmp/g", "/tmp/d/f").unwrap();
+    let stat = vfs_getattr::<FakeFSC>("/tmp/d/f", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_ino, ino);
+    assert!(!exists("/tmp/g"));
+    println!("rename safety ok");
+}
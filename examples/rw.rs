@@ -0,0 +1,108 @@
+use rvfs::file::{
+    vfs_close_file, vfs_llseek, vfs_mkdir, vfs_open_file, vfs_pread, vfs_pwrite, vfs_read,
+    vfs_write, vfs_write_file, File, OpenFlags, SeekFrom,
+};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::Arc;
+use std::thread;
+
+fn open(name: &str, flags: OpenFlags) -> Arc<File> {
+    vfs_open_file::<FakeFSC>(name, flags, Mode::from_bits_truncate(0o644)).unwrap()
+}
+
+fn pos(file: &Arc<File>) -> u64 {
+    vfs_llseek(file.clone(), SeekFrom::Current(0)).unwrap()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+
+    // vfs_read and vfs_write use and advance the file offset
+    let file = open("/tmp/f1", OpenFlags::O_CREAT | OpenFlags::O_RDWR);
+    assert_eq!(vfs_write::<FakeFSC>(file.clone(), b"hello"), Ok(5));
+    assert_eq!(vfs_write::<FakeFSC>(file.clone(), b" world"), Ok(6));
+    assert_eq!(pos(&file), 11);
+    vfs_llseek(file.clone(), SeekFrom::Start(0)).unwrap();
+    let mut buf = [0u8; 5];
+    assert_eq!(vfs_read::<FakeFSC>(file.clone(), &mut buf), Ok(5));
+    assert_eq!(&buf, b"hello");
+    assert_eq!(vfs_read::<FakeFSC>(file.clone(), &mut buf), Ok(5));
+    assert_eq!(&buf, b" worl");
+    assert_eq!(pos(&file), 10);
+
+    // vfs_pread and vfs_pwrite don't touch the file offset
+    assert_eq!(vfs_pread::<FakeFSC>(file.clone(), &mut buf, 6), Ok(5));
+    assert_eq!(&buf, b"world");
+    assert_eq!(vfs_pwrite::<FakeFSC>(file.clone(), b"HELLO", 0), Ok(5));
+    assert_eq!(pos(&file), 10);
+    assert_eq!(vfs_read::<FakeFSC>(file.clone(), &mut buf), Ok(1));
+    assert_eq!(buf[0], b'd');
+    assert_eq!(vfs_read::<FakeFSC>(file.clone(), &mut buf), Ok(0));
+
+    // every open has its own offset
+    let other = open("/tmp/f1", OpenFlags::O_RDONLY);
+    assert_eq!(pos(&other), 0);
+    assert_eq!(vfs_read::<FakeFSC>(other.clone(), &mut buf), Ok(5));
+    assert_eq!(&buf, b"HELLO");
+    assert_eq!(pos(&file), 11);
+    // a read only open doesn't affect the other one
+    assert!(vfs_write::<FakeFSC>(other.clone(), b"x").is_err());
+    vfs_write::<FakeFSC>(file.clone(), b"!").unwrap();
+    vfs_close_file::<FakeFSC>(other).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+
+    // O_APPEND always writes to the end of file
+    let append = open("/tmp/f1", OpenFlags::O_WRONLY | OpenFlags::O_APPEND);
+    let file = open("/tmp/f1", OpenFlags::O_RDWR);
+    vfs_write::<FakeFSC>(file.clone(), b"0123456789abcdef").unwrap();
+    assert_eq!(vfs_write::<FakeFSC>(append.clone(), b"+"), Ok(1));
+    assert_eq!(pos(&append), 17);
+    vfs_llseek(append.clone(), SeekFrom::Start(0)).unwrap();
+    vfs_write::<FakeFSC>(append.clone(), b"+").unwrap();
+    vfs_pwrite::<FakeFSC>(append.clone(), b"+", 0).unwrap();
+    vfs_write_file::<FakeFSC>(append.clone(), b"+", 0).unwrap();
+    let mut data = [0u8; 32];
+    assert_eq!(vfs_pread::<FakeFSC>(file.clone(), &mut data, 0), Ok(20));
+    assert_eq!(&data[..20], b"0123456789abcdef++++");
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    vfs_close_file::<FakeFSC>(append).unwrap();
+
+    // the concurrent appenders don't overwrite each other
+    let (threads, count) = (4, 100);
+    let handles = (0..threads)
+        .map(|i| {
+            thread::spawn(move || {
+                let file = open(
+                    "/tmp/log",
+                    OpenFlags::O_CREAT | OpenFlags::O_WRONLY | OpenFlags::O_APPEND,
+                );
+                for _ in 0..count {
+                    vfs_write::<FakeFSC>(file.clone(), &[b'a' + i as u8; 8]).unwrap();
+                }
+                vfs_close_file::<FakeFSC>(file).unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    let size = vfs_getattr::<FakeFSC>("/tmp/log", StatFlags::empty())
+        .unwrap()
+        .st_size;
+    assert_eq!(size, (threads * count * 8) as u64);
+    let file = open("/tmp/log", OpenFlags::O_RDONLY);
+    let mut record = [0u8; 8];
+    while vfs_read::<FakeFSC>(file.clone(), &mut record).unwrap() > 0 {
+        assert!(record.iter().all(|&c| c == record[0]));
+    }
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    println!("rw ok");
+}
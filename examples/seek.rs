@@ -1,6 +1,5 @@
-use rvfs::file::{
-    vfs_llseek, vfs_open_file, vfs_read_file, vfs_write_file, FileMode, OpenFlags, SeekFrom,
-};
+use rvfs::file::{vfs_llseek, vfs_open_file, vfs_read_file, vfs_write_file, OpenFlags, SeekFrom};
+use rvfs::inode::Mode;
 use rvfs::{init_process_info, mount_rootfs, FakeFSC};
 
 fn main() {
@@ -12,7 +11,7 @@ fn main() {
     let file1 = vfs_open_file::<FakeFSC>(
         "/file1",
         OpenFlags::O_CREAT | OpenFlags::O_RDWR,
-        FileMode::FMODE_WRITE,
+        Mode::from_bits_truncate(0o644),
     )
     .unwrap();
 
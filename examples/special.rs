@@ -0,0 +1,91 @@
+use rvfs::dentry::vfs_rename;
+use rvfs::file::{vfs_mkdir, vfs_open_file, vfs_write_file, OpenFlags};
+use rvfs::info::ProcessCred;
+use rvfs::inode::Mode;
+use rvfs::link::vfs_unlink;
+use rvfs::stat::{vfs_chmod, vfs_chown, vfs_getattr, vfs_getattr_by_file, StatFlags};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
+
+fn set_cred(uid: u32, gid: u32) {
+    PROCESS_FS_CONTEXT.lock().cred = ProcessCred::new(uid, gid, uid, gid);
+}
+
+fn stat_mode(name: &str) -> Mode {
+    let stat = vfs_getattr::<FakeFSC>(name, StatFlags::empty()).unwrap();
+    Mode::from_bits_truncate(stat.st_mode)
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+
+    // sticky directory
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::S_ISVTX | Mode::from_bits_truncate(0o777)).unwrap();
+    set_cred(1000, 1000);
+    vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    set_cred(1001, 1001);
+    assert!(vfs_unlink::<FakeFSC>("/tmp/f1").is_err());
+    assert!(vfs_rename::<FakeFSC>("/tmp/f1", "/tmp/f2").is_err());
+    set_cred(1000, 1000);
+    vfs_rename::<FakeFSC>("/tmp/f1", "/tmp/f2").unwrap();
+    vfs_unlink::<FakeFSC>("/tmp/f2").unwrap();
+    println!("sticky directory ok");
+
+    // setgid directory
+    set_cred(0, 0);
+    vfs_mkdir::<FakeFSC>("/share", Mode::from_bits_truncate(0o777)).unwrap();
+    vfs_chown::<FakeFSC>("/share", None, Some(100)).unwrap();
+    vfs_chmod::<FakeFSC>("/share", Mode::S_ISGID | Mode::from_bits_truncate(0o777)).unwrap();
+    set_cred(1000, 1000);
+    vfs_mkdir::<FakeFSC>("/share/d1", Mode::from_bits_truncate(0o755)).unwrap();
+    let file = vfs_open_file::<FakeFSC>(
+        "/share/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    let stat = vfs_getattr_by_file(file).unwrap();
+    assert_eq!(stat.st_uid, 1000);
+    assert_eq!(stat.st_gid, 100);
+    assert!(!Mode::from_bits_truncate(stat.st_mode).contains(Mode::S_ISGID));
+    let stat = vfs_getattr::<FakeFSC>("/share/d1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_gid, 100);
+    assert!(Mode::from_bits_truncate(stat.st_mode).contains(Mode::S_ISGID));
+    println!("setgid directory ok");
+
+    // writing to a setuid file clears the special bits
+    let file = vfs_open_file::<FakeFSC>(
+        "/share/suid",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::S_ISUID | Mode::S_ISGID | Mode::from_bits_truncate(0o755),
+    )
+    .unwrap();
+    assert!(stat_mode("/share/suid").contains(Mode::S_ISUID));
+    vfs_write_file::<FakeFSC>(file, b"hello", 0).unwrap();
+    let mode = stat_mode("/share/suid");
+    assert!(!mode.contains(Mode::S_ISUID) && !mode.contains(Mode::S_ISGID));
+    assert_eq!(mode.permission().bits(), 0o755);
+
+    // chown clears the special bits too
+    set_cred(0, 0);
+    vfs_chmod::<FakeFSC>(
+        "/share/suid",
+        Mode::S_ISUID | Mode::from_bits_truncate(0o755),
+    )
+    .unwrap();
+    // the privileged user keeps the bits when writing
+    let file = vfs_open_file::<FakeFSC>("/share/suid", OpenFlags::O_RDWR, Mode::empty()).unwrap();
+    vfs_write_file::<FakeFSC>(file, b"world", 5).unwrap();
+    assert!(stat_mode("/share/suid").contains(Mode::S_ISUID));
+    vfs_chown::<FakeFSC>("/share/suid", Some(1001), None).unwrap();
+    assert!(!stat_mode("/share/suid").contains(Mode::S_ISUID));
+    set_cred(1000, 1000);
+    assert!(vfs_chown::<FakeFSC>("/share/suid", Some(1000), None).is_err());
+    println!("setuid stripping ok");
+}
@@ -0,0 +1,51 @@
+use rvfs::dentry::{path_walk, LookUpFlags};
+use rvfs::file::{vfs_close_file, vfs_mkdir, vfs_open_file, vfs_write_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::link::vfs_symlink;
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC, PROCESS_FS_CONTEXT};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+
+    vfs_mkdir::<FakeFSC>("/d1", Mode::from_bits_truncate(0o755)).unwrap();
+    let file = vfs_open_file::<FakeFSC>(
+        "/d1/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_write_file::<FakeFSC>(file.clone(), b"hello world", 0).unwrap();
+    vfs_close_file::<FakeFSC>(file).unwrap();
+    vfs_symlink::<FakeFSC>("/d1/f1", "/s1").unwrap();
+
+    // directories can be stated
+    let stat = vfs_getattr::<FakeFSC>("/d1", StatFlags::empty()).unwrap();
+    assert!(Mode::from_bits_truncate(stat.st_mode).is_dir());
+    let stat = vfs_getattr::<FakeFSC>("/d1/", StatFlags::empty()).unwrap();
+    assert!(Mode::from_bits_truncate(stat.st_mode).is_dir());
+
+    // stat follows the symlink, lstat doesn't
+    let stat = vfs_getattr::<FakeFSC>("/s1", StatFlags::empty()).unwrap();
+    assert!(Mode::from_bits_truncate(stat.st_mode).is_file());
+    assert_eq!(stat.st_size, 11);
+    let stat = vfs_getattr::<FakeFSC>("/s1", StatFlags::AT_SYMLINK_NOFOLLOW).unwrap();
+    assert!(Mode::from_bits_truncate(stat.st_mode).is_symlink());
+    assert_eq!(stat.st_size, 6);
+
+    // the file isn't opened by stat
+    let lookup = path_walk::<FakeFSC>("/d1/f1", LookUpFlags::empty()).unwrap();
+    assert!(lookup.mnt.super_block.find_file(&lookup.dentry).is_none());
+
+    // empty path refers to the current directory only with AT_EMPTY_PATH
+    assert!(vfs_getattr::<FakeFSC>("", StatFlags::empty()).is_err());
+    let d1 = path_walk::<FakeFSC>("/d1", LookUpFlags::empty()).unwrap();
+    PROCESS_FS_CONTEXT.lock().cwd = d1.dentry.clone();
+    let stat = vfs_getattr::<FakeFSC>("", StatFlags::AT_EMPTY_PATH).unwrap();
+    assert_eq!(stat.st_ino, d1.dentry.access_inner().d_inode.number as u64);
+    let stat = vfs_getattr::<FakeFSC>("f1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_size, 11);
+    println!("stat ok");
+}
@@ -0,0 +1,113 @@
+use rvfs::dentry::vfs_truncate;
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{vfs_fallocate, vfs_mkdir, vfs_mknod, vfs_open_file, vfs_write_file, OpenFlags};
+use rvfs::inode::{FallocFlags, Mode};
+use rvfs::link::vfs_unlink;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, vfs_statfs, vfs_statfs_by_file, StatFlags};
+use rvfs::superblock::{makedev, register_filesystem, StatFsFlags, NAME_MAX};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    register_filesystem(DEVFS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    do_mount::<FakeFSC>(
+        "dev",
+        "/dev",
+        "devfs",
+        MountFlags::MNT_NOSUID | MountFlags::MNT_NO_EXEC,
+        None,
+    )
+    .unwrap();
+
+    // the common fields
+    let root = vfs_statfs::<FakeFSC>("/").unwrap();
+    println!("rootfs: {root:?}");
+    assert_eq!(root.f_namelen, NAME_MAX);
+    assert_eq!(root.f_frsize, root.f_bsize);
+    // rootfs is mounted with MNT_NO_DEV
+    assert_eq!(root.f_flags, StatFsFlags::ST_VALID | StatFsFlags::ST_NODEV);
+    let dev = vfs_getattr::<FakeFSC>("/", StatFlags::empty())
+        .unwrap()
+        .st_dev;
+    assert_eq!(root.f_fsid, [dev as u32, 0]);
+
+    // tmpfs has no capacity limit, the block and inode counts are the usage
+    let tmp = vfs_statfs::<FakeFSC>("/tmp").unwrap();
+    assert_ne!(tmp.f_fsid, root.f_fsid);
+    assert_eq!(tmp.f_blocks, 0);
+    assert_eq!((tmp.f_bfree, tmp.f_bavail, tmp.f_ffree), (0, 0, 0));
+    assert_eq!(tmp.f_files, 1);
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    let buf = [1u8; 5000];
+    vfs_write_file::<FakeFSC>(file.clone(), &buf, 0).unwrap();
+    let stat = vfs_statfs_by_file(file.clone()).unwrap();
+    assert_eq!(stat.f_blocks, 2);
+    assert_eq!(stat.f_files, 2);
+    // the root file system is not changed
+    let stat = vfs_statfs::<FakeFSC>("/").unwrap();
+    assert_eq!(stat.f_blocks, root.f_blocks);
+    assert_eq!(stat.f_files, root.f_files);
+
+    // the file grows with a hole, no block is allocated
+    vfs_truncate::<FakeFSC>("/tmp/f1", 1 << 30).unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/tmp").unwrap();
+    assert_eq!(stat.f_blocks, 2);
+    vfs_fallocate(file.clone(), FallocFlags::empty(), 0, 5 * 4096).unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/tmp").unwrap();
+    assert_eq!(stat.f_blocks, 5);
+    vfs_truncate::<FakeFSC>("/tmp/f1", 10).unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/tmp").unwrap();
+    assert_eq!(stat.f_blocks, 1);
+    vfs_unlink::<FakeFSC>("/tmp/f1").unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/tmp").unwrap();
+    assert_eq!((stat.f_blocks, stat.f_files), (0, 1));
+
+    // a file may be larger than 64MB
+    let big = vfs_open_file::<FakeFSC>(
+        "/big",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    let chunk = vec![2u8; 1 << 20];
+    for i in 0..65 {
+        let len = vfs_write_file::<FakeFSC>(big.clone(), &chunk, (i << 20) as u64).unwrap();
+        assert_eq!(len, chunk.len());
+    }
+    let stat = vfs_statfs::<FakeFSC>("/").unwrap();
+    assert_eq!(stat.f_blocks, root.f_blocks + 65 * 256);
+    vfs_unlink::<FakeFSC>("/big").unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/").unwrap();
+    assert_eq!(stat.f_blocks, root.f_blocks);
+
+    // devfs counts its nodes and reports the mount flags
+    let dev = vfs_statfs::<FakeFSC>("/dev").unwrap();
+    assert_eq!(dev.f_files, 1);
+    assert_eq!(dev.f_blocks, 0);
+    assert_eq!(
+        dev.f_flags,
+        StatFsFlags::ST_VALID | StatFsFlags::ST_NOSUID | StatFsFlags::ST_NOEXEC
+    );
+    vfs_mknod::<FakeFSC>(
+        "/dev/sda1",
+        Mode::S_BLKDEV | Mode::from_bits_truncate(0o660),
+        makedev(8, 1),
+    )
+    .unwrap();
+    let stat = vfs_statfs::<FakeFSC>("/dev/sda1").unwrap();
+    assert_eq!(stat.f_files, 2);
+    println!("statfs ok");
+}
@@ -0,0 +1,88 @@
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{vfs_mkdir, vfs_mknod, vfs_open_file, vfs_write_file, OpenFlags};
+use rvfs::inode::Mode;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_getattr, vfs_statx, StatFlags, StatxAttributes, StatxMask};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs.clone());
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    register_filesystem(DEVFS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
+    let tmp = do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let dev = do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::empty(), None).unwrap();
+
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    vfs_write_file::<FakeFSC>(file, b"hello", 0).unwrap();
+    let stx =
+        vfs_statx::<FakeFSC>("/tmp/f1", StatFlags::empty(), StatxMask::STATX_BASIC_STATS).unwrap();
+    println!("{stx:#?}");
+    assert!(stx
+        .stx_mask
+        .contains(StatxMask::STATX_BASIC_STATS | StatxMask::STATX_MNT_ID));
+    // tmpfs doesn't know the birth time
+    assert!(!stx.stx_mask.contains(StatxMask::STATX_BTIME));
+    assert_eq!(stx.stx_size, 5);
+    assert_eq!(
+        stx.stx_mode as u32,
+        (Mode::S_FILE | Mode::from_bits_truncate(0o644)).bits()
+    );
+    assert_eq!(stx.stx_mnt_id, tmp.mnt_id as u64);
+    assert!(stx.stx_attributes.is_empty());
+    assert!(stx
+        .stx_attributes_mask
+        .contains(StatxAttributes::STATX_ATTR_IMMUTABLE | StatxAttributes::STATX_ATTR_APPEND));
+    let stx =
+        vfs_statx::<FakeFSC>("/tmp", StatFlags::empty(), StatxMask::STATX_BASIC_STATS).unwrap();
+    assert_eq!(stx.stx_mnt_id, tmp.mnt_id as u64);
+    let stx = vfs_statx::<FakeFSC>("/", StatFlags::empty(), StatxMask::STATX_BASIC_STATS).unwrap();
+    assert_eq!(stx.stx_mnt_id, rootfs.mnt_id as u64);
+
+    // devfs fills the birth time and the device number
+    vfs_mknod::<FakeFSC>(
+        "/dev/tty1",
+        Mode::S_CHARDEV | Mode::from_bits_truncate(0o620),
+        (4 << 8) | 1,
+    )
+    .unwrap();
+    let stx =
+        vfs_statx::<FakeFSC>("/dev/tty1", StatFlags::empty(), StatxMask::STATX_BTIME).unwrap();
+    println!("{stx:#?}");
+    assert!(stx.stx_mask.contains(StatxMask::STATX_BTIME));
+    assert_eq!(stx.stx_mnt_id, dev.mnt_id as u64);
+    assert_eq!((stx.stx_rdev_major, stx.stx_rdev_minor), (4, 1));
+    let stat = vfs_getattr::<FakeFSC>("/dev/tty1", StatFlags::empty()).unwrap();
+    assert_eq!(stat.st_rdev, (4 << 8) | 1);
+
+    // the sync type flags can't be used together
+    vfs_statx::<FakeFSC>(
+        "/tmp/f1",
+        StatFlags::AT_STATX_DONT_SYNC,
+        StatxMask::STATX_SIZE,
+    )
+    .unwrap();
+    vfs_statx::<FakeFSC>(
+        "/tmp/f1",
+        StatFlags::AT_STATX_FORCE_SYNC,
+        StatxMask::STATX_SIZE,
+    )
+    .unwrap();
+    assert!(vfs_statx::<FakeFSC>(
+        "/tmp/f1",
+        StatFlags::AT_STATX_FORCE_SYNC | StatFlags::AT_STATX_DONT_SYNC,
+        StatxMask::STATX_SIZE
+    )
+    .is_err());
+    println!("statx ok");
+}
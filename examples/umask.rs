@@ -0,0 +1,60 @@
+use rvfs::devfs::DEVFS_TYPE;
+use rvfs::file::{
+    apply_umask, vfs_get_umask, vfs_mkdir, vfs_mknod, vfs_open_file, vfs_umask, OpenFlags,
+};
+use rvfs::inode::Mode;
+use rvfs::link::vfs_symlink;
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::stat::{vfs_getattr, StatFlags};
+use rvfs::superblock::register_filesystem;
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+
+fn mode(name: &str) -> u32 {
+    vfs_getattr::<FakeFSC>(name, StatFlags::empty())
+        .unwrap()
+        .st_mode
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs);
+    register_filesystem(DEVFS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/dev", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("dev", "/dev", "devfs", MountFlags::MNT_NO_DEV, None).unwrap();
+
+    // the default umask is 0o022
+    assert_eq!(vfs_get_umask::<FakeFSC>().bits(), 0o022);
+    let old = vfs_umask::<FakeFSC>(Mode::from_bits_truncate(0o027));
+    assert_eq!(old.bits(), 0o022);
+    assert_eq!(vfs_get_umask::<FakeFSC>().bits(), 0o027);
+
+    let m = apply_umask::<FakeFSC>(Mode::S_SYMLINK, Mode::from_bits_truncate(0o777));
+    assert_eq!(m.bits(), 0o120750);
+
+    // create/mkdir/mknod mask the requested permission bits
+    vfs_open_file::<FakeFSC>(
+        "/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o666),
+    )
+    .unwrap();
+    assert_eq!(mode("/f1"), 0o100640);
+    vfs_mkdir::<FakeFSC>("/d1", Mode::from_bits_truncate(0o777)).unwrap();
+    assert_eq!(mode("/d1"), 0o040750);
+    vfs_mknod::<FakeFSC>(
+        "/dev/tty",
+        Mode::S_CHARDEV | Mode::from_bits_truncate(0o666),
+        9,
+    )
+    .unwrap();
+    assert_eq!(mode("/dev/tty") & 0o777, 0o640);
+    vfs_symlink::<FakeFSC>("/f1", "/s1").unwrap();
+
+    // the file type bits of the mask are ignored
+    vfs_umask::<FakeFSC>(Mode::S_DIR | Mode::from_bits_truncate(0o077));
+    assert_eq!(vfs_get_umask::<FakeFSC>().bits(), 0o077);
+    vfs_mkdir::<FakeFSC>("/d2", Mode::from_bits_truncate(0o755)).unwrap();
+    assert_eq!(mode("/d2"), 0o040700);
+    println!("umask ok");
+}
@@ -0,0 +1,195 @@
+use rvfs::dentry::{vfs_rename, DirEntry, DirEntryOps, DirFlags};
+use rvfs::file::{
+    vfs_fdatasync, vfs_fsync, vfs_mkdir, vfs_open_file, vfs_pwrite, vfs_write, File, FileOps,
+    OpenFlags, OpenMode,
+};
+use rvfs::info::VfsTime;
+use rvfs::inode::{DirtyFlags, Inode, InodeOps, Mode};
+use rvfs::link::{vfs_link, vfs_unlink};
+use rvfs::mount::{do_mount, MountFlags};
+use rvfs::page_cache::{
+    generic_file_fsync, generic_file_read, generic_file_write, AddressSpaceOps,
+};
+use rvfs::ramfs::tmpfs::TMP_FS_TYPE;
+use rvfs::stat::{vfs_chmod, vfs_set_time};
+use rvfs::superblock::{register_filesystem, SuperBlock, SuperBlockOps};
+use rvfs::writeback::{mark_inode_dirty, vfs_sync, vfs_syncfs, vfs_writeback_periodic};
+use rvfs::{init_process_info, mount_rootfs, FakeFSC};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+static WRITE_PAGE: AtomicUsize = AtomicUsize::new(0);
+static WRITE_INODE: AtomicUsize = AtomicUsize::new(0);
+static WRITE_SUPER: AtomicUsize = AtomicUsize::new(0);
+static SYNC_FS: AtomicUsize = AtomicUsize::new(0);
+static FAIL: AtomicBool = AtomicBool::new(false);
+
+fn count(counter: &AtomicUsize) -> usize {
+    counter.swap(0, Ordering::SeqCst)
+}
+
+fn dirty_inodes(sb: &SuperBlock) -> usize {
+    sb.access_inner().dirty_inode.len()
+}
+
+fn main() {
+    env_logger::init();
+    let rootfs = mount_rootfs();
+    init_process_info(rootfs.clone());
+
+    // a filesystem counting the writeback
+    let sb = Arc::new(SuperBlock {
+        super_block_ops: SuperBlockOps {
+            write_inode: |_, _| {
+                if FAIL.load(Ordering::SeqCst) {
+                    return Err("I/O error");
+                }
+                WRITE_INODE.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            write_super: |_| {
+                WRITE_SUPER.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            sync_fs: |_| {
+                SYNC_FS.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            },
+            ..SuperBlockOps::empty()
+        },
+        ..SuperBlock::empty()
+    });
+    let mut f_ops = FileOps::empty();
+    f_ops.read = generic_file_read;
+    f_ops.write = generic_file_write;
+    f_ops.fsync = generic_file_fsync;
+    let mut inode = Inode::new(
+        sb.clone(),
+        1,
+        0,
+        InodeOps::empty(),
+        f_ops.clone(),
+        None,
+        Mode::S_FILE | Mode::from_bits_truncate(0o644),
+    );
+    inode.mapping.a_ops = AddressSpaceOps {
+        readpage: |_, _, _| Ok(()),
+        writepage: |_, _, _| {
+            WRITE_PAGE.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        },
+        ..AddressSpaceOps::empty()
+    };
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode.clone(),
+        DirEntryOps::empty(),
+        Weak::new(),
+        "file",
+    );
+    let file = Arc::new(File::new(
+        Arc::new(dentry),
+        rootfs,
+        OpenFlags::O_RDWR,
+        OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+        f_ops,
+    ));
+
+    // growing the file dirties the pages and the size
+    vfs_pwrite::<FakeFSC>(file.clone(), b"hello", 0).unwrap();
+    assert_eq!(
+        inode.access_inner().dirty,
+        DirtyFlags::I_DIRTY_PAGES | DirtyFlags::I_DIRTY_DATASYNC
+    );
+    assert_eq!(dirty_inodes(&sb), 1);
+    // fdatasync writes the size
+    vfs_fdatasync(file.clone()).unwrap();
+    assert_eq!((count(&WRITE_PAGE), count(&WRITE_INODE)), (1, 1));
+    assert!(inode.access_inner().dirty.is_empty());
+    assert_eq!(dirty_inodes(&sb), 0);
+    // overwriting the data doesn't change the metadata
+    vfs_pwrite::<FakeFSC>(file.clone(), b"HELLO", 0).unwrap();
+    assert_eq!(inode.access_inner().dirty, DirtyFlags::I_DIRTY_PAGES);
+    vfs_fdatasync(file.clone()).unwrap();
+    assert_eq!((count(&WRITE_PAGE), count(&WRITE_INODE)), (1, 0));
+
+    // fdatasync skips the metadata not needed to read the data, fsync doesn't
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
+    vfs_fdatasync(file.clone()).unwrap();
+    assert_eq!(count(&WRITE_INODE), 0);
+    assert_eq!(dirty_inodes(&sb), 1);
+    vfs_fsync(file.clone()).unwrap();
+    assert_eq!(count(&WRITE_INODE), 1);
+    assert_eq!(dirty_inodes(&sb), 0);
+
+    // syncfs writes the dirty inodes, the dirty super block and syncs the filesystem
+    vfs_pwrite::<FakeFSC>(file.clone(), b"world", 5).unwrap();
+    sb.mark_dirty();
+    vfs_syncfs(sb.clone()).unwrap();
+    assert_eq!(count(&WRITE_PAGE), 1);
+    assert_eq!(count(&WRITE_INODE), 1);
+    assert_eq!((count(&WRITE_SUPER), count(&SYNC_FS)), (1, 1));
+    assert_eq!(dirty_inodes(&sb), 0);
+    assert!(sb.access_inner().sync_inode.is_empty());
+    // the clean super block is not written again
+    vfs_syncfs(sb.clone()).unwrap();
+    assert_eq!((count(&WRITE_SUPER), count(&SYNC_FS)), (0, 1));
+
+    // the inode failed to be written stays dirty
+    FAIL.store(true, Ordering::SeqCst);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
+    assert_eq!(vfs_syncfs(sb.clone()), Err("I/O error"));
+    assert_eq!(dirty_inodes(&sb), 1);
+    assert!(sb.access_inner().sync_inode.is_empty());
+    assert_eq!(inode.access_inner().dirty, DirtyFlags::I_DIRTY_SYNC);
+    FAIL.store(false, Ordering::SeqCst);
+    vfs_syncfs(sb.clone()).unwrap();
+    assert_eq!(dirty_inodes(&sb), 0);
+
+    // the mounted filesystems
+    register_filesystem(TMP_FS_TYPE).unwrap();
+    vfs_mkdir::<FakeFSC>("/tmp", Mode::from_bits_truncate(0o755)).unwrap();
+    do_mount::<FakeFSC>("", "/tmp", "tmpfs", MountFlags::empty(), None).unwrap();
+    let file = vfs_open_file::<FakeFSC>(
+        "/tmp/f1",
+        OpenFlags::O_CREAT | OpenFlags::O_RDWR,
+        Mode::from_bits_truncate(0o644),
+    )
+    .unwrap();
+    let tmp_sb = file.f_mnt.super_block.clone();
+    vfs_write::<FakeFSC>(file.clone(), b"data").unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 1);
+    // the periodic writeback writes the inode dirty for a whole period
+    vfs_writeback_periodic().unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 1);
+    vfs_writeback_periodic().unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 0);
+    // sync writes all of them
+    vfs_chmod::<FakeFSC>("/tmp/f1", Mode::from_bits_truncate(0o600)).unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 1);
+    vfs_sync().unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 0);
+
+    // changing the times or the links dirties the inodes
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    vfs_set_time::<FakeFSC>("/tmp/f1", [VfsTime::default(); 3]).unwrap();
+    assert_eq!(inode.access_inner().dirty, DirtyFlags::I_DIRTY_SYNC);
+    assert_eq!(dirty_inodes(&tmp_sb), 1);
+    vfs_sync().unwrap();
+    // the file and the dir
+    vfs_link::<FakeFSC>("/tmp/f1", "/tmp/f2").unwrap();
+    assert_eq!(inode.access_inner().dirty, DirtyFlags::I_DIRTY_SYNC);
+    assert_eq!(dirty_inodes(&tmp_sb), 2);
+    vfs_sync().unwrap();
+    vfs_rename::<FakeFSC>("/tmp/f2", "/tmp/f3").unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 2);
+    vfs_sync().unwrap();
+    vfs_unlink::<FakeFSC>("/tmp/f3").unwrap();
+    assert_eq!(inode.access_inner().dirty, DirtyFlags::I_DIRTY_SYNC);
+    assert_eq!(dirty_inodes(&tmp_sb), 2);
+    vfs_sync().unwrap();
+    assert_eq!(dirty_inodes(&tmp_sb), 0);
+    println!("writeback ok");
+}
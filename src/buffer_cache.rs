@@ -0,0 +1,370 @@
+//! 块缓存
+//!
+//! The blocks of the [Device] are cached in [BufferHead]s, keyed by the device,
+//! the block number and the block size. A filesystem reads a block with
+//! [BufferCache::bread], modifies it and calls [BufferCache::mark_dirty], then
+//! releases it with [BufferCache::brelse].
+//!
+//! The dirty buffers are written back in the order they were first marked
+//! dirty, both by [BufferCache::sync_dirty_buffers] and when a dirty buffer is
+//! evicted, so a filesystem can rely on the earlier modifications reaching the
+//! device first.
+use crate::superblock::{Device, SuperBlock};
+use crate::{ddebug, StrResult};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Debug, Formatter};
+use hashbrown::HashMap;
+use lazy_static::lazy_static;
+use spin::{Mutex, MutexGuard};
+
+/// the default memory budget of [BUFFER_CACHE]
+pub const BUFFER_CACHE_BUDGET: usize = 4 * 1024 * 1024;
+
+lazy_static! {
+    pub static ref BUFFER_CACHE: BufferCache = BufferCache::new(BUFFER_CACHE_BUDGET);
+}
+
+/// (device, block number, block size)
+type BufferKey = (usize, usize, usize);
+
+pub struct BufferHead {
+    pub device: Arc<dyn Device>,
+    /// 块号
+    pub block: usize,
+    /// 块大小
+    pub size: usize,
+    inner: Mutex<BufferInner>,
+}
+
+pub struct BufferInner {
+    pub data: Vec<u8>,
+    /// the data is read from the device or fully written
+    pub uptodate: bool,
+    /// the sequence number when the buffer was marked dirty
+    dirty: Option<u64>,
+}
+
+impl Debug for BufferHead {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let inner = self.inner.lock();
+        f.debug_struct("BufferHead")
+            .field("block", &self.block)
+            .field("size", &self.size)
+            .field("uptodate", &inner.uptodate)
+            .field("dirty", &inner.dirty.is_some())
+            .finish()
+    }
+}
+
+impl BufferHead {
+    pub fn access_inner(&self) -> MutexGuard<'_, BufferInner> {
+        self.inner.lock()
+    }
+    pub fn is_dirty(&self) -> bool {
+        self.inner.lock().dirty.is_some()
+    }
+    pub fn is_uptodate(&self) -> bool {
+        self.inner.lock().uptodate
+    }
+    fn key(&self) -> BufferKey {
+        buffer_key(&self.device, self.block, self.size)
+    }
+    fn read_block(&self, inner: &mut BufferInner) -> StrResult<()> {
+        let len = self
+            .device
+            .read(&mut inner.data, self.block * self.size)
+            .map_err(|_| "I/O error")?;
+        if len != self.size {
+            return Err("I/O error");
+        }
+        inner.uptodate = true;
+        Ok(())
+    }
+    fn write_block(&self, inner: &mut BufferInner) -> StrResult<()> {
+        let len = self
+            .device
+            .write(&inner.data, self.block * self.size)
+            .map_err(|_| "I/O error")?;
+        if len != self.size {
+            return Err("I/O error");
+        }
+        inner.dirty = None;
+        Ok(())
+    }
+}
+
+fn buffer_key(device: &Arc<dyn Device>, block: usize, size: usize) -> BufferKey {
+    (Arc::as_ptr(device) as *const () as usize, block, size)
+}
+
+struct CacheEntry {
+    bh: Arc<BufferHead>,
+    /// the last access time, for LRU
+    tick: u64,
+}
+
+struct BufferCacheInner {
+    buffers: HashMap<BufferKey, CacheEntry>,
+    /// the dirty buffers in the order they were marked dirty
+    dirty: BTreeMap<u64, BufferKey>,
+    /// the bytes of the cached data
+    used: usize,
+    tick: u64,
+    seq: u64,
+}
+
+pub struct BufferCache {
+    /// the max bytes of the cached data
+    budget: usize,
+    inner: Mutex<BufferCacheInner>,
+}
+
+impl Debug for BufferCache {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let inner = self.inner.lock();
+        f.debug_struct("BufferCache")
+            .field("budget", &self.budget)
+            .field("used", &inner.used)
+            .field("buffers", &inner.buffers.len())
+            .field("dirty", &inner.dirty.len())
+            .finish()
+    }
+}
+
+impl BufferCache {
+    pub fn new(budget: usize) -> Self {
+        BufferCache {
+            budget,
+            inner: Mutex::new(BufferCacheInner {
+                buffers: HashMap::new(),
+                dirty: BTreeMap::new(),
+                used: 0,
+                tick: 0,
+                seq: 0,
+            }),
+        }
+    }
+
+    /// the bytes of the cached data
+    pub fn used(&self) -> usize {
+        self.inner.lock().used
+    }
+
+    /// the number of cached buffers
+    pub fn len(&self) -> usize {
+        self.inner.lock().buffers.len()
+    }
+
+    /// get the buffer of the block, the data is not read from the device
+    pub fn getblk(
+        &self,
+        device: &Arc<dyn Device>,
+        block: usize,
+        size: usize,
+    ) -> StrResult<Arc<BufferHead>> {
+        let end = block
+            .checked_add(1)
+            .and_then(|n| n.checked_mul(size))
+            .ok_or("Invalid argument")?;
+        if size == 0 || end > device.size() {
+            return Err("Invalid argument");
+        }
+        let key = buffer_key(device, block, size);
+        if let Some(bh) = Self::lookup(&mut self.inner.lock(), key) {
+            return Ok(bh);
+        }
+        self.shrink(size)?;
+        let mut inner = self.inner.lock();
+        // the buffer may be added by others when the lock is released
+        if let Some(bh) = Self::lookup(&mut inner, key) {
+            return Ok(bh);
+        }
+        let bh = Arc::new(BufferHead {
+            device: device.clone(),
+            block,
+            size,
+            inner: Mutex::new(BufferInner {
+                data: vec![0; size],
+                uptodate: false,
+                dirty: None,
+            }),
+        });
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.buffers.insert(
+            key,
+            CacheEntry {
+                bh: bh.clone(),
+                tick,
+            },
+        );
+        inner.used += size;
+        Ok(bh)
+    }
+
+    /// get the buffer of the block and read it from the device if it is not uptodate
+    pub fn bread(
+        &self,
+        device: &Arc<dyn Device>,
+        block: usize,
+        size: usize,
+    ) -> StrResult<Arc<BufferHead>> {
+        let bh = self.getblk(device, block, size)?;
+        {
+            let mut inner = bh.access_inner();
+            if !inner.uptodate {
+                bh.read_block(&mut inner)?;
+            }
+        }
+        Ok(bh)
+    }
+
+    /// mark the buffer dirty, it is written back by [BufferCache::sync_dirty_buffers]
+    /// or when it is evicted
+    pub fn mark_dirty(&self, bh: &Arc<BufferHead>) {
+        let mut inner = self.inner.lock();
+        let mut bh_inner = bh.access_inner();
+        bh_inner.uptodate = true;
+        if bh_inner.dirty.is_none() {
+            inner.seq += 1;
+            let seq = inner.seq;
+            bh_inner.dirty = Some(seq);
+            inner.dirty.insert(seq, bh.key());
+        }
+    }
+
+    /// release the buffer, it can be evicted when no one else holds it
+    pub fn brelse(&self, bh: Arc<BufferHead>) {
+        drop(bh);
+        // the buffers over budget are evicted as soon as possible
+        let _ = self.shrink(0);
+    }
+
+    /// write back the dirty buffers of the device, or all devices if it is None,
+    /// in the order they were marked dirty
+    pub fn sync_dirty_buffers(&self, device: Option<&Arc<dyn Device>>) -> StrResult<()> {
+        ddebug!("sync_dirty_buffers");
+        let dev = device.map(|d| Arc::as_ptr(d) as *const () as usize);
+        let dirty = {
+            let inner = self.inner.lock();
+            inner
+                .dirty
+                .iter()
+                .filter(|(_, key)| dev.is_none_or(|d| key.0 == d))
+                .map(|(seq, key)| (*seq, inner.buffers[key].bh.clone()))
+                .collect::<Vec<_>>()
+        };
+        self.write_dirty(dirty)?;
+        match device {
+            Some(device) => device.flush(),
+            None => self.devices().iter().for_each(|d| d.flush()),
+        }
+        ddebug!("sync_dirty_buffers end");
+        Ok(())
+    }
+
+    /// drop the clean buffers of the device that no one holds
+    pub fn invalidate_buffers(&self, device: &Arc<dyn Device>) {
+        let dev = Arc::as_ptr(device) as *const () as usize;
+        let mut inner = self.inner.lock();
+        let keys = inner
+            .buffers
+            .iter()
+            .filter(|(key, e)| key.0 == dev && Arc::strong_count(&e.bh) == 1 && !e.bh.is_dirty())
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+        for key in keys {
+            inner.buffers.remove(&key);
+            inner.used -= key.2;
+        }
+    }
+
+    fn devices(&self) -> Vec<Arc<dyn Device>> {
+        let inner = self.inner.lock();
+        let mut devices: Vec<Arc<dyn Device>> = Vec::new();
+        for entry in inner.buffers.values() {
+            if !devices.iter().any(|d| Arc::ptr_eq(d, &entry.bh.device)) {
+                devices.push(entry.bh.device.clone());
+            }
+        }
+        devices
+    }
+
+    /// find the cached buffer and update its access time
+    fn lookup(inner: &mut BufferCacheInner, key: BufferKey) -> Option<Arc<BufferHead>> {
+        inner.tick += 1;
+        let tick = inner.tick;
+        let entry = inner.buffers.get_mut(&key)?;
+        entry.tick = tick;
+        Some(entry.bh.clone())
+    }
+
+    /// write back the dirty buffers in order, the device is accessed without the lock
+    /// of the cache
+    fn write_dirty(&self, dirty: Vec<(u64, Arc<BufferHead>)>) -> StrResult<()> {
+        for (seq, bh) in dirty {
+            {
+                let mut bh_inner = bh.access_inner();
+                // it may be written back by others in the meantime
+                if bh_inner.dirty == Some(seq) {
+                    bh.write_block(&mut bh_inner)?;
+                }
+            }
+            self.inner.lock().dirty.remove(&seq);
+        }
+        Ok(())
+    }
+
+    /// evict the least recently used buffers that no one holds, until `extra` bytes fit in
+    /// the budget. A dirty buffer is written back with all buffers dirtied before it.
+    ///
+    /// If all buffers are in use, the cache grows beyond the budget.
+    fn shrink(&self, extra: usize) -> StrResult<()> {
+        loop {
+            let mut inner = self.inner.lock();
+            if inner.used + extra <= self.budget {
+                return Ok(());
+            }
+            let victim = inner
+                .buffers
+                .iter()
+                .filter(|(_, e)| Arc::strong_count(&e.bh) == 1)
+                .min_by_key(|(_, e)| e.tick)
+                .map(|(key, e)| (*key, e.bh.access_inner().dirty));
+            let Some((key, dirty)) = victim else {
+                return Ok(());
+            };
+            match dirty {
+                Some(seq) => {
+                    let dirty = inner
+                        .dirty
+                        .range(..=seq)
+                        .map(|(seq, key)| (*seq, inner.buffers[key].bh.clone()))
+                        .collect::<Vec<_>>();
+                    drop(inner);
+                    // the victim is clean and evicted in the next round
+                    self.write_dirty(dirty)?;
+                }
+                None => {
+                    inner.buffers.remove(&key);
+                    inner.used -= key.2;
+                }
+            }
+        }
+    }
+}
+
+/// read the block of the super block's device with the block size of the file system
+pub fn sb_bread(sb: &SuperBlock, block: usize) -> StrResult<Arc<BufferHead>> {
+    let device = sb.device.as_ref().ok_or("No such device")?;
+    BUFFER_CACHE.bread(device, block, sb.block_size as usize)
+}
+
+/// get the buffer of the block of the super block's device without reading it
+pub fn sb_getblk(sb: &SuperBlock, block: usize) -> StrResult<Arc<BufferHead>> {
+    let device = sb.device.as_ref().ok_or("No such device")?;
+    BUFFER_CACHE.getblk(device, block, sb.block_size as usize)
+}
@@ -1,4 +1,4 @@
-use crate::inode::{Inode, InodeMode};
+use crate::inode::{Inode, Mode};
 use crate::mount::VfsMount;
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
@@ -46,9 +46,9 @@ impl DirEntry {
     pub fn access_inner(&self) -> MutexGuard<DirEntryInner> {
         self.inner.lock()
     }
-    pub fn with_inode_mode(mode: InodeMode) -> Self {
-        let mut inode = Inode::empty();
-        inode.mode = mode;
+    pub fn with_inode_mode(mode: Mode) -> Self {
+        let inode = Inode::empty();
+        inode.access_inner().mode = mode;
         DirEntry {
             d_flags: DirFlags::empty(),
             d_ops: DirEntryOps::empty(),
@@ -230,17 +230,17 @@ bitflags! {
     }
 }
 
-impl From<InodeMode> for DirentType {
-    fn from(value: InodeMode) -> Self {
-        match value {
-            InodeMode::S_SYMLINK => DirentType::DT_LNK,
-            InodeMode::S_DIR => DirentType::DT_DIR,
-            InodeMode::S_FILE => DirentType::DT_REG,
-            InodeMode::S_CHARDEV => DirentType::DT_CHR,
-            InodeMode::S_BLKDEV => DirentType::DT_BLK,
-            InodeMode::S_FIFO => DirentType::DT_FIFO,
-            InodeMode::S_SOCK => DirentType::DT_SOCK,
-            // InodeMode:: => DirentType::DT_WHT,
+impl From<Mode> for DirentType {
+    fn from(value: Mode) -> Self {
+        match value.file_type() {
+            Mode::S_SYMLINK => DirentType::DT_LNK,
+            Mode::S_DIR => DirentType::DT_DIR,
+            Mode::S_FILE => DirentType::DT_REG,
+            Mode::S_CHARDEV => DirentType::DT_CHR,
+            Mode::S_BLKDEV => DirentType::DT_BLK,
+            Mode::S_FIFO => DirentType::DT_FIFO,
+            Mode::S_SOCK => DirentType::DT_SOCK,
+            // Mode:: => DirentType::DT_WHT,
             _ => DirentType::DT_UNKNOWN,
         }
     }
@@ -353,7 +353,11 @@ impl LookUpData {
 
 bitflags! {
     pub struct RenameFlag:u32{
-        const RENAME_EXCHANGE = 0x1;
-        const RENAME_NOREPLACE = 0x2;
+        /// don't overwrite the target
+        const RENAME_NOREPLACE = 0x1;
+        /// exchange the source and the target
+        const RENAME_EXCHANGE = 0x2;
+        /// leave a whiteout object at the source
+        const RENAME_WHITEOUT = 0x4;
     }
 }
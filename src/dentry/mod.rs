@@ -1,13 +1,19 @@
 mod define;
 use crate::file::File;
 use crate::info::ProcessFs;
-use crate::inode::{Inode, InodeFlags, InodeMode};
+use crate::inode::{DirtyFlags, Inode, InodeFlags, Mode};
 use crate::mount::{mnt_want_write, VfsMount};
+use crate::page_cache::truncate_inode_pages;
+use crate::writeback::mark_inode_dirty;
 use crate::{ddebug, StrResult, GLOBAL_HASH_MOUNT};
 use alloc::string::ToString;
 use alloc::sync::Arc;
 pub use define::*;
 use log::debug;
+use spin::Mutex;
+
+/// serialize the renames across directories, so the ancestor check stays valid
+static RENAME_LOCK: Mutex<()> = Mutex::new(());
 
 /// 当删除物理文件时，释放缓存描述符的引用并将其从哈希表中删除
 pub fn remove_dentry_cache(_dentry: Arc<DirEntry>) {
@@ -108,19 +114,19 @@ fn __generic_load_dentry<T: ProcessFs>(
         inode = next_dentry.access_inner().d_inode.clone();
 
         //不是目录也不是符号链接
-        let inode_mode = inode.mode;
+        let inode_mode = inode.mode().file_type();
 
         match inode_mode {
-            InodeMode::S_SYMLINK => {
+            Mode::S_SYMLINK => {
                 // 链接文件
                 advance_link::<T>(lookup_data, next_dentry)?;
                 inode = lookup_data.dentry.access_inner().d_inode.clone();
                 // 如果链接文件没有指向目录，那么就不再继续循环
-                if inode.mode != InodeMode::S_DIR {
+                if !inode.mode().is_dir() {
                     return Err("file is not link or dir");
                 }
             }
-            InodeMode::S_DIR => {
+            Mode::S_DIR => {
                 // 普通目录对象
                 lookup_data.mnt = next_mnt.clone();
                 lookup_data.dentry = next_dentry.clone();
@@ -138,7 +144,7 @@ fn __normal_load_dentry<T: ProcessFs>(
     lookup_data: &mut LookUpData,
     lookup_flags: LookUpFlags,
     dir: &str,
-    inode: Arc<Inode>,
+    _inode: Arc<Inode>,
 ) -> StrResult<()> {
     ddebug!("__normal_load_dentry");
     // 不解析最后一个文件名
@@ -159,7 +165,6 @@ fn __normal_load_dentry<T: ProcessFs>(
         return Ok(());
     }
     // 处理. / ..两种特殊目录
-    let mut inode = inode;
     if dir == "." {
         return Ok(());
     } else if dir == ".." {
@@ -177,11 +182,10 @@ fn __normal_load_dentry<T: ProcessFs>(
 
     // 如果是一个符号链接并且需要读取链接文件
     if lookup_flags.contains(LookUpFlags::READ_LINK)
-        && next_dentry.access_inner().d_inode.mode == InodeMode::S_SYMLINK
+        && next_dentry.access_inner().d_inode.mode().is_symlink()
     {
         // 处理链接文件
         advance_link::<T>(lookup_data, next_dentry.clone())?;
-        inode = lookup_data.dentry.access_inner().d_inode.clone();
     } else {
         // 普通目录对象
         debug!("普通目录对象");
@@ -190,8 +194,9 @@ fn __normal_load_dentry<T: ProcessFs>(
     }
     // 要求最后一个文件必须是目录
     // 例如cd进入目录的情况，或者最后一个字符是/
-    if lookup_flags.contains(LookUpFlags::DIRECTORY) && inode.mode != InodeMode::S_DIR {
-        return Err("file is not dir");
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    if lookup_flags.contains(LookUpFlags::DIRECTORY) && !inode.mode().is_dir() {
+        return Err("Not a directory");
     }
     Ok(())
 }
@@ -257,7 +262,7 @@ pub fn find_file_indir(
 ) -> StrResult<(Arc<VfsMount>, Arc<DirEntry>)> {
     ddebug!("find_file_indir");
     // 检查是否是在目录下查找
-    if !lookup_data.dentry.access_inner().d_inode.mode == InodeMode::S_DIR {
+    if !lookup_data.dentry.access_inner().d_inode.mode().is_dir() {
         return Err("not a dir");
     }
     // 先在缓存中搜索，看看文件是否存在
@@ -389,6 +394,15 @@ pub fn advance_link<T: ProcessFs>(
     lookup_data.nested_count -= 1;
     Ok(())
 }
+/// follow the symlink in the last component of a path with the flags of lookup_data,
+/// with NOLAST the target of the link doesn't need to exist
+pub fn advance_last_link<T: ProcessFs>(
+    lookup_data: &mut LookUpData,
+    dentry: Arc<DirEntry>,
+) -> StrResult<()> {
+    __advance_link::<T>(lookup_data, dentry)
+}
+
 /// 符号链接查找，不考虑嵌套计数
 fn __advance_link<T: ProcessFs>(
     lookup_data: &mut LookUpData,
@@ -402,8 +416,8 @@ fn __advance_link<T: ProcessFs>(
         // 是以'/'开头，已经找到一个绝对路径了
         // 因此没有必要保留前一个路径的任何信息,一切从头开始。
         let process_info = T::get_fs_info();
-        lookup_data.dentry = process_info.current_dir.clone();
-        lookup_data.mnt = process_info.current_mount;
+        lookup_data.dentry = process_info.root_dir.clone();
+        lookup_data.mnt = process_info.root_mount;
     }
     __generic_load_dentry::<T>(&target_name, lookup_data)
 }
@@ -441,7 +455,7 @@ pub fn vfs_rmdir<T: ProcessFs>(dir_name: &str) -> StrResult<()> {
     let dentry = lookup_data.dentry;
     let parent = dentry.access_inner().parent.upgrade().unwrap();
     let parent_inode = parent.access_inner().d_inode.clone();
-    may_delete(parent_inode.clone(), dentry.clone(), true)?;
+    may_delete::<T>(parent_inode.clone(), dentry.clone(), true)?;
 
     // mount point
     let mount = dentry.access_inner().mount_count;
@@ -455,6 +469,8 @@ pub fn vfs_rmdir<T: ProcessFs>(dir_name: &str) -> StrResult<()> {
         return Err("directory not empty");
     }
 
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let rmdir = parent_inode.inode_ops.rmdir;
     // remove from parent dentry
     let name = dentry.access_inner().d_name.clone();
@@ -486,17 +502,24 @@ pub fn vfs_rmdir<T: ProcessFs>(dir_name: &str) -> StrResult<()> {
  *     nfs_async_unlink().
  */
 /// check whether we can delete a find in dir
-pub fn may_delete(dir: Arc<Inode>, dentry: Arc<DirEntry>, isdir: bool) -> StrResult<()> {
+pub fn may_delete<T: ProcessFs>(
+    dir: Arc<Inode>,
+    dentry: Arc<DirEntry>,
+    isdir: bool,
+) -> StrResult<()> {
     ddebug!("may_delete");
-    let mode = dir.mode;
-    if mode != InodeMode::S_DIR {
+    if !dir.mode().is_dir() {
         return Err("not a directory");
     }
-    if dentry.access_inner().d_inode.access_inner().flags == InodeFlags::S_INVALID {
+    let inode = dentry.access_inner().d_inode.clone();
+    if inode.access_inner().flags == InodeFlags::S_INVALID {
         return Err("invalid dir");
     }
+    if check_sticky::<T>(&dir, &inode) {
+        return Err("Operation not permitted");
+    }
     if isdir {
-        if dentry.access_inner().d_inode.mode != InodeMode::S_DIR {
+        if !dentry.access_inner().d_inode.mode().is_dir() {
             return Err("not a directory");
         }
         // root
@@ -504,17 +527,33 @@ pub fn may_delete(dir: Arc<Inode>, dentry: Arc<DirEntry>, isdir: bool) -> StrRes
         if Arc::ptr_eq(&parent, &dentry) {
             return Err("can't remove root directory");
         }
-    } else if dentry.access_inner().d_inode.mode == InodeMode::S_DIR {
+    } else if dentry.access_inner().d_inode.mode().is_dir() {
         return Err("is a directory");
     }
     ddebug!("may_delete end");
     Ok(())
 }
 
+/// In a sticky directory only the owner of the file, the owner of the directory
+/// or a privileged user can delete or rename the file.
+///
+/// return true if the caller is not allowed to do it
+fn check_sticky<T: ProcessFs>(dir: &Arc<Inode>, inode: &Arc<Inode>) -> bool {
+    let (dir_mode, dir_uid) = {
+        let dir_inner = dir.access_inner();
+        (dir_inner.mode, dir_inner.uid)
+    };
+    if !dir_mode.contains(Mode::S_ISVTX) {
+        return false;
+    }
+    let cred = T::get_cred();
+    let uid = inode.access_inner().uid;
+    cred.euid != uid && cred.euid != dir_uid && !cred.is_privileged()
+}
+
 pub fn may_create(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
     ddebug!("may_create");
-    let mode = dir.mode;
-    if mode != InodeMode::S_DIR {
+    if !dir.mode().is_dir() {
         return Err("not a directory");
     }
     // root
@@ -557,7 +596,10 @@ pub fn __truncate(inode: Arc<Inode>, mnt: Arc<VfsMount>, len: usize) -> StrResul
     }
     // ignore permission
     // todo!
-    // modify the inode file_size
+    // modify the inode file_size, the write lock keeps the size consistent with the writers
+    let sb = mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    let _guard = inode.write_lock.lock();
     let old_size = inode.access_inner().file_size;
     inode.access_inner().file_size = len;
     let truncate = inode.inode_ops.truncate;
@@ -565,23 +607,46 @@ pub fn __truncate(inode: Arc<Inode>, mnt: Arc<VfsMount>, len: usize) -> StrResul
         inode.access_inner().file_size = old_size;
         e
     })?;
+    // the cached pages beyond the new size are invalid
+    truncate_inode_pages(&inode, len);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_DATASYNC);
     ddebug!("__truncate end");
     Ok(())
 }
 
 #[inline(always)]
 pub fn is_dir(inode: Arc<Inode>) -> bool {
-    inode.mode == InodeMode::S_DIR
+    inode.mode().is_dir()
 }
 
 /// rename a file
 /// * `old_name` - old file name
 /// * `new_name` - new file name
-/// * `flag` - rename flag
 /// # description
 /// 1. old_name and new_name must be in the same file system
 pub fn vfs_rename<T: ProcessFs>(old_name: &str, new_name: &str) -> StrResult<()> {
-    ddebug!("vfs_rename");
+    vfs_rename2::<T>(old_name, new_name, RenameFlag::empty())
+}
+
+/// rename a file with flags
+/// * `RENAME_NOREPLACE` - fail if the target exists
+/// * `RENAME_EXCHANGE` - exchange the source and the target, both must exist
+/// * `RENAME_WHITEOUT` - leave a whiteout character device at the source
+pub fn vfs_rename2<T: ProcessFs>(
+    old_name: &str,
+    new_name: &str,
+    flags: RenameFlag,
+) -> StrResult<()> {
+    ddebug!("vfs_rename2");
+    if flags.contains(RenameFlag::RENAME_EXCHANGE)
+        && flags.intersects(RenameFlag::RENAME_NOREPLACE | RenameFlag::RENAME_WHITEOUT)
+    {
+        return Err("Invalid argument");
+    }
+    // creating a whiteout is the same as mknod
+    if flags.contains(RenameFlag::RENAME_WHITEOUT) && !T::get_cred().is_privileged() {
+        return Err("Operation not permitted");
+    }
     if old_name == "/" {
         return Err("can't rename root directory");
     }
@@ -593,7 +658,7 @@ pub fn vfs_rename<T: ProcessFs>(old_name: &str, new_name: &str) -> StrResult<()>
     let new_mnt = &new_lookup_data.mnt;
     // check if in the same file system
     if !Arc::ptr_eq(old_mnt, new_mnt) {
-        return Err("not in the same file system");
+        return Err("Invalid cross-device link");
     }
     let old_dentry = old_lookup_data.dentry.clone();
     let new_dentry = new_lookup_data.dentry.clone();
@@ -602,6 +667,12 @@ pub fn vfs_rename<T: ProcessFs>(old_name: &str, new_name: &str) -> StrResult<()>
     {
         return Err("invalid path");
     }
+    // the tree can't be changed by another cross-directory rename
+    let _rename_guard = if Arc::ptr_eq(&old_dentry, &new_dentry) {
+        None
+    } else {
+        Some(RENAME_LOCK.lock())
+    };
 
     // find old file in parent dir
     let last = old_lookup_data.last.clone();
@@ -618,41 +689,132 @@ pub fn vfs_rename<T: ProcessFs>(old_name: &str, new_name: &str) -> StrResult<()>
     debug!("new last: {}", new_last);
     let res = find_file_indir(&mut new_lookup_data, &new_last);
     let new_sub_dentry = match res {
+        Ok(_) if flags.contains(RenameFlag::RENAME_NOREPLACE) => return Err("File exists"),
         Ok((_, sub_dentry)) => sub_dentry,
+        Err(_) if flags.contains(RenameFlag::RENAME_EXCHANGE) => {
+            return Err("No such file or directory")
+        }
         Err(_) => {
             // a fake dentry
             debug!("make a fake dentry");
-            let mut dentry = DirEntry::with_inode_mode(old_sub_dentry.access_inner().d_inode.mode);
+            let mut dentry =
+                DirEntry::with_inode_mode(old_sub_dentry.access_inner().d_inode.mode());
             dentry.access_inner().d_name = new_lookup_data.last.clone();
             dentry.access_inner().parent = Arc::downgrade(&new_dentry);
             dentry.d_ops = old_sub_dentry.d_ops.clone();
+            // the target doesn't exist
+            dentry.access_inner().d_inode.access_inner().flags = InodeFlags::S_INVALID;
             Arc::new(dentry)
         }
     };
     debug!("path walk over");
+    // a dir can't be moved into its own subtree
+    if is_subdir(&new_dentry, &old_sub_dentry) {
+        return Err("Invalid argument");
+    }
+    if flags.contains(RenameFlag::RENAME_EXCHANGE) && is_subdir(&old_dentry, &new_sub_dentry) {
+        return Err("Invalid argument");
+    }
+    if old_sub_dentry.access_inner().mount_count > 0
+        || new_sub_dentry.access_inner().mount_count > 0
+    {
+        return Err("Device or resource busy");
+    }
 
+    let sb = old_lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let old_inode = old_dentry.access_inner().d_inode.clone();
+    // don't hold the lock of new_dentry, the fs may update the children of the parent
+    let new_inode = new_dentry.access_inner().d_inode.clone();
     // the old_dentry may be equal to new_dentry
-    do_internal_rename(
+    do_internal_rename::<T>(
         old_inode,
         old_sub_dentry.clone(),
-        new_dentry.access_inner().d_inode.clone(),
+        new_inode,
         new_sub_dentry.clone(),
+        flags,
     )?;
-    // after rename, the old dentry is invalid
-    // so we need to update the old dentry
-    // warn!("update old dentry");
-    old_sub_dentry.access_inner().d_name = new_sub_dentry.access_inner().d_name.clone();
-    old_sub_dentry.access_inner().parent = new_sub_dentry.access_inner().parent.clone();
-    ddebug!("vfs_rename end");
+    if flags.contains(RenameFlag::RENAME_EXCHANGE) {
+        d_exchange(&old_dentry, &old_sub_dentry, &new_dentry, &new_sub_dentry);
+    } else {
+        // after rename, the old dentry takes the place of the new dentry
+        d_move(&old_dentry, &old_sub_dentry, &new_dentry, &new_sub_dentry);
+    }
+    ddebug!("vfs_rename2 end");
     Ok(())
 }
 
-fn do_internal_rename(
+/// check whether dentry is ancestor or ancestor's descendant
+fn is_subdir(dentry: &Arc<DirEntry>, ancestor: &Arc<DirEntry>) -> bool {
+    let mut dentry = dentry.clone();
+    loop {
+        if Arc::ptr_eq(&dentry, ancestor) {
+            return true;
+        }
+        let parent = dentry.access_inner().parent.upgrade();
+        match parent {
+            // the root of the file system
+            Some(parent) if !Arc::ptr_eq(&parent, &dentry) => dentry = parent,
+            _ => return false,
+        }
+    }
+}
+
+/// move the dentry from old_parent to new_parent in the cache,
+/// the target dentry is dropped from the cache and ".." of the dentry points to new_parent
+fn d_move(
+    old_parent: &Arc<DirEntry>,
+    dentry: &Arc<DirEntry>,
+    new_parent: &Arc<DirEntry>,
+    target: &Arc<DirEntry>,
+) {
+    old_parent
+        .access_inner()
+        .children
+        .retain(|x| !Arc::ptr_eq(x, dentry));
+    new_parent
+        .access_inner()
+        .children
+        .retain(|x| !Arc::ptr_eq(x, target));
+    let name = target.access_inner().d_name.clone();
+    let mut inner = dentry.access_inner();
+    inner.d_name = name;
+    inner.parent = Arc::downgrade(new_parent);
+    drop(inner);
+    new_parent.insert_child(dentry.clone());
+}
+
+/// exchange the name and the parent of the two dentries in the cache
+fn d_exchange(
+    old_parent: &Arc<DirEntry>,
+    dentry: &Arc<DirEntry>,
+    new_parent: &Arc<DirEntry>,
+    target: &Arc<DirEntry>,
+) {
+    if !Arc::ptr_eq(old_parent, new_parent) {
+        old_parent
+            .access_inner()
+            .children
+            .retain(|x| !Arc::ptr_eq(x, dentry));
+        new_parent
+            .access_inner()
+            .children
+            .retain(|x| !Arc::ptr_eq(x, target));
+        old_parent.insert_child(target.clone());
+        new_parent.insert_child(dentry.clone());
+    }
+    let mut inner = dentry.access_inner();
+    let mut target_inner = target.access_inner();
+    core::mem::swap(&mut inner.d_name, &mut target_inner.d_name);
+    core::mem::swap(&mut inner.parent, &mut target_inner.parent);
+}
+
+fn do_internal_rename<T: ProcessFs>(
     old_dir: Arc<Inode>,
     old_dentry: Arc<DirEntry>,
     new_dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
 ) -> StrResult<()> {
     ddebug!("do_internal_rename");
     let is_dir = is_dir(old_dentry.access_inner().d_inode.clone());
@@ -663,20 +825,50 @@ fn do_internal_rename(
     }
 
     debug!("old_dentry: {:?}", old_dentry.access_inner().d_name);
-    may_delete(old_dir.clone(), old_dentry.clone(), is_dir)?;
+    may_delete::<T>(old_dir.clone(), old_dentry.clone(), is_dir)?;
 
     debug!("new_dentry: {:?}", new_dentry.access_inner().d_name);
-    if new_dentry.access_inner().d_inode.access_inner().flags == InodeFlags::S_INVALID {
+    let target_exists = new_inode.access_inner().flags != InodeFlags::S_INVALID;
+    if !target_exists {
         // if the file doesn't exist, we need to create it
         may_create(new_dir.clone(), new_dentry.clone())?;
+    } else if flags.contains(RenameFlag::RENAME_EXCHANGE) {
+        // the target is moved to the old place, so it may be of any type
+        let new_is_dir = new_inode.mode().is_dir();
+        may_delete::<T>(new_dir.clone(), new_dentry.clone(), new_is_dir)?;
     } else {
-        may_delete(new_dir.clone(), new_dentry.clone(), is_dir)?;
+        may_delete::<T>(new_dir.clone(), new_dentry.clone(), is_dir)?;
+        // only an empty dir can be replaced
+        if is_dir && new_inode.access_inner().file_size > 0 {
+            return Err("Directory not empty");
+        }
     }
     // rename
     if is_dir {
-        vfs_rename_dir(old_dir, old_dentry, new_dir, new_dentry)?;
+        vfs_rename_dir(
+            old_dir.clone(),
+            old_dentry,
+            new_dir.clone(),
+            new_dentry,
+            flags,
+        )?;
     } else {
-        vfs_rename_other(old_dir, old_dentry, new_dir, new_dentry)?;
+        vfs_rename_other(
+            old_dir.clone(),
+            old_dentry,
+            new_dir.clone(),
+            new_dentry,
+            flags,
+        )?;
+    }
+    // the link counts and the entries of the dirs are changed
+    mark_inode_dirty(&old_inode, DirtyFlags::I_DIRTY_SYNC);
+    if target_exists {
+        mark_inode_dirty(&new_inode, DirtyFlags::I_DIRTY_SYNC);
+    }
+    mark_inode_dirty(&old_dir, DirtyFlags::I_DIRTY_SYNC);
+    if !Arc::ptr_eq(&old_dir, &new_dir) {
+        mark_inode_dirty(&new_dir, DirtyFlags::I_DIRTY_SYNC);
     }
     ddebug!("do_internal_rename end");
     Ok(())
@@ -687,11 +879,12 @@ fn vfs_rename_other(
     old_dentry: Arc<DirEntry>,
     new_dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
 ) -> StrResult<()> {
     ddebug!("vfs_rename_other start");
     // do somthing that i dont know
     let rename = old_dir.inode_ops.rename;
-    rename(old_dir, old_dentry, new_dir, new_dentry)?;
+    rename(old_dir, old_dentry, new_dir, new_dentry, flags)?;
     ddebug!("vfs_rename_other end");
     Ok(())
 }
@@ -701,11 +894,12 @@ fn vfs_rename_dir(
     old_dentry: Arc<DirEntry>,
     new_dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
 ) -> StrResult<()> {
     ddebug!("vfs_rename_dir start");
     // do somthing that i dont know
     let rename = old_dir.inode_ops.rename;
-    rename(old_dir, old_dentry, new_dir, new_dentry)?;
+    rename(old_dir, old_dentry, new_dir, new_dentry, flags)?;
     ddebug!("vfs_rename_dir end");
     Ok(())
 }
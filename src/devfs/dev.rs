@@ -3,18 +3,18 @@ use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
 use alloc::vec;
 
-use core::cmp::min;
-
 use crate::dentry::{
     DirEntry, DirEntryOps, DirFlags, Dirent64, DirentType, LookUpData, LookUpFlags,
 };
 use crate::devfs::{DevDir, DevNode, DevType};
-use crate::file::{File, FileMode, FileOps};
-use crate::info::MAGIC_BASE;
-use crate::inode::{create_tmp_inode_from_sb_blk, Inode, InodeMode, InodeOps};
+use crate::file::{File, FileOps};
+use crate::info::{VfsTimeSpec, MAGIC_BASE};
+use crate::inode::{create_tmp_inode_from_sb_blk, simple_statfs, Inode, InodeOps, Mode};
 use crate::mount::{MountFlags, VfsMount};
+use crate::stat::{StatFlags, Statx, StatxMask, StatxTimestamp};
 use crate::superblock::{
-    find_super_blk, DataOps, FileSystemType, StatFs, SuperBlock, SuperBlockInner, SuperBlockOps,
+    find_super_blk, free_anon_dev, get_anon_dev, DataOps, FileSystemType, SbWriters, StatFs,
+    SuperBlock, SuperBlockInner, SuperBlockOps,
 };
 use crate::StrResult;
 use core::sync::atomic::{AtomicUsize, Ordering};
@@ -38,6 +38,7 @@ const DEVFS_DIR_INODE_OPS: InodeOps = {
     ops.rmdir = devfs_dir_rmdir;
     // ops.create = devfs_dir_create;
     ops.mknod = devfs_dir_mknod;
+    ops.getattr = devfs_getattr;
     ops
 };
 
@@ -45,6 +46,13 @@ const DEVFS_SYMLINK_INODE_OPS: InodeOps = {
     let mut ops = InodeOps::empty();
     ops.readlink = devfs_symlink_readlink;
     ops.follow_link = devfs_symlink_follow_link;
+    ops.getattr = devfs_getattr;
+    ops
+};
+
+const DEVFS_OTHER_INODE_OPS: InodeOps = {
+    let mut ops = InodeOps::empty();
+    ops.getattr = devfs_getattr;
     ops
 };
 
@@ -63,21 +71,24 @@ const DEVFS_OTHER_FILE_OPS: FileOps = {
     ops
 };
 
+/// devfs keeps no data, only the nodes are counted
 fn devfs_stat_fs(super_blk: Arc<SuperBlock>) -> StrResult<StatFs> {
-    let mut name = [0u8; 32];
-    let dev_name = "devfs";
-    let name_len = min(dev_name.len(), name.len());
-    name[..name_len].copy_from_slice(dev_name.as_bytes());
-    let statfs = StatFs {
-        fs_type: 323232,
-        block_size: super_blk.block_size as u64,
-        total_blocks: 0,
-        free_blocks: 0,
-        total_inodes: 0,
-        name_len: name_len as u32,
-        name,
-    };
-    Ok(statfs)
+    let mut stat = simple_statfs(super_blk.clone())?;
+    let root = super_blk.access_inner().root.clone();
+    let inode = root.access_inner().d_inode.clone();
+    if inode.access_inner().data.is_some() {
+        let devnode = inode_to_devnode(inode)?;
+        stat.f_files = __dev_count_nodes(&devnode);
+    }
+    Ok(stat)
+}
+
+/// count the node and all nodes under it
+fn __dev_count_nodes(node: &Arc<DevNode>) -> u64 {
+    match &node.access_inner().dev_type {
+        DevType::Dir(dir) => 1 + dir.children.iter().map(__dev_count_nodes).sum::<u64>(),
+        _ => 1,
+    }
 }
 
 pub fn devfs_get_super_blk(
@@ -98,8 +109,8 @@ pub fn devfs_get_super_blk(
         }
     };
     let inode = devfs_root_inode(sb_blk.clone())?;
-    // 根目录硬链接计数不用自增1
-    assert_eq!(inode.access_inner().hard_links, 1);
+    // 根目录硬链接计数为2
+    assert_eq!(inode.access_inner().hard_links, 2);
     // create dentry
     let dentry = devfs_root_dentry(inode)?;
     sb_blk.update_root(dentry);
@@ -124,19 +135,17 @@ fn devfs_root_inode(sb_blk: Arc<SuperBlock>) -> StrResult<Arc<Inode>> {
     let inode = create_tmp_inode_from_sb_blk(
         sb_blk,
         number,
-        InodeMode::S_DIR,
+        Mode::S_DIR | Mode::from_bits_truncate(0o755),
         0,
         DEVFS_DIR_INODE_OPS,
         DEVFS_DIR_FILE_OPS,
         None,
     )?;
-    inode.access_inner().hard_links = 1;
     let devfs_inode = DevNode::new(
-        InodeMode::S_DIR,
+        Mode::S_DIR | Mode::from_bits_truncate(0o755),
         0,
         "root".to_string(),
         DevType::Dir(DevDir::empty()),
-        FileMode::FMODE_RDWR,
     );
     let devfs_inode = Arc::new(devfs_inode);
     devfs_inode.access_inner().parent = Arc::downgrade(&devfs_inode);
@@ -151,7 +160,7 @@ fn create_dev_super_blk(
     data: Option<Box<dyn DataOps>>,
 ) -> StrResult<Arc<SuperBlock>> {
     let sb_blk = SuperBlock {
-        dev_desc: 0,
+        dev_desc: get_anon_dev(),
         device: None,
         block_size: 1024,
         dirty_flag: false,
@@ -162,6 +171,7 @@ fn create_dev_super_blk(
         super_block_ops: DEVFS_SB_OPS,
         blk_dev_name: dev_name.to_string(),
         data,
+        writers: SbWriters::new(),
         inner: Mutex::new(SuperBlockInner::empty()),
     };
     let sb_blk = Arc::new(sb_blk);
@@ -169,6 +179,7 @@ fn create_dev_super_blk(
 }
 
 pub fn devfs_kill_super_blk(super_blk: Arc<SuperBlock>) {
+    free_anon_dev(super_blk.dev_desc);
     let mut sb_inner = super_blk.access_inner();
     sb_inner.root = Arc::new(DirEntry::empty());
     sb_inner.files.clear();
@@ -203,25 +214,28 @@ fn devfs_dir_unlink(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
     }
     let sub_file = dentry.access_inner().d_inode.clone();
     // remove devnode from inode data
-    sub_file.access_inner().data = None;
+    let mut sub_file_inner = sub_file.access_inner();
+    sub_file_inner.data = None;
+    sub_file_inner.hard_links -= 1;
     Ok(())
 }
 
-fn devfs_dir_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrResult<()> {
+fn devfs_dir_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()> {
     let devnode = inode_to_devnode(dir.clone())?;
     let name = dentry.access_inner().d_name.to_string();
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     let new_node = DevNode::new(
-        InodeMode::S_DIR,
+        Mode::S_DIR | mode.permission(),
         number,
         name,
         DevType::Dir(DevDir::empty()),
-        mode,
     );
     let new_node = Arc::new(new_node);
     devfs_node_stick(devnode.clone(), new_node.clone())?;
-    let inode = devfs_create_inode(dir, new_node)?;
+    let inode = devfs_create_inode(dir.clone(), new_node)?;
     dentry.access_inner().d_inode = inode;
+    // the ".." of the new dir links to the parent
+    dir.access_inner().hard_links += 1;
     Ok(())
 }
 
@@ -229,7 +243,7 @@ fn devfs_dir_rmdir(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
     debug!("devfs_dir_rmdir");
     let sub_dir = dentry.access_inner().d_inode.clone();
     let sub_devnode = inode_to_devnode(sub_dir.clone())?;
-    if !sub_devnode.mode.contains(InodeMode::S_DIR) {
+    if !sub_devnode.mode.is_dir() {
         return Err("It is not dir");
     }
     if !sub_devnode.access_inner().may_delete {
@@ -246,21 +260,30 @@ fn devfs_dir_rmdir(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
         dir.children.retain(|node| !Arc::ptr_eq(node, &sub_devnode));
     }
     // remove devnode from inode data
-    sub_dir.access_inner().data = None;
-    dir.access_inner().file_size -= 1;
+    let mut sub_dir_inner = sub_dir.access_inner();
+    sub_dir_inner.data = None;
+    sub_dir_inner.hard_links = 0;
+    drop(sub_dir_inner);
+    let mut dir_inner = dir.access_inner();
+    dir_inner.file_size -= 1;
+    dir_inner.hard_links -= 1;
     Ok(())
 }
 
-fn devfs_dir_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult<()> {
+fn devfs_dir_symlink(
+    dir: Arc<Inode>,
+    dentry: Arc<DirEntry>,
+    target: &str,
+    mode: Mode,
+) -> StrResult<()> {
     let devnode = inode_to_devnode(dir.clone())?;
     let name = dentry.access_inner().d_name.to_string();
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     let new_node = DevNode::new(
-        InodeMode::S_SYMLINK,
+        Mode::S_SYMLINK | mode.permission(),
         number,
         name,
         DevType::SymLink(target.to_string()),
-        FileMode::empty(),
     );
     let new_node = Arc::new(new_node);
     devfs_node_stick(devnode.clone(), new_node.clone())?;
@@ -269,17 +292,11 @@ fn devfs_dir_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> St
     Ok(())
 }
 
-fn devfs_dir_mknod(
-    dir: Arc<Inode>,
-    dentry: Arc<DirEntry>,
-    type_: InodeMode,
-    mode: FileMode,
-    dev: u32,
-) -> StrResult<()> {
+fn devfs_dir_mknod(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode, dev: u32) -> StrResult<()> {
     let devnode = inode_to_devnode(dir.clone())?;
     let name = dentry.access_inner().d_name.to_string();
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
-    let new_node = DevNode::new(type_, number, name, DevType::Dev(dev), mode);
+    let new_node = DevNode::new(mode, number, name, DevType::Dev(dev));
     let new_node = Arc::new(new_node);
     devfs_node_stick(devnode.clone(), new_node.clone())?;
     let inode = devfs_create_inode(dir, new_node)?;
@@ -306,7 +323,7 @@ fn devfs_symlink_readlink(dentry: Arc<DirEntry>, buf: &mut [u8]) -> StrResult<us
 
 fn devfs_symlink_follow_link(dentry: Arc<DirEntry>, lookup_data: &mut LookUpData) -> StrResult<()> {
     let devnode = inode_to_devnode(dentry.access_inner().d_inode.clone())?;
-    if !devnode.mode.contains(InodeMode::S_SYMLINK) {
+    if !devnode.mode.is_symlink() {
         return Err("It is not symlink");
     }
     if let DevType::SymLink(target) = &devnode.access_inner().dev_type {
@@ -394,7 +411,7 @@ pub fn devfs_dir_readdir(file: Arc<File>, dirents: &mut [u8]) -> StrResult<usize
 }
 
 fn devfs_node_stick(dir: Arc<DevNode>, new_node: Arc<DevNode>) -> StrResult<()> {
-    if !dir.mode.contains(InodeMode::S_DIR) {
+    if !dir.mode.is_dir() {
         return Err("It is not dir");
     }
     let mut dir_inner = dir.access_inner();
@@ -416,17 +433,17 @@ fn devfs_node_stick(dir: Arc<DevNode>, new_node: Arc<DevNode>) -> StrResult<()>
 }
 
 fn devfs_create_inode(dir: Arc<Inode>, node: Arc<DevNode>) -> StrResult<Arc<Inode>> {
-    let (inode_ops, file_ops, dev_desc) = match node.mode {
-        InodeMode::S_DIR => (DEVFS_DIR_INODE_OPS, DEVFS_DIR_FILE_OPS, 0),
-        InodeMode::S_SYMLINK => (DEVFS_SYMLINK_INODE_OPS, DEVFS_OTHER_FILE_OPS, 0),
-        InodeMode::S_CHARDEV | InodeMode::S_BLKDEV => {
+    let (inode_ops, file_ops, dev_desc) = match node.mode.file_type() {
+        Mode::S_DIR => (DEVFS_DIR_INODE_OPS, DEVFS_DIR_FILE_OPS, 0),
+        Mode::S_SYMLINK => (DEVFS_SYMLINK_INODE_OPS, DEVFS_OTHER_FILE_OPS, 0),
+        Mode::S_CHARDEV | Mode::S_BLKDEV => {
             let dev_desc = match &node.access_inner().dev_type {
                 DevType::Dev(dev) => *dev,
                 _ => return Err("devfs_create_inode error"),
             };
-            (InodeOps::empty(), DEVFS_OTHER_FILE_OPS, dev_desc as u32)
+            (DEVFS_OTHER_INODE_OPS, DEVFS_OTHER_FILE_OPS, dev_desc as u32)
         }
-        InodeMode::S_FIFO | InodeMode::S_SOCK => (InodeOps::empty(), DEVFS_OTHER_FILE_OPS, 0),
+        Mode::S_FIFO | Mode::S_SOCK => (DEVFS_OTHER_INODE_OPS, DEVFS_OTHER_FILE_OPS, 0),
         _ => panic!("devfs_create_inode error"),
     };
 
@@ -440,15 +457,25 @@ fn devfs_create_inode(dir: Arc<Inode>, node: Arc<DevNode>) -> StrResult<Arc<Inod
         None,
     )?;
 
-    match node.mode {
-        InodeMode::S_SYMLINK => {
+    match node.mode.file_type() {
+        Mode::S_SYMLINK => {
             inode.access_inner().file_size = match &node.access_inner().dev_type {
                 DevType::SymLink(s) => s.len(),
                 _ => 0,
             };
         }
-        InodeMode::S_CHARDEV | InodeMode::S_BLKDEV => {}
-        InodeMode::S_FIFO | InodeMode::S_SOCK => {}
+        Mode::S_DIR => {
+            // "." and ".." of every sub dir
+            inode.access_inner().hard_links = match &node.access_inner().dev_type {
+                DevType::Dir(dir) => {
+                    let sub_dirs = dir.children.iter().filter(|x| x.mode.is_dir()).count();
+                    2 + sub_dirs as u32
+                }
+                _ => 2,
+            };
+        }
+        Mode::S_CHARDEV | Mode::S_BLKDEV => {}
+        Mode::S_FIFO | Mode::S_SOCK => {}
         _ => {}
     };
     // warn!("{} devfs_create_inode:{}",dir.number,inode.number);
@@ -457,6 +484,30 @@ fn devfs_create_inode(dir: Arc<Inode>, node: Arc<DevNode>) -> StrResult<Arc<Inod
     Ok(inode)
 }
 
+/// fill the time of the devnode, include the birth time
+fn devfs_getattr(
+    dentry: Arc<DirEntry>,
+    stat: &mut Statx,
+    _mask: StatxMask,
+    _flags: StatFlags,
+) -> StrResult<()> {
+    let inode = dentry.access_inner().d_inode.clone();
+    // the node has been deleted
+    if inode.access_inner().data.is_none() {
+        return Ok(());
+    }
+    let devnode = inode_to_devnode(inode)?;
+    let inner = devnode.access_inner();
+    let to_timestamp =
+        |time: &VfsTimeSpec| StatxTimestamp::new(time.tv_sec as i64, time.tv_nsec as u32);
+    stat.stx_atime = to_timestamp(&inner.access_time);
+    stat.stx_mtime = to_timestamp(&inner.data_modify_time);
+    stat.stx_ctime = to_timestamp(&inner.meta_modify_time);
+    stat.stx_btime = to_timestamp(&inner.create_time);
+    stat.stx_mask |= StatxMask::STATX_BTIME;
+    Ok(())
+}
+
 fn inode_to_devnode(inode: Arc<Inode>) -> StrResult<Arc<DevNode>> {
     let inode_inner = inode.access_inner();
     let data = inode_inner.data.as_ref().unwrap();
@@ -465,7 +516,7 @@ fn inode_to_devnode(inode: Arc<Inode>) -> StrResult<Arc<DevNode>> {
 }
 
 fn __dev_find_in_dir(node: Arc<DevNode>, name: &str) -> StrResult<Arc<DevNode>> {
-    if !node.mode.contains(InodeMode::S_DIR) {
+    if !node.mode.is_dir() {
         return Err("not a dir");
     }
     return match &node.access_inner().dev_type {
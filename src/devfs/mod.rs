@@ -3,9 +3,8 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
 use crate::devfs::dev::{devfs_get_super_blk, devfs_kill_super_blk};
-use crate::file::FileMode;
 use crate::info::VfsTimeSpec;
-use crate::inode::InodeMode;
+use crate::inode::Mode;
 use crate::superblock::{DataOps, Device, FileSystemAttr, FileSystemType, FileSystemTypeInner};
 use spin::{Mutex, MutexGuard};
 
@@ -23,7 +22,7 @@ pub const DEVFS_TYPE: FileSystemType = FileSystemType {
 
 #[derive(Debug)]
 pub struct DevNode {
-    mode: InodeMode,
+    mode: Mode,
     number: usize,
     inner: Mutex<DevNodeInner>,
 }
@@ -40,20 +39,14 @@ pub struct DevNodeInner {
     may_delete: bool,
     name: String,
     parent: Weak<DevNode>,
-    perm: FileMode,
 }
 
 impl DevNode {
     pub fn access_inner(&self) -> MutexGuard<DevNodeInner> {
         self.inner.lock()
     }
-    pub fn new(
-        mode: InodeMode,
-        number: usize,
-        name: String,
-        dev_type: DevType,
-        perm: FileMode,
-    ) -> Self {
+    /// the mode contains the file type and the permission bits
+    pub fn new(mode: Mode, number: usize, name: String, dev_type: DevType) -> Self {
         Self {
             mode,
             number,
@@ -68,7 +61,6 @@ impl DevNode {
                 may_delete: true,
                 name,
                 parent: Weak::new(),
-                perm,
             }),
         }
     }
@@ -0,0 +1,293 @@
+//! 文件之间的数据复制
+//!
+//! [vfs_copy_file_range] and [vfs_sendfile] let the filesystem copy the data itself
+//! with `FileOps::copy_file_range`, otherwise the data is read and written through a
+//! kernel buffer. [vfs_clone_file_range] clones the blocks with `FileOps::remap_file_range`.
+use super::{__vfs_read, __vfs_write, lock_pos, File, OpenFlags, OpenMode, RwfFlags};
+use crate::info::ProcessFs;
+use crate::inode::{file_remove_privs, DirtyFlags, Inode, InodeFlags};
+use crate::page_cache::PAGE_SIZE;
+use crate::writeback::mark_inode_dirty;
+use crate::{ddebug, StrResult};
+use alloc::sync::Arc;
+use alloc::vec;
+use core::cmp::min;
+
+/// the size of the buffer used by the generic copy
+const COPY_CHUNK_SIZE: usize = 16 * PAGE_SIZE;
+
+type CopyFn = fn(Arc<File>, u64, Arc<File>, u64, usize) -> StrResult<usize>;
+
+/// copy `len` bytes from `src` at `src_off` to `dst` at `dst_off`, return the bytes copied
+///
+/// The file offsets are not changed. The copy stops at the end of `src`.
+/// Files on different super blocks can only be copied by a filesystem supporting it,
+/// otherwise "Invalid cross-device link" is returned.
+pub fn vfs_copy_file_range<T: ProcessFs>(
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    ddebug!("vfs_copy_file_range");
+    let (src_inode, dst_inode) = copy_checks(&src, &dst)?;
+    src_off
+        .checked_add(len as u64)
+        .zip(dst_off.checked_add(len as u64))
+        .ok_or("File too large")?;
+    if Arc::ptr_eq(&src_inode, &dst_inode)
+        && src_off < dst_off + len as u64
+        && dst_off < src_off + len as u64
+    {
+        return Err("Invalid argument");
+    }
+    let size = src_inode.access_inner().file_size as u64;
+    let len = min(len as u64, size.saturating_sub(src_off)) as usize;
+    if len == 0 {
+        return Ok(0);
+    }
+    let src_sb = &src.f_mnt.super_block;
+    let dst_sb = &dst.f_mnt.super_block;
+    let same_sb = Arc::ptr_eq(src_sb, dst_sb);
+    if !same_sb && !src_sb.file_system_type.ptr_eq(&dst_sb.file_system_type) {
+        return Err("Invalid cross-device link");
+    }
+    let copy_file_range = dst.f_ops.copy_file_range;
+    let res = match copy_in_fs::<T>(copy_file_range, &src, src_off, &dst, dst_off, len) {
+        // the blocks are cloned if the filesystem can't copy them
+        Err("Not support") if same_sb => {
+            let remap_file_range = dst.f_ops.remap_file_range;
+            match copy_in_fs::<T>(remap_file_range, &src, src_off, &dst, dst_off, len) {
+                Err(_) => generic_copy_file_range::<T>(src, src_off, dst, dst_off, len),
+                res => res,
+            }
+        }
+        Err("Not support") => Err("Invalid cross-device link"),
+        res => res,
+    };
+    ddebug!("vfs_copy_file_range end");
+    res
+}
+
+/// copy `count` bytes from `in_file` to `out_file` at its current offset, which is advanced
+/// * offset: the offset to read from, updated after the copy. If it is None, the current
+///   offset of `in_file` is used and advanced
+pub fn vfs_sendfile<T: ProcessFs>(
+    out_file: Arc<File>,
+    in_file: Arc<File>,
+    offset: Option<&mut u64>,
+    count: usize,
+) -> StrResult<usize> {
+    ddebug!("vfs_sendfile");
+    if !in_file.f_mode.contains(OpenMode::FMODE_READ)
+        || !out_file.f_mode.contains(OpenMode::FMODE_WRITE)
+    {
+        return Err("Bad file descriptor");
+    }
+    if out_file.access_inner().flags.contains(OpenFlags::O_APPEND) {
+        return Err("Invalid argument");
+    }
+    let _out_guard = out_file.lock_pos();
+    let _in_guard = match offset {
+        None if !Arc::ptr_eq(&in_file, &out_file) => Some(lock_pos(&in_file, RwfFlags::empty())?),
+        _ => None,
+    };
+    let pos = match &offset {
+        Some(offset) => **offset,
+        None => in_file.access_inner().f_pos as u64,
+    };
+    let out_pos = out_file.access_inner().f_pos as u64;
+    pos.checked_add(count as u64).ok_or("Invalid argument")?;
+    let in_inode = in_file.f_dentry.access_inner().d_inode.clone();
+    let out_inode = out_file.f_dentry.access_inner().d_inode.clone();
+    // the regular files on the same super block are copied by the filesystem
+    let in_fs = in_inode.mode().is_file()
+        && out_inode.mode().is_file()
+        && !Arc::ptr_eq(&in_inode, &out_inode)
+        && Arc::ptr_eq(&in_file.f_mnt.super_block, &out_file.f_mnt.super_block);
+    let len = if in_fs {
+        let size = in_inode.access_inner().file_size as u64;
+        let count = min(count as u64, size.saturating_sub(pos)) as usize;
+        let copy_file_range = out_file.f_ops.copy_file_range;
+        match count {
+            0 => Ok(0),
+            _ => match copy_in_fs::<T>(copy_file_range, &in_file, pos, &out_file, out_pos, count) {
+                Err("Not support") => generic_copy_file_range::<T>(
+                    in_file.clone(),
+                    pos,
+                    out_file.clone(),
+                    out_pos,
+                    count,
+                ),
+                res => res,
+            },
+        }
+    } else {
+        generic_copy_file_range::<T>(in_file.clone(), pos, out_file.clone(), out_pos, count)
+    }?;
+    match offset {
+        Some(offset) => *offset = pos + len as u64,
+        None => in_file.access_inner().f_pos = (pos + len as u64) as usize,
+    }
+    out_file.access_inner().f_pos = (out_pos + len as u64) as usize;
+    ddebug!("vfs_sendfile end");
+    Ok(len)
+}
+
+/// clone the blocks of `src` to `dst` (FICLONERANGE), return the bytes cloned
+/// * len: 0 means up to the end of `src`
+///
+/// The offsets and the length must be multiple of the block size, except the
+/// length reaching the end of `src`.
+pub fn vfs_clone_file_range<T: ProcessFs>(
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    ddebug!("vfs_clone_file_range");
+    let (src_inode, dst_inode) = copy_checks(&src, &dst)?;
+    if !Arc::ptr_eq(&src.f_mnt, &dst.f_mnt) {
+        return Err("Invalid cross-device link");
+    }
+    let size = src_inode.access_inner().file_size as u64;
+    let len = match len {
+        0 => size.saturating_sub(src_off),
+        len => len as u64,
+    };
+    let src_end = src_off.checked_add(len).ok_or("Invalid argument")?;
+    let dst_end = dst_off.checked_add(len).ok_or("File too large")?;
+    if src_end > size {
+        return Err("Invalid argument");
+    }
+    let block = dst.f_mnt.super_block.block_size.max(1) as u64;
+    if !src_off.is_multiple_of(block) || !dst_off.is_multiple_of(block) {
+        return Err("Invalid argument");
+    }
+    // the tail of the file can only be cloned over the tail of dst
+    if !len.is_multiple_of(block)
+        && (src_end != size || dst_end < dst_inode.access_inner().file_size as u64)
+    {
+        return Err("Invalid argument");
+    }
+    if Arc::ptr_eq(&src_inode, &dst_inode) && src_off < dst_end && dst_off < src_end {
+        return Err("Invalid argument");
+    }
+    if len == 0 {
+        return Ok(0);
+    }
+    let remap_file_range = dst.f_ops.remap_file_range;
+    let len = copy_in_fs::<T>(remap_file_range, &src, src_off, &dst, dst_off, len as usize)
+        .map_err(|e| match e {
+            "Not support" => "Operation not supported",
+            e => e,
+        })?;
+    ddebug!("vfs_clone_file_range end");
+    Ok(len)
+}
+
+/// clone the whole `src` to `dst` (FICLONE)
+pub fn vfs_clone_file<T: ProcessFs>(src: Arc<File>, dst: Arc<File>) -> StrResult<()> {
+    vfs_clone_file_range::<T>(src, 0, dst, 0, 0).map(|_| ())
+}
+
+/// copy through a kernel buffer with the read and write of the files
+///
+/// It stops at the end of `src` or the first short write, an error is returned only
+/// if nothing is copied.
+pub fn generic_copy_file_range<T: ProcessFs>(
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    let mut buf = vec![0u8; min(len, COPY_CHUNK_SIZE)];
+    let mut total = 0;
+    while total < len {
+        let chunk = min(len - total, buf.len());
+        let res = __vfs_read(
+            src.clone(),
+            &mut [&mut buf[..chunk]],
+            src_off + total as u64,
+        )
+        .and_then(|read| {
+            __vfs_write::<T>(
+                dst.clone(),
+                &[&buf[..read]],
+                dst_off + total as u64,
+                RwfFlags::empty(),
+            )
+            .map(|(_, written)| (read, written))
+        });
+        match res {
+            Ok((read, written)) => {
+                total += written;
+                if read < chunk || written < read {
+                    break;
+                }
+            }
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
+        }
+    }
+    Ok(total)
+}
+
+/// the checks shared by copy and clone, return the inodes of src and dst
+fn copy_checks(src: &Arc<File>, dst: &Arc<File>) -> StrResult<(Arc<Inode>, Arc<Inode>)> {
+    if !src.f_mode.contains(OpenMode::FMODE_READ)
+        || !dst.f_mode.contains(OpenMode::FMODE_WRITE)
+        || dst.access_inner().flags.contains(OpenFlags::O_APPEND)
+    {
+        return Err("Bad file descriptor");
+    }
+    let src_inode = src.f_dentry.access_inner().d_inode.clone();
+    let dst_inode = dst.f_dentry.access_inner().d_inode.clone();
+    if src_inode.mode().is_dir() || dst_inode.mode().is_dir() {
+        return Err("Is a directory");
+    }
+    if !src_inode.mode().is_file() || !dst_inode.mode().is_file() {
+        return Err("Invalid argument");
+    }
+    if dst_inode
+        .access_inner()
+        .flags
+        .intersects(InodeFlags::S_IMMUTABLE | InodeFlags::S_APPEND)
+    {
+        return Err("Operation not permitted");
+    }
+    Ok((src_inode, dst_inode))
+}
+
+/// let the filesystem copy the data, dst is locked like a writer
+fn copy_in_fs<T: ProcessFs>(
+    copy: CopyFn,
+    src: &Arc<File>,
+    src_off: u64,
+    dst: &Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    let inode = dst.f_dentry.access_inner().d_inode.clone();
+    let sb = dst.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    file_remove_privs::<T>(&inode);
+    let guard = inode.write_lock.lock();
+    let len = copy(src.clone(), src_off, dst.clone(), dst_off, len)?;
+    let grown = {
+        let mut inner = inode.access_inner();
+        let grown = dst_off as usize + len > inner.file_size;
+        if grown {
+            inner.file_size = dst_off as usize + len;
+        }
+        grown
+    };
+    drop(guard);
+    if grown {
+        mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_DATASYNC);
+    }
+    Ok(len)
+}
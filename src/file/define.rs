@@ -1,6 +1,8 @@
 use crate::dentry::DirEntry;
 use crate::inode::{Inode, SpecialData};
+pub use crate::mmap::VmArea;
 use crate::mount::VfsMount;
+use crate::poll::PollWaiter;
 use crate::StrResult;
 use alloc::sync::Arc;
 use bitflags::bitflags;
@@ -14,7 +16,9 @@ pub struct File {
     // 文件操作
     pub f_ops: FileOps,
     // 打开模式
-    pub f_mode: FileMode,
+    pub f_mode: OpenMode,
+    // 保证读写与文件偏移量的更新是原子的
+    f_pos_lock: Mutex<()>,
     inner: Mutex<FileInner>,
 }
 #[derive(Debug)]
@@ -25,7 +29,6 @@ pub struct FileInner {
     pub f_uid: u32,
     pub f_gid: u32,
     pub f_ops_ext: FileExtOps,
-    pub f_mode2: FileMode2,
 }
 
 impl Debug for File {
@@ -43,7 +46,7 @@ impl File {
         dentry: Arc<DirEntry>,
         mnt: Arc<VfsMount>,
         flags: OpenFlags,
-        mode: FileMode,
+        mode: OpenMode,
         f_ops: FileOps,
     ) -> File {
         File {
@@ -52,19 +55,26 @@ impl File {
             f_ops,
 
             f_mode: mode,
+            f_pos_lock: Mutex::new(()),
             inner: Mutex::new(FileInner {
                 flags,
                 f_pos: 0,
                 f_uid: 0,
                 f_gid: 0,
                 f_ops_ext: FileExtOps::empty(),
-                f_mode2: FileMode2::default(),
             }),
         }
     }
     pub fn access_inner(&self) -> MutexGuard<FileInner> {
         self.inner.lock()
     }
+    /// lock the file position, the read and write using `f_pos` hold it
+    pub fn lock_pos(&self) -> MutexGuard<'_, ()> {
+        self.f_pos_lock.lock()
+    }
+    pub fn try_lock_pos(&self) -> Option<MutexGuard<'_, ()>> {
+        self.f_pos_lock.try_lock()
+    }
 
     pub fn is_block_device(&self) -> bool {
         if let Some(SpecialData::BlockData(_x)) = self
@@ -93,7 +103,7 @@ impl File {
     }
 
     pub fn is_pipe(&self) -> bool {
-        if let Some(SpecialData::PipeData(_x)) = self
+        if let Some(SpecialData::PipeData(_)) = self
             .f_dentry
             .access_inner()
             .d_inode
@@ -140,104 +150,38 @@ bitflags! {
     }
 }
 
-/*
-S_IRWXU  00700 user (file owner) has read, write, and
-execute permission
-
-S_IRUSR  00400 user has read permission
-
-S_IWUSR  00200 user has write permission
-
-S_IXUSR  00100 user has execute permission
-
-S_IRWXG  00070 group has read, write, and execute
-permission
-
-S_IRGRP  00040 group has read permission
-
-S_IWGRP  00020 group has write permission
-
-S_IXGRP  00010 group has execute permission
-
-S_IRWXO  00007 others have read, write, and execute
-permission
-
-S_IROTH  00004 others have read permission
-
-S_IWOTH  00002 others have write permission
-
-S_IXOTH  00001 others have execute permission
-
-According to POSIX, the effect when other bits are set in
-mode is unspecified.  On Linux, the following bits are
-also honored in mode:
-
-S_ISUID  0004000 set-user-ID bit
-
-S_ISGID  0002000 set-group-ID bit (see inode(7)).
-
-S_ISVTX  0001000 sticky bit (see inode(7)).*/
 bitflags! {
-    pub struct FileMode:u32{
-        const FMODE_READ = 0x0;
-        const FMODE_WRITE = 0x1;
-        const FMODE_RDWR = 0x2;
-        const FMODE_EXEC = 0x5; //read and execute
-    }
-
-    pub struct FileMode2:u32{
-        const S_IRUSR = 0x00400;
-        const S_IWUSR = 0x00200;
-        const S_IXUSR = 0x00100;
-        const S_IRWXU = 0x0070;
-        const S_IRGRP = 0x00040;
-        const S_IWGRP = 0x00020;
-        const S_IXGRP = 0x00010;
-        const S_IRWXG = 0x0007;
-        const S_IROTH = 0x00004;
-        const S_IWOTH = 0x00002;
-        const S_IXOTH = 0x00001;
-        const S_ISUID = 0x0004000;
-        const S_ISGID = 0x0002000;
-        const S_ISVTX = 0x0001000;
-    }
-}
-
-impl Default for FileMode2 {
-    fn default() -> Self {
-        FileMode2::from_bits_truncate(0x600)
+    /// the access mode of an opened file, derived from the open flags
+    pub struct OpenMode:u32{
+        const FMODE_READ = 0x1;
+        const FMODE_WRITE = 0x2;
+        const FMODE_EXEC = 0x20;
     }
 }
 
-impl From<FileMode2> for FileMode {
-    fn from(value: FileMode2) -> Self {
-        let mut file_mode = FileMode::empty();
-        if value.contains(FileMode2::S_IRUSR) {
-            file_mode |= FileMode::FMODE_READ;
-        }
-        if value.contains(FileMode2::S_IWUSR) {
-            file_mode |= FileMode::FMODE_WRITE;
-        }
-        if value.contains(FileMode2::S_IXUSR) {
-            file_mode |= FileMode::FMODE_EXEC;
+impl From<OpenFlags> for OpenMode {
+    fn from(value: OpenFlags) -> Self {
+        match value.bits() & 0o3 {
+            0o1 => OpenMode::FMODE_WRITE,
+            0o2 => OpenMode::FMODE_READ | OpenMode::FMODE_WRITE,
+            _ => OpenMode::FMODE_READ,
         }
-        file_mode
     }
 }
 
-impl From<&[u8]> for FileMode {
-    fn from(value: &[u8]) -> Self {
-        let mut mode = FileMode::empty();
-        if value.contains(&b'r') {
-            mode |= FileMode::FMODE_READ;
-        }
-        if value.contains(&b'w') {
-            mode |= FileMode::FMODE_WRITE;
-        }
-        if value.contains(&b'x') {
-            mode |= FileMode::FMODE_EXEC;
-        }
-        mode
+bitflags! {
+    /// the flags of preadv2 and pwritev2
+    pub struct RwfFlags:u32{
+        /// high priority request, ignored
+        const RWF_HIPRI = 0x1;
+        /// per-IO O_DSYNC
+        const RWF_DSYNC = 0x2;
+        /// per-IO O_SYNC
+        const RWF_SYNC = 0x4;
+        /// return EAGAIN if the operation would block
+        const RWF_NOWAIT = 0x8;
+        /// per-IO O_APPEND
+        const RWF_APPEND = 0x10;
     }
 }
 
@@ -246,6 +190,10 @@ pub enum SeekFrom {
     Start(u64),
     End(u64),
     Current(i64),
+    /// the next data at or after the offset
+    Data(u64),
+    /// the next hole at or after the offset
+    Hole(u64),
     Unknown,
 }
 
@@ -255,17 +203,13 @@ impl From<(usize, usize)> for SeekFrom {
             (0, offset) => SeekFrom::Start(offset as u64),
             (1, offset) => SeekFrom::Current(offset as i64),
             (2, offset) => SeekFrom::End(offset as u64),
+            (3, offset) => SeekFrom::Data(offset as u64),
+            (4, offset) => SeekFrom::Hole(offset as u64),
             _ => SeekFrom::Unknown,
         }
     }
 }
 
-#[derive(Debug)]
-pub struct VmArea {
-    pub vm_start: usize,
-    pub vm_end: usize,
-}
-
 /// For poll
 #[derive(Clone)]
 pub struct FileExtOps {
@@ -273,6 +217,8 @@ pub struct FileExtOps {
     pub is_ready_write: fn(file: Arc<File>) -> bool,
     pub is_ready_exception: fn(file: Arc<File>) -> bool,
     pub is_hang_up: fn(file: Arc<File>) -> bool,
+    /// register the waiter on the file, it is woken up when the readiness changes
+    pub poll_wait: fn(file: Arc<File>, waiter: Arc<dyn PollWaiter>),
     pub ioctl: fn(file: Arc<File>, cmd: u32, arg: usize) -> isize,
 }
 impl Debug for FileExtOps {
@@ -282,6 +228,10 @@ impl Debug for FileExtOps {
             .field("is_ready_write", &"fn(file: Arc<File>) -> bool")
             .field("is_ready_exception", &"fn(file: Arc<File>) -> bool")
             .field("is_hang_up", &"fn(file: Arc<File>) -> bool")
+            .field(
+                "poll_wait",
+                &"fn(file: Arc<File>, waiter: Arc<dyn PollWaiter>)",
+            )
             .finish()
     }
 }
@@ -293,6 +243,8 @@ impl FileExtOps {
             is_ready_write: |_| true,
             is_ready_exception: |_| false,
             is_hang_up: |_| false,
+            // the state of the file never changes
+            poll_wait: |_, _| {},
             ioctl: |_, _, _| -1,
         }
     }
@@ -303,12 +255,17 @@ pub struct FileOps {
     pub llseek: fn(file: Arc<File>, whence: SeekFrom) -> StrResult<u64>,
     pub read: fn(file: Arc<File>, buf: &mut [u8], offset: u64) -> StrResult<usize>,
     pub write: fn(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usize>,
+    /// 向量读，如果没有实现，则使用read逐个读取
+    pub read_iter: fn(file: Arc<File>, bufs: &mut [&mut [u8]], offset: u64) -> StrResult<usize>,
+    /// 向量写，如果没有实现，则使用write逐个写入
+    pub write_iter: fn(file: Arc<File>, bufs: &[&[u8]], offset: u64) -> StrResult<usize>,
     pub readdir: fn(file: Arc<File>, dirents: &mut [u8]) -> StrResult<usize>,
     /// 系统调用ioctl提供了一种执行设备特殊命令的方法(如格式化软盘的某个磁道，这既不是读也不是写操作)。
     /// 另外，内核还能识别一部分ioctl命令，而不必调用fops表中的ioctl。如果设备不提供ioctl入口点，
     /// 则对于任何内核未预先定义的请求，ioctl系统调用将返回错误(-ENOTYY)
     pub ioctl: fn(dentry: Arc<Inode>, file: Arc<File>, cmd: u32, arg: u64) -> StrResult<isize>,
-    pub mmap: fn(file: Arc<File>, vma: VmArea) -> StrResult<()>,
+    /// 映射文件，设置vma的vm_ops
+    pub mmap: fn(file: Arc<File>, vma: &mut VmArea) -> StrResult<()>,
     pub open: fn(file: Arc<File>) -> StrResult<()>,
     pub flush: fn(file: Arc<File>) -> StrResult<()>,
     /// 该方法是fsync系统调用的后端实现
@@ -316,6 +273,22 @@ pub struct FileOps {
     /// 如果驱动程序没有实现这一方法，fsync系统调用将返回-EINVAL。
     pub fsync: fn(file: Arc<File>, datasync: bool) -> StrResult<()>,
     pub release: fn(file: Arc<File>) -> StrResult<()>,
+    /// 在文件系统内部复制数据，不经过用户的缓冲区，没有实现时使用读写复制
+    pub copy_file_range: fn(
+        src: Arc<File>,
+        src_off: u64,
+        dst: Arc<File>,
+        dst_off: u64,
+        len: usize,
+    ) -> StrResult<usize>,
+    /// 克隆文件的数据(reflink)，src与dst在同一个文件系统中，范围已经按块对齐
+    pub remap_file_range: fn(
+        src: Arc<File>,
+        src_off: u64,
+        dst: Arc<File>,
+        dst_off: u64,
+        len: usize,
+    ) -> StrResult<usize>,
 }
 
 impl Debug for FileOps {
@@ -330,6 +303,8 @@ impl FileOps {
             llseek: |_, _| Err("Not support"),
             read: |_, _, _| Err("Not support"),
             write: |_, _, _| Err("Not support"),
+            read_iter: |_, _, _| Err("Not support"),
+            write_iter: |_, _, _| Err("Not support"),
             readdir: |_, _| Err("Not support"),
             ioctl: |_, _, _, _| Err("Not support"),
             mmap: |_, _| Err("Not support"),
@@ -337,6 +312,8 @@ impl FileOps {
             flush: |_| Ok(()),
             fsync: |_, _| Ok(()),
             release: |_| Ok(()),
+            copy_file_range: |_, _, _, _, _| Err("Not support"),
+            remap_file_range: |_, _, _, _, _| Err("Not support"),
         }
     }
 }
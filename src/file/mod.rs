@@ -1,32 +1,41 @@
+mod copy;
 mod define;
 use crate::dentry::{
-    advance_link, advance_mount, find_file_indir, path_walk, DirEntry, LookUpData, LookUpFlags,
-    PathType,
+    __truncate, advance_last_link, advance_mount, find_file_indir, path_walk, DirEntry, LookUpData,
+    LookUpFlags, PathType,
 };
 use crate::info::ProcessFs;
-use crate::inode::{Inode, InodeMode};
+use crate::inode::{
+    file_remove_privs, generic_permission, inode_init_owner, AccessMode, DirtyFlags, FallocFlags,
+    Inode, InodeFlags, Mode,
+};
+use crate::mount::{mnt_want_write, MountFlags};
+use crate::pipe::{fifo_open, PIPE_FILE_OPS};
+use crate::stat::StatFlags;
+use crate::writeback::{mark_inode_dirty, write_inode_now};
 use crate::{ddebug, StrResult};
 use alloc::sync::Arc;
+pub use copy::*;
 pub use define::*;
 use log::debug;
+use spin::MutexGuard;
 
 /// 打开文件
 /// * name:文件名
 /// * flags: 访问模式
-/// * mode: 创建文件读写权限
+/// * mode: 创建文件时的权限，会经过umask的过滤
 pub fn vfs_open_file<T: ProcessFs>(
     name: &str,
     flags: OpenFlags,
-    mode: FileMode,
+    mode: Mode,
 ) -> StrResult<Arc<File>> {
     ddebug!("open_file");
-    let mut flags = flags;
-    //  如果flag包含truncate标志，则将其转换为读写模式
-    if flags.contains(OpenFlags::O_TRUNC) {
-        flags |= OpenFlags::O_RDWR;
+    if flags.contains(OpenFlags::O_CREAT | OpenFlags::O_DIRECTORY) {
+        return Err("Invalid argument");
     }
     let lookup_data = open_dentry::<T>(name, flags, mode)?;
-    let file = construct_file(&lookup_data, flags, mode)?;
+    may_open(&lookup_data, flags)?;
+    let file = construct_file::<T>(&lookup_data, flags, flags.into())?;
     if flags.contains(OpenFlags::O_APPEND) {
         let size = file
             .f_dentry
@@ -39,28 +48,59 @@ pub fn vfs_open_file<T: ProcessFs>(
     Ok(file)
 }
 
-fn construct_file(
+/// check the type of the file against the open flags and truncate the file if needed
+/// * a directory can only be opened for reading
+/// * O_DIRECTORY requires a directory
+/// * O_TRUNC truncates a regular file opened for writing
+fn may_open(lookup_data: &LookUpData, flags: OpenFlags) -> StrResult<()> {
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let mode = inode.mode();
+    let write = OpenMode::from(flags).contains(OpenMode::FMODE_WRITE);
+    if mode.is_symlink() {
+        // O_NOFOLLOW and the last component is a symlink
+        return Err("Too many levels of symbolic links");
+    }
+    if mode.is_dir() && write {
+        return Err("Is a directory");
+    }
+    if flags.contains(OpenFlags::O_DIRECTORY) && !mode.is_dir() {
+        return Err("Not a directory");
+    }
+    let is_special = mode.is_chr() || mode.is_blk() || mode.is_fifo() || mode.is_sock();
+    if write && !is_special && !mnt_want_write(&lookup_data.mnt) {
+        return Err("read only file system");
+    }
+    if write && flags.contains(OpenFlags::O_TRUNC) && mode.is_file() {
+        __truncate(inode, lookup_data.mnt.clone(), 0)?;
+    }
+    Ok(())
+}
+
+fn construct_file<T: ProcessFs>(
     lookup_data: &LookUpData,
     flags: OpenFlags,
-    mode: FileMode,
+    mode: OpenMode,
 ) -> StrResult<Arc<File>> {
     ddebug!("construct_file");
     let dentry = lookup_data.dentry.clone();
     // flags include directory
     let binding = &lookup_data.mnt;
     let sb = &binding.super_block;
-    if let Some(file) = sb.find_file(&dentry) {
-        // we reset the file position to 0
-        file.access_inner().f_pos = 0;
-        return Ok(file);
-    }
     let inode = dentry.access_inner().d_inode.clone();
-    let f_ops = inode.file_ops.clone();
+    // the FIFO is opened as a pipe whatever the file system is
+    let is_fifo = inode.mode().is_fifo();
+    let f_ops = match is_fifo {
+        true => PIPE_FILE_OPS,
+        false => inode.file_ops.clone(),
+    };
     let open = f_ops.open;
     let file = File::new(dentry, lookup_data.mnt.clone(), flags, mode, f_ops);
     let file = Arc::new(file);
     // TODO impl open in inodeops
-    let res = open(file.clone());
+    let res = match is_fifo {
+        true => fifo_open::<T>(&file),
+        false => open(file.clone()),
+    };
     if res.is_err() {
         return Err(res.err().unwrap());
     }
@@ -97,8 +137,62 @@ pub fn vfs_read_file<T: ProcessFs>(
     buf: &mut [u8],
     offset: u64,
 ) -> StrResult<usize> {
+    let _guard = file.lock_pos();
+    let len = __vfs_read(file.clone(), &mut [buf], offset)?;
+    // update inode offset
+    file.access_inner().f_pos = offset as usize + len;
+    Ok(len)
+}
+
+/// read file from the current file offset and advance it
+pub fn vfs_read<T: ProcessFs>(file: Arc<File>, buf: &mut [u8]) -> StrResult<usize> {
+    vfs_readv::<T>(file, &mut [buf])
+}
+
+/// read file at the given offset, the file offset is not changed
+///
+/// "Illegal seek" is returned for a pipe.
+pub fn vfs_pread<T: ProcessFs>(file: Arc<File>, buf: &mut [u8], offset: u64) -> StrResult<usize> {
+    if file.is_pipe() {
+        return Err("Illegal seek");
+    }
+    __vfs_read(file, &mut [buf], offset)
+}
+
+/// read file into several buffers from the current file offset and advance it
+pub fn vfs_readv<T: ProcessFs>(file: Arc<File>, bufs: &mut [&mut [u8]]) -> StrResult<usize> {
+    vfs_preadv2::<T>(file, bufs, -1, RwfFlags::empty())
+}
+
+/// read file into several buffers
+/// * offset: the offset to read from, -1 means the current file offset, which is advanced
+/// * flags: RWF_NOWAIT returns an error instead of waiting for the file offset
+pub fn vfs_preadv2<T: ProcessFs>(
+    file: Arc<File>,
+    bufs: &mut [&mut [u8]],
+    offset: i64,
+    flags: RwfFlags,
+) -> StrResult<usize> {
+    if offset < -1 {
+        return Err("Invalid argument");
+    }
+    if offset >= 0 {
+        // the pipe has no offset to read at
+        if file.is_pipe() {
+            return Err("Illegal seek");
+        }
+        return __vfs_read(file, bufs, offset as u64);
+    }
+    let _guard = lock_pos(&file, flags)?;
+    let pos = file.access_inner().f_pos;
+    let len = __vfs_read(file.clone(), bufs, pos as u64)?;
+    file.access_inner().f_pos = pos + len;
+    Ok(len)
+}
+
+pub(crate) fn __vfs_read(file: Arc<File>, bufs: &mut [&mut [u8]], offset: u64) -> StrResult<usize> {
     let mode = file.f_mode;
-    if !mode.contains(FileMode::FMODE_READ) {
+    if !mode.contains(OpenMode::FMODE_READ) {
         return Err("file not open for reading");
     }
     let inode = file.f_dentry.access_inner().d_inode.clone();
@@ -106,30 +200,119 @@ pub fn vfs_read_file<T: ProcessFs>(
         debug!("file is invalid");
         return Err("file is invalid");
     }
-    if inode.mode == InodeMode::S_DIR {
+    if inode.mode().is_dir() {
         return Err("file is dir");
     }
+    let read_iter = file.f_ops.read_iter;
+    match read_iter(file.clone(), bufs, offset) {
+        Err("Not support") => generic_file_read_iter(file, bufs, offset),
+        res => res,
+    }
+}
+
+/// read the buffers one by one with the read method of the file
+///
+/// It stops at the first short read, an error is returned only if nothing is read.
+pub fn generic_file_read_iter(
+    file: Arc<File>,
+    bufs: &mut [&mut [u8]],
+    offset: u64,
+) -> StrResult<usize> {
     let read = file.f_ops.read;
-    let len = read(file.clone(), buf, offset);
-    if let Ok(len) = len {
-        // update inode offset
-        file.access_inner().f_pos = offset as usize + len;
-        return Ok(len);
+    let mut total = 0;
+    for buf in bufs.iter_mut() {
+        match read(file.clone(), buf, offset + total as u64) {
+            Ok(len) => {
+                total += len;
+                if len < buf.len() {
+                    break;
+                }
+            }
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
+        }
     }
-    Err(len.err().unwrap())
+    Ok(total)
 }
 
 /// write file
 ///
 /// This function will update the file size and offset if the write operation is successful.
+/// If the file is opened with O_APPEND, the data is always appended to the end of file.
 pub fn vfs_write_file<T: ProcessFs>(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usize> {
-    let write = file.f_ops.write;
-    let mode = file.f_mode;
-    let mode2 = file.access_inner().f_mode2.clone();
-    if !mode.contains(FileMode::FMODE_WRITE) && !mode.contains(FileMode::FMODE_RDWR) {
-        if mode2 != FileMode2::from_bits_truncate(0x777) {
-            return Err("file not open for writing");
+    let _guard = file.lock_pos();
+    let (offset, len) = __vfs_write::<T>(file.clone(), &[buf], offset, RwfFlags::empty())?;
+    let mut inner = file.access_inner();
+    if offset as usize + len > inner.f_pos {
+        inner.f_pos = offset as usize + len;
+    }
+    Ok(len)
+}
+
+/// write file at the current file offset and advance it
+///
+/// If the file is opened with O_APPEND, the file offset is moved to the end of file first.
+pub fn vfs_write<T: ProcessFs>(file: Arc<File>, buf: &[u8]) -> StrResult<usize> {
+    vfs_writev::<T>(file, &[buf])
+}
+
+/// write file at the given offset, the file offset is not changed
+///
+/// Like linux, the offset is ignored if the file is opened with O_APPEND,
+/// and "Illegal seek" is returned for a pipe.
+pub fn vfs_pwrite<T: ProcessFs>(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usize> {
+    if file.is_pipe() {
+        return Err("Illegal seek");
+    }
+    __vfs_write::<T>(file, &[buf], offset, RwfFlags::empty()).map(|(_, len)| len)
+}
+
+/// write several buffers at the current file offset and advance it
+///
+/// The buffers are written as a whole, other writers can't insert data between them.
+pub fn vfs_writev<T: ProcessFs>(file: Arc<File>, bufs: &[&[u8]]) -> StrResult<usize> {
+    vfs_pwritev2::<T>(file, bufs, -1, RwfFlags::empty())
+}
+
+/// write several buffers
+/// * offset: the offset to write to, -1 means the current file offset, which is advanced
+/// * flags:
+///     * RWF_APPEND: append the data to the end of file like O_APPEND
+///     * RWF_NOWAIT: return an error instead of waiting for the other writers
+///     * RWF_DSYNC/RWF_SYNC: sync the file after the write
+pub fn vfs_pwritev2<T: ProcessFs>(
+    file: Arc<File>,
+    bufs: &[&[u8]],
+    offset: i64,
+    flags: RwfFlags,
+) -> StrResult<usize> {
+    if offset < -1 {
+        return Err("Invalid argument");
+    }
+    if offset >= 0 {
+        // the pipe has no offset to write at
+        if file.is_pipe() {
+            return Err("Illegal seek");
         }
+        return __vfs_write::<T>(file, bufs, offset as u64, flags).map(|(_, len)| len);
+    }
+    let _guard = lock_pos(&file, flags)?;
+    let pos = file.access_inner().f_pos;
+    let (offset, len) = __vfs_write::<T>(file.clone(), bufs, pos as u64, flags)?;
+    file.access_inner().f_pos = offset as usize + len;
+    Ok(len)
+}
+
+/// write the data and update the inode size, return the offset actually used and the length
+pub(crate) fn __vfs_write<T: ProcessFs>(
+    file: Arc<File>,
+    bufs: &[&[u8]],
+    offset: u64,
+    flags: RwfFlags,
+) -> StrResult<(u64, usize)> {
+    let mode = file.f_mode;
+    if !mode.contains(OpenMode::FMODE_WRITE) {
+        return Err("file not open for writing");
     }
     // check whether file is valid
     let inode = file.f_dentry.access_inner().d_inode.clone();
@@ -137,27 +320,129 @@ pub fn vfs_write_file<T: ProcessFs>(file: Arc<File>, buf: &[u8], offset: u64) ->
         debug!("file is invalid");
         return Err("file is invalid");
     }
-    if inode.mode == InodeMode::S_DIR {
+    if inode.mode().is_dir() {
         return Err("file is dir");
     }
-    let len = write(file.clone(), buf, offset);
-    // update inode size and offset
-    if let Ok(len) = len {
-        let mut size = inode.access_inner().file_size;
-        if offset as usize + len > size {
-            size = offset as usize + len;
-            inode.access_inner().file_size = size;
+    // the pipe has no size and may block, so it doesn't hold the locks of the filesystem
+    if inode.mode().is_fifo() {
+        let res = write_iter_or_write(file, bufs, offset);
+        if res == Err("Broken pipe") {
+            T::send_sigpipe();
+        }
+        return res.map(|len| (offset, len));
+    }
+    // a frozen filesystem blocks the writers until it is thawed
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = if flags.contains(RwfFlags::RWF_NOWAIT) {
+        sb.try_start_write()
+            .ok_or("Resource temporarily unavailable")?
+    } else {
+        sb.start_write()
+    };
+    // writing to a setuid/setgid file clears the special bits
+    file_remove_privs::<T>(&inode);
+    let append = flags.contains(RwfFlags::RWF_APPEND)
+        || file.access_inner().flags.contains(OpenFlags::O_APPEND);
+    // the size is read and updated under the write lock, so the appends don't overwrite each other
+    let _guard = if flags.contains(RwfFlags::RWF_NOWAIT) {
+        inode
+            .write_lock
+            .try_lock()
+            .ok_or("Resource temporarily unavailable")?
+    } else {
+        inode.write_lock.lock()
+    };
+    let offset = if append {
+        inode.access_inner().file_size as u64
+    } else {
+        offset
+    };
+    let len = write_iter_or_write(file.clone(), bufs, offset)?;
+    // update inode size
+    let grown = {
+        let mut inner = inode.access_inner();
+        let grown = offset as usize + len > inner.file_size;
+        if grown {
+            inner.file_size = offset as usize + len;
+        }
+        grown
+    };
+    if grown {
+        mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_DATASYNC);
+    }
+    if flags.intersects(RwfFlags::RWF_DSYNC | RwfFlags::RWF_SYNC) {
+        let fsync = file.f_ops.fsync;
+        if let Err(err) = fsync(file.clone(), !flags.contains(RwfFlags::RWF_SYNC)) {
+            // the data is written, so the error is left to the next fsync
+            if len == 0 {
+                return Err(err);
+            }
+            inode.mapping.set_wb_err(err);
         }
-        if offset as usize + len > file.access_inner().f_pos {
-            file.access_inner().f_pos = offset as usize + len;
+    }
+    Ok((offset, len))
+}
+
+fn write_iter_or_write(file: Arc<File>, bufs: &[&[u8]], offset: u64) -> StrResult<usize> {
+    let write_iter = file.f_ops.write_iter;
+    match write_iter(file.clone(), bufs, offset) {
+        Err("Not support") => generic_file_write_iter(file, bufs, offset),
+        res => res,
+    }
+}
+
+/// write the buffers one by one with the write method of the file
+///
+/// It stops at the first short write, an error is returned only if nothing is written.
+pub fn generic_file_write_iter(file: Arc<File>, bufs: &[&[u8]], offset: u64) -> StrResult<usize> {
+    let write = file.f_ops.write;
+    let mut total = 0;
+    for buf in bufs {
+        match write(file.clone(), buf, offset + total as u64) {
+            Ok(len) => {
+                total += len;
+                if len < buf.len() {
+                    break;
+                }
+            }
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
         }
-        Ok(len)
+    }
+    Ok(total)
+}
+
+/// lock the file offset, fail if it is held by others and RWF_NOWAIT is set
+fn lock_pos(file: &Arc<File>, flags: RwfFlags) -> StrResult<MutexGuard<'_, ()>> {
+    if flags.contains(RwfFlags::RWF_NOWAIT) {
+        file.try_lock_pos()
+            .ok_or("Resource temporarily unavailable")
     } else {
-        len
+        Ok(file.lock_pos())
     }
 }
 
-pub fn vfs_mkdir<T: ProcessFs>(name: &str, mode: FileMode) -> StrResult<()> {
+/// set the file mode creation mask of the process and return the previous mask
+pub fn vfs_umask<T: ProcessFs>(mask: Mode) -> Mode {
+    let old = T::get_fs_info().umask;
+    let mask = mask & (Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+    T::update_umask(mask);
+    old
+}
+
+/// get the file mode creation mask of the process
+pub fn vfs_get_umask<T: ProcessFs>() -> Mode {
+    T::get_fs_info().umask
+}
+
+/// build the mode of a new file from the file type and the permission bits given by user,
+/// the umask of the process is applied to the permission bits
+pub fn apply_umask<T: ProcessFs>(type_: Mode, mode: Mode) -> Mode {
+    let umask = T::get_fs_info().umask;
+    type_ | (mode.permission() - umask)
+}
+
+pub fn vfs_mkdir<T: ProcessFs>(name: &str, mode: Mode) -> StrResult<()> {
     ddebug!("vfs_mkdir");
     let lookup_data = path_walk::<T>(name, LookUpFlags::NOLAST);
     if lookup_data.is_err() {
@@ -184,22 +469,69 @@ pub fn vfs_mkdir<T: ProcessFs>(name: &str, mode: FileMode) -> StrResult<()> {
     target_dentry.access_inner().d_name = last;
     // 设置父子关系
     target_dentry.access_inner().parent = Arc::downgrade(&dentry);
+    // only the permission bits and the sticky bit are used by mkdir
+    let mode = mode & (Mode::S_ISVTX | Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+    let mode = apply_umask::<T>(Mode::S_DIR, mode);
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let mkdir = inode.inode_ops.mkdir;
-    mkdir(inode, target_dentry.clone(), mode)?;
+    mkdir(inode.clone(), target_dentry.clone(), mode)?;
+    inode_init_owner::<T>(&inode, &target_dentry.access_inner().d_inode);
     dentry.insert_child(target_dentry);
     // TODO dentry 插入全局链表
     Ok(())
 }
 
+/// check whether the process can access the file, the file isn't opened
+/// * mode: R_OK/W_OK/X_OK, F_OK only checks whether the file exists
+/// * flags: AT_EACCESS uses the effective ids instead of the real ids,
+///   AT_SYMLINK_NOFOLLOW doesn't follow the last symlink
+pub fn vfs_access<T: ProcessFs>(name: &str, mode: AccessMode, flags: StatFlags) -> StrResult<()> {
+    ddebug!("vfs_access");
+    let lookup_flags = if flags.contains(StatFlags::AT_SYMLINK_NOFOLLOW) {
+        LookUpFlags::empty()
+    } else {
+        LookUpFlags::READ_LINK
+    };
+    let lookup_data = path_walk::<T>(name, lookup_flags)?;
+    if mode.is_empty() {
+        return Ok(());
+    }
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let inode_mode = inode.mode();
+    let is_special =
+        inode_mode.is_chr() || inode_mode.is_blk() || inode_mode.is_fifo() || inode_mode.is_sock();
+    // the device files are not stored in the file system
+    if mode.contains(AccessMode::W_OK) && !is_special && !mnt_want_write(&lookup_data.mnt) {
+        return Err("read only file system");
+    }
+    if mode.contains(AccessMode::X_OK)
+        && inode_mode.is_file()
+        && lookup_data.mnt.flag.contains(MountFlags::MNT_NO_EXEC)
+    {
+        return Err("Permission denied");
+    }
+    let cred = T::get_cred();
+    let (uid, gid) = if flags.contains(StatFlags::AT_EACCESS) {
+        (cred.euid, cred.egid)
+    } else {
+        (cred.uid, cred.gid)
+    };
+    if !generic_permission(&inode, mode, uid, gid) {
+        return Err("Permission denied");
+    }
+    ddebug!("vfs_access end");
+    Ok(())
+}
+
 /// llseek
 pub fn vfs_llseek(file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
+    let _guard = file.lock_pos();
     let llseek = file.f_ops.llseek;
     let res = llseek(file.clone(), whence);
     match res {
-        Err("Not support") => return __llseek(file, whence),
-        Err(_) => {
-            return Err("llseek error");
-        }
+        Err("Not support") => return __llseek(file.clone(), whence),
+        Err(e) => return Err(e),
         Ok(_) => {}
     }
     res
@@ -235,6 +567,19 @@ fn __llseek(file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
             }
             inner.f_pos = new_pos as usize;
         }
+        // the whole file is data if the file system doesn't know the holes
+        SeekFrom::Data(off) => {
+            if off as usize >= f_size {
+                return Err("No such device or address");
+            }
+            inner.f_pos = off as usize;
+        }
+        SeekFrom::Hole(off) => {
+            if off as usize >= f_size {
+                return Err("No such device or address");
+            }
+            inner.f_pos = f_size;
+        }
         _ => {
             return Err("invalid whence");
         }
@@ -242,27 +587,100 @@ fn __llseek(file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
     Ok(inner.f_pos as u64)
 }
 
+/// manipulate the space of the file
+/// * mode: empty allocates the blocks of [offset, offset + len) and extends the file,
+///   see [FallocFlags] for the others
+pub fn vfs_fallocate(
+    file: Arc<File>,
+    mode: FallocFlags,
+    offset: usize,
+    len: usize,
+) -> StrResult<()> {
+    ddebug!("vfs_fallocate");
+    if len == 0 {
+        return Err("Invalid argument");
+    }
+    // punching a hole never changes the size
+    if mode.contains(FallocFlags::FALLOC_FL_PUNCH_HOLE)
+        && (!mode.contains(FallocFlags::FALLOC_FL_KEEP_SIZE)
+            || mode.contains(FallocFlags::FALLOC_FL_ZERO_RANGE))
+    {
+        return Err("Operation not supported");
+    }
+    if mode.contains(FallocFlags::FALLOC_FL_COLLAPSE_RANGE)
+        && mode != FallocFlags::FALLOC_FL_COLLAPSE_RANGE
+    {
+        return Err("Invalid argument");
+    }
+    if !file.f_mode.contains(OpenMode::FMODE_WRITE) {
+        return Err("Bad file descriptor");
+    }
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let flags = inode.access_inner().flags;
+    if flags.contains(InodeFlags::S_IMMUTABLE) {
+        return Err("Operation not permitted");
+    }
+    // only the space can be added to an append only file
+    if flags.contains(InodeFlags::S_APPEND) && !(mode - FallocFlags::FALLOC_FL_KEEP_SIZE).is_empty()
+    {
+        return Err("Operation not permitted");
+    }
+    if inode.mode().is_dir() {
+        return Err("Is a directory");
+    }
+    if !inode.mode().is_file() {
+        return Err("No such device");
+    }
+    offset.checked_add(len).ok_or("File too large")?;
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    // the size is changed under the write lock like the writers
+    let guard = inode.write_lock.lock();
+    let fallocate = inode.inode_ops.fallocate;
+    fallocate(inode.clone(), mode, offset, len).map_err(|e| match e {
+        "Not support" => "Operation not supported",
+        e => e,
+    })?;
+    drop(guard);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_DATASYNC);
+    ddebug!("vfs_fallocate end");
+    Ok(())
+}
+
 pub fn vfs_readdir(file: Arc<File>, dirents: &mut [u8]) -> StrResult<usize> {
     let readdir = file.f_ops.readdir;
     readdir(file, dirents)
 }
 
 pub fn vfs_fsync(file: Arc<File>) -> StrResult<()> {
+    __vfs_fsync(file, false)
+}
+
+/// like fsync, but only the metadata needed to read the data is written, e.g. the size
+pub fn vfs_fdatasync(file: Arc<File>) -> StrResult<()> {
+    __vfs_fsync(file, true)
+}
+
+fn __vfs_fsync(file: Arc<File>, datasync: bool) -> StrResult<()> {
     // check file mode
     let mode = file.f_mode;
-    if !mode.contains(FileMode::FMODE_WRITE) {
+    if !mode.contains(OpenMode::FMODE_WRITE) {
         return Err("file not open for writing");
     }
     let fsync = file.f_ops.fsync;
-    fsync(file, true)
+    fsync(file.clone(), datasync)?;
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    write_inode_now(&inode, datasync)?;
+    // the error of an earlier sync write
+    match inode.mapping.take_wb_err() {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
 }
 
-pub fn vfs_mknod<T: ProcessFs>(
-    name: &str,
-    type_: InodeMode,
-    mode: FileMode,
-    dev: u32,
-) -> StrResult<()> {
+/// create a special file
+/// * mode: the file type and the permission bits, a regular file is created if the type is empty
+pub fn vfs_mknod<T: ProcessFs>(name: &str, mode: Mode, dev: u32) -> StrResult<()> {
     ddebug!("vfs_mknod");
     let lookup_data = path_walk::<T>(name, LookUpFlags::NOLAST);
     if lookup_data.is_err() {
@@ -289,8 +707,16 @@ pub fn vfs_mknod<T: ProcessFs>(
     target_dentry.access_inner().d_name = last;
     // 设置父子关系
     target_dentry.access_inner().parent = Arc::downgrade(&dentry);
+    let type_ = match mode.file_type() {
+        t if t.is_empty() => Mode::S_FILE,
+        t => t,
+    };
+    let mode = apply_umask::<T>(type_, mode);
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let mknode = inode.inode_ops.mknod;
-    mknode(inode, target_dentry.clone(), type_, mode, dev)?;
+    mknode(inode.clone(), target_dentry.clone(), mode, dev)?;
+    inode_init_owner::<T>(&inode, &target_dentry.access_inner().d_inode);
     dentry.insert_child(target_dentry);
     Ok(())
 }
@@ -324,17 +750,14 @@ impl From<OpenFlags> for LookUpFlags {
 pub fn open_dentry<T: ProcessFs>(
     name: &str,
     flags: OpenFlags,
-    mode: FileMode,
+    mode: Mode,
 ) -> StrResult<LookUpData> {
     ddebug!("open_dentry");
     debug!("{:?} -> {:?}", flags, Into::<LookUpFlags>::into(flags));
     // TODO 根据路径从缓存中直接查找
     // 只打开文件而不创建
     if !flags.contains(OpenFlags::O_CREAT) {
-        let res = path_walk::<T>(name, flags.into())?;
-        //TODO 检查文件属性是否与参数冲突
-        check_file_flags();
-        return Ok(res);
+        return path_walk::<T>(name, flags.into());
     }
     // 查找文件所在父目录
     let mut lookup_data = path_walk::<T>(name, LookUpFlags::NOLAST)?;
@@ -352,7 +775,7 @@ pub fn open_dentry<T: ProcessFs>(
     debug!("find father over, find child [{}] in dir", last);
     let mut find = find_file_indir(&mut lookup_data, &last).map(|x| x.1);
     // 识别最后一个分量
-    let res = __recognize_last::<T>(&mut find, inode, flags, mode, &mut lookup_data);
+    let res = __recognize_last::<T>(&mut find, inode, flags, mode, &mut lookup_data, &mut 0);
     if res.is_ok() {
         Ok(lookup_data.clone())
     } else {
@@ -363,11 +786,11 @@ fn __recognize_last<T: ProcessFs>(
     find: &mut Result<Arc<DirEntry>, &str>,
     inode: Arc<Inode>,
     flags: OpenFlags,
-    mode: FileMode,
+    mode: Mode,
     lookup_data: &mut LookUpData,
+    count: &mut usize,
 ) -> StrResult<()> {
     ddebug!("__recognize_last");
-    let mut count = 0usize;
     if find.is_err() {
         // 在父目录中创建文件
         // 调用文件系统的回调来创建真实的文件
@@ -375,24 +798,25 @@ fn __recognize_last<T: ProcessFs>(
             "create file in dir {}",
             lookup_data.dentry.access_inner().d_name
         );
+        let sb = lookup_data.mnt.super_block.clone();
+        let _sb_guard = sb.start_write();
         let create_func = inode.inode_ops.create;
         let target_dentry = Arc::new(DirEntry::empty());
         // 设置dentry信息
         target_dentry.access_inner().d_name = lookup_data.last.clone();
         target_dentry.access_inner().parent = Arc::downgrade(&lookup_data.dentry);
+        let mode = apply_umask::<T>(Mode::S_FILE, mode);
         create_func(inode.clone(), target_dentry.clone(), mode)?;
+        inode_init_owner::<T>(&inode, &target_dentry.access_inner().d_inode);
         lookup_data.dentry.insert_child(target_dentry.clone());
 
         lookup_data.dentry = target_dentry;
-        let mut flags = flags;
-        flags -= OpenFlags::O_TRUNC;
-        check_file_flags();
         return Ok(());
     }
     // 文件存在
-    // 如果包含O_EXCL，不能打开文件
+    // 如果包含O_EXCL，不能打开文件，即使是指向不存在文件的符号链接
     if flags.contains(OpenFlags::O_EXCL) {
-        return Err("flags contains O_EXCL");
+        return Err("File exists");
     }
     // 是否挂载了文件系统
     let mut find_dentry = find.as_ref().unwrap().clone();
@@ -404,56 +828,46 @@ fn __recognize_last<T: ProcessFs>(
         lookup_data.dentry = find_dentry.clone();
     }
     // 处理链接文件
-    if find_dentry
-        .access_inner()
-        .d_inode
-        .mode
-        .contains(InodeMode::S_SYMLINK)
-    {
-        __solve_link_file::<T>(flags, mode, inode, lookup_data, &mut count)?;
+    if find_dentry.access_inner().d_inode.mode().is_symlink() {
+        return __solve_link_file::<T>(flags, mode, find_dentry, lookup_data, count);
     }
-    // 文件为目录
-    if find_dentry
-        .access_inner()
-        .d_inode
-        .mode
-        .contains(InodeMode::S_DIR)
-    {
-        return Err("open_DirEntry: file is a directory");
+    // 文件为目录，O_CREAT不能打开目录
+    if find_dentry.access_inner().d_inode.mode().is_dir() {
+        return Err("Is a directory");
     }
-    //TODO
-    check_file_flags();
     // 设置正确结果
     lookup_data.dentry = find_dentry;
     ddebug!("__recognize_last over");
     Ok(())
 }
 
+/// the last component is a symlink, open or create the target of the link
 fn __solve_link_file<T: ProcessFs>(
     flags: OpenFlags,
-    mode: FileMode,
-    inode: Arc<Inode>,
+    mode: Mode,
+    link: Arc<DirEntry>,
     lookup_data: &mut LookUpData,
     count: &mut usize,
 ) -> StrResult<()> {
     if flags.contains(OpenFlags::O_NOFOLLOW) {
-        return Err("open_DirEntry: file is a symbolic link");
+        return Err("Too many levels of symbolic links");
+    }
+    *count += 1;
+    if *count > T::max_link_count() as usize {
+        return Err("Too many levels of symbolic links");
     }
     lookup_data.flags |= LookUpFlags::NOLAST;
-    advance_link::<T>(lookup_data, lookup_data.dentry.clone())?;
+    advance_last_link::<T>(lookup_data, link)?;
     lookup_data.flags -= LookUpFlags::NOLAST;
+    // the file is created in the dir of the target
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
     if lookup_data.path_type != PathType::PATH_NORMAL {
-        return Err("open_DirEntry: file is a directory");
-    }
-    if *count > T::max_link_count() as usize {
-        return Err("open_DirEntry: too many symbolic links");
+        return Err("Is a directory");
     }
     // 前面查找到父目录一级
     // 这里在父目录中查找最后一个文件
     let last = lookup_data.last.clone();
     let mut find = find_file_indir(lookup_data, &last).map(|x| x.1);
     // 识别最后一个分量
-    __recognize_last::<T>(&mut find, inode, flags, mode, lookup_data)
+    __recognize_last::<T>(&mut find, inode, flags, mode, lookup_data, count)
 }
-
-fn check_file_flags() {}
@@ -1,5 +1,8 @@
 use crate::dentry::DirEntry;
+use crate::inode::Mode;
 use crate::mount::VfsMount;
+use crate::StrResult;
+use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::sync::Arc;
 use core::error::Error;
@@ -15,6 +18,8 @@ pub struct ProcessFsInfo {
     pub root_dir: Arc<DirEntry>,
     pub current_dir: Arc<DirEntry>,
     pub current_mount: Arc<VfsMount>,
+    /// the file mode creation mask
+    pub umask: Mode,
 }
 impl ProcessFsInfo {
     pub fn new(
@@ -22,15 +27,43 @@ impl ProcessFsInfo {
         root_dir: Arc<DirEntry>,
         current_dir: Arc<DirEntry>,
         current_mount: Arc<VfsMount>,
+        umask: Mode,
     ) -> ProcessFsInfo {
         ProcessFsInfo {
             root_mount,
             root_dir,
             current_dir,
             current_mount,
+            umask,
         }
     }
 }
+/// The credentials of the process used for permission checks
+#[derive(Default, Debug, Clone, Copy)]
+pub struct ProcessCred {
+    pub uid: u32,
+    pub gid: u32,
+    /// effective user id
+    pub euid: u32,
+    /// effective group id
+    pub egid: u32,
+}
+
+impl ProcessCred {
+    pub fn new(uid: u32, gid: u32, euid: u32, egid: u32) -> ProcessCred {
+        ProcessCred {
+            uid,
+            gid,
+            euid,
+            egid,
+        }
+    }
+    /// the privileged user can override the permission checks
+    pub fn is_privileged(&self) -> bool {
+        self.euid == 0
+    }
+}
+
 pub trait ProcessFs {
     // 调用此函数时进程应该保证数据中间没有被修改
     fn get_fs_info() -> ProcessFsInfo;
@@ -40,6 +73,39 @@ pub trait ProcessFs {
     fn update_link_data();
     fn max_link_count() -> u32;
     fn current_time() -> VfsTime;
+    // 更新进程的文件创建掩码
+    fn update_umask(umask: Mode);
+    // 获取进程的用户凭证
+    fn get_cred() -> ProcessCred;
+    // 创建等待队列，管道的读者与写者、冻结文件系统时的写者与冻结者在其中等待，默认忙等
+    fn new_wait_queue() -> Box<dyn WaitQueue> {
+        Box::new(SpinWaitQueue)
+    }
+    // 向当前进程发送SIGPIPE信号，写入没有读者的管道时调用
+    fn send_sigpipe() {}
+}
+
+/// The wait queue supplied by the kernel, the tasks sleep on it until they are woken up
+pub trait WaitQueue: Send + Sync {
+    /// sleep until `cond` returns true, it is checked again after each `wake_up`
+    ///
+    /// Return Err("Interrupted system call") if the sleep is interrupted by a signal.
+    fn wait_event(&self, cond: &mut dyn FnMut() -> bool) -> StrResult<()>;
+    /// wake up all the tasks sleeping on the queue
+    fn wake_up(&self);
+}
+
+/// the wait queue spinning on the condition, for the kernel without a scheduler
+pub struct SpinWaitQueue;
+
+impl WaitQueue for SpinWaitQueue {
+    fn wait_event(&self, cond: &mut dyn FnMut() -> bool) -> StrResult<()> {
+        while !cond() {
+            core::hint::spin_loop();
+        }
+        Ok(())
+    }
+    fn wake_up(&self) {}
 }
 
 #[derive(Default, Debug, Clone, Copy)]
@@ -1,11 +1,15 @@
-use crate::dentry::{DirEntry, LookUpData};
-use crate::file::{FileMode, FileOps};
-use crate::superblock::{DataOps, Device, StatFs, SuperBlock};
+use crate::dentry::{DirEntry, LookUpData, RenameFlag};
+use crate::file::FileOps;
+use crate::info::ProcessFs;
+use crate::page_cache::AddressSpace;
+use crate::pipe::Pipe;
+use crate::stat::{StatFlags, Statx, StatxMask};
+use crate::superblock::{DataOps, Device, StatFs, SuperBlock, NAME_MAX};
+use crate::writeback::mark_inode_dirty;
 use crate::{ddebug, StrResult};
 use alloc::boxed::Box;
 use alloc::sync::{Arc, Weak};
 use bitflags::bitflags;
-use core::cmp::min;
 use core::fmt::{Debug, Formatter};
 use spin::{Mutex, MutexGuard};
 
@@ -14,8 +18,32 @@ bitflags! {
         const S_DEL = 0x1;
         const S_CACHE = 0x2;
         const S_INVALID = 0x4;
+        /// the file can't be modified
+        const S_IMMUTABLE = 0x8;
+        /// the file can only be appended
+        const S_APPEND = 0x10;
     }
-    pub struct InodeMode:u32{
+    /// the dirty state of an inode
+    pub struct DirtyFlags:u32{
+        /// the metadata not needed by fdatasync is dirty, e.g. the mode and owner
+        const I_DIRTY_SYNC = 0x1;
+        /// the metadata needed by fdatasync is dirty, e.g. the size
+        const I_DIRTY_DATASYNC = 0x2;
+        /// the inode has dirty pages in the page cache
+        const I_DIRTY_PAGES = 0x4;
+    }
+    /// the mode of fallocate, the same value as linux
+    pub struct FallocFlags:u32{
+        /// don't change the file size
+        const FALLOC_FL_KEEP_SIZE = 0x1;
+        /// free the blocks of the range, must be used with FALLOC_FL_KEEP_SIZE
+        const FALLOC_FL_PUNCH_HOLE = 0x2;
+        /// remove the range and move the data after it down
+        const FALLOC_FL_COLLAPSE_RANGE = 0x8;
+        /// zero the range and allocate its blocks
+        const FALLOC_FL_ZERO_RANGE = 0x10;
+    }
+    pub struct Mode:u32{
         const S_SYMLINK = 0o120000;
         const S_DIR = 0o040000;
         const S_FILE = 0o100000;
@@ -23,6 +51,70 @@ bitflags! {
         const S_CHARDEV = 0o020000;
         const S_FIFO = 0o010000;
         const S_SOCK = 0o140000;
+        /// set-user-ID bit
+        const S_ISUID = 0o4000;
+        /// set-group-ID bit
+        const S_ISGID = 0o2000;
+        /// sticky bit
+        const S_ISVTX = 0o1000;
+        const S_IRWXU = 0o700;
+        const S_IRUSR = 0o400;
+        const S_IWUSR = 0o200;
+        const S_IXUSR = 0o100;
+        const S_IRWXG = 0o070;
+        const S_IRGRP = 0o040;
+        const S_IWGRP = 0o020;
+        const S_IXGRP = 0o010;
+        const S_IRWXO = 0o007;
+        const S_IROTH = 0o004;
+        const S_IWOTH = 0o002;
+        const S_IXOTH = 0o001;
+    }
+}
+
+bitflags! {
+    /// the permission to check, F_OK only checks the existence of the file
+    pub struct AccessMode:u32{
+        const F_OK = 0;
+        const X_OK = 1;
+        const W_OK = 2;
+        const R_OK = 4;
+    }
+}
+
+impl Mode {
+    /// mask of the file type bits
+    pub const S_IFMT: u32 = 0o170000;
+    /// mask of the permission bits, including setuid/setgid/sticky
+    pub const S_IALLUGO: u32 = 0o7777;
+    /// the file type part of the mode
+    pub fn file_type(&self) -> Mode {
+        Mode::from_bits_truncate(self.bits() & Self::S_IFMT)
+    }
+    /// the permission part of the mode
+    pub fn permission(&self) -> Mode {
+        Mode::from_bits_truncate(self.bits() & Self::S_IALLUGO)
+    }
+    pub fn is_dir(&self) -> bool {
+        self.file_type() == Mode::S_DIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.file_type() == Mode::S_FILE
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.file_type() == Mode::S_SYMLINK
+    }
+    pub fn is_chr(&self) -> bool {
+        self.file_type() == Mode::S_CHARDEV
+    }
+    pub fn is_blk(&self) -> bool {
+        self.file_type() == Mode::S_BLKDEV
+    }
+    pub fn is_fifo(&self) -> bool {
+        self.file_type() == Mode::S_FIFO
+    }
+    pub fn is_sock(&self) -> bool {
+        self.file_type() == Mode::S_SOCK
     }
 }
 
@@ -40,15 +132,19 @@ pub struct Inode {
     pub blk_dev: Option<Arc<dyn Device>>,
     /// 块大小
     pub blk_size: u32,
-    /// 索引节点模式
-    pub mode: InodeMode,
     /// 超级块引用
     pub super_blk: Weak<SuperBlock>,
+    /// 写锁，保证追加写与文件大小的更新是原子的
+    pub write_lock: Mutex<()>,
+    /// 页缓存
+    pub mapping: AddressSpace,
     pub inner: Mutex<InodeInner>,
 }
 
 #[derive(Debug)]
 pub struct InodeInner {
+    /// 索引节点模式,包含文件类型和权限位
+    pub mode: Mode,
     /// 硬链接数
     pub hard_links: u32,
     /// 状态
@@ -59,6 +155,10 @@ pub struct InodeInner {
     pub gid: u32,
     /// 文件大小
     pub file_size: usize,
+    /// 脏状态
+    pub dirty: DirtyFlags,
+    /// the writeback tick when the inode became dirty
+    pub dirtied_when: u64,
     /// private data
     pub data: Option<Box<dyn DataOps>>,
     pub special_data: Option<SpecialData>,
@@ -66,7 +166,7 @@ pub struct InodeInner {
 
 #[derive(Debug)]
 pub enum SpecialData {
-    PipeData(*const u8),
+    PipeData(Arc<Pipe>),
     CharData(*const u8),
     BlockData(*const u8),
     Socket,
@@ -80,15 +180,19 @@ impl Inode {
             inode_ops: InodeOps::empty(),
             file_ops: FileOps::empty(),
             blk_dev: None,
-            mode: InodeMode::empty(),
             super_blk: Weak::new(),
             blk_size: 0,
+            write_lock: Mutex::new(()),
+            mapping: AddressSpace::empty(),
             inner: Mutex::new(InodeInner {
+                mode: Mode::empty(),
                 hard_links: 0,
                 flags: InodeFlags::empty(),
                 uid: 0,
                 gid: 0,
                 file_size: 0,
+                dirty: DirtyFlags::empty(),
+                dirtied_when: 0,
                 data: None,
                 special_data: None,
             }),
@@ -103,7 +207,7 @@ impl Inode {
         inode_ops: InodeOps,
         file_ops: FileOps,
         blk_dev: Option<Arc<dyn Device>>,
-        mode: InodeMode,
+        mode: Mode,
     ) -> Self {
         Self {
             number,
@@ -112,14 +216,18 @@ impl Inode {
             file_ops,
             blk_dev,
             blk_size: sb_blk.block_size,
-            mode,
             super_blk: Arc::downgrade(&sb_blk),
+            write_lock: Mutex::new(()),
+            mapping: AddressSpace::empty(),
             inner: Mutex::new(InodeInner {
+                mode,
                 hard_links: 0,
                 flags: InodeFlags::S_CACHE,
                 uid: 0,
                 gid: 0,
                 file_size: 0,
+                dirty: DirtyFlags::empty(),
+                dirtied_when: 0,
                 data: None,
                 special_data: None,
             }),
@@ -132,6 +240,10 @@ impl Inode {
     pub fn access_inner(&self) -> MutexGuard<InodeInner> {
         self.inner.lock()
     }
+    /// the file type and permission bits of the inode
+    pub fn mode(&self) -> Mode {
+        self.access_inner().mode
+    }
 }
 
 pub struct InodeOps {
@@ -141,17 +253,14 @@ pub struct InodeOps {
     pub readlink: fn(dentry: Arc<DirEntry>, buf: &mut [u8]) -> StrResult<usize>,
     pub lookup: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()>,
     /// 在某一目录下，为与目录项对象相关的普通文件创建一个新的磁盘索引节点。
-    pub create: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrResult<()>,
+    ///
+    /// the mode contains the file type and the permission bits, the umask has been applied
+    pub create: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()>,
     /// mkdir(dir, dentry, mode)  在某个目录下，为与目录项对应的目录创建一个新的索引节点
-    pub mkdir: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrResult<()>,
+    pub mkdir: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()>,
     pub rmdir: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()>,
-    pub mknod: fn(
-        dir: Arc<Inode>,
-        dentry: Arc<DirEntry>,
-        type_: InodeMode,
-        mode: FileMode,
-        dev: u32,
-    ) -> StrResult<()>,
+    /// create a special file, the file type is in the mode
+    pub mknod: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode, dev: u32) -> StrResult<()>,
     /// 在某个目录下，创建一个硬链接
     pub link:
         fn(old_dentry: Arc<DirEntry>, dir: Arc<Inode>, new_dentry: Arc<DirEntry>) -> StrResult<()>,
@@ -160,16 +269,31 @@ pub struct InodeOps {
     /// 修改索引节点 inode 所指文件的长度。在调用该方法之前，必须将
     /// inode 对象的 i_size 域设置为需要的新长度值
     pub truncate: fn(inode: Arc<Inode>) -> StrResult<()>,
+    /// manipulate the space of [offset, offset + len), the file system updates the
+    /// file_size of the inode
+    pub fallocate:
+        fn(inode: Arc<Inode>, mode: FallocFlags, offset: usize, len: usize) -> StrResult<()>,
+    /// update the attribute filled by vfs with the newest information of the file system,
+    /// the fields it fills should be added to `stx_mask`
+    pub getattr: fn(
+        dentry: Arc<DirEntry>,
+        stat: &mut Statx,
+        mask: StatxMask,
+        flags: StatFlags,
+    ) -> StrResult<()>,
     pub get_attr: fn(dentry: Arc<DirEntry>, key: &str, val: &mut [u8]) -> StrResult<usize>,
     pub set_attr: fn(dentry: Arc<DirEntry>, key: &str, val: &[u8]) -> StrResult<()>,
     pub remove_attr: fn(dentry: Arc<DirEntry>, key: &str) -> StrResult<()>,
     pub list_attr: fn(dentry: Arc<DirEntry>, buf: &mut [u8]) -> StrResult<usize>,
-    pub symlink: fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult<()>,
+    pub symlink:
+        fn(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str, mode: Mode) -> StrResult<()>,
+    /// the file system should reject the flags it can't do atomically
     pub rename: fn(
         old_dir: Arc<Inode>,
         old_dentry: Arc<DirEntry>,
         new_dir: Arc<Inode>,
         new_dentry: Arc<DirEntry>,
+        flags: RenameFlag,
     ) -> StrResult<()>,
 }
 impl Debug for InodeOps {
@@ -187,54 +311,124 @@ impl InodeOps {
             create: |_, _, _| Err("Not support"),
             mkdir: |_, _, _| Err("Not support"),
             rmdir: |_, _| Err("Not support"),
-            mknod: |_, _, _, _, _| Err("Not Support"),
+            mknod: |_, _, _, _| Err("Not Support"),
             link: |_, _, _| Err("Not support"),
             unlink: |_, _| Err("Not support"),
             truncate: |_| Err("Not support"),
+            fallocate: |_, _, _, _| Err("Not support"),
+            getattr: |_, _, _, _| Err("Not support"),
             get_attr: |_, _, _| Err("Not support"),
             set_attr: |_, _, _| Err("Not support"),
             remove_attr: |_, _| Err("Not support"),
             list_attr: |_, _| Err("Not support"),
-            symlink: |_, _, _| Err("Not support"),
-            rename: |_, _, _, _| Err("Not support"),
+            symlink: |_, _, _, _| Err("Not support"),
+            rename: |_, _, _, _, _| Err("Not support"),
         }
     }
 }
 
-impl From<&[u8]> for InodeMode {
+impl From<&[u8]> for Mode {
     fn from(bytes: &[u8]) -> Self {
         match bytes {
-            b"f" => InodeMode::S_FILE,
-            b"d" => InodeMode::S_DIR,
-            b"l" => InodeMode::S_SYMLINK,
-            _ => InodeMode::empty(),
+            b"f" => Mode::S_FILE,
+            b"d" => Mode::S_DIR,
+            b"l" => Mode::S_SYMLINK,
+            _ => Mode::empty(),
         }
     }
 }
 
+/// the statistics every file system can report, the block and inode counts are left zero
 pub fn simple_statfs(sb_blk: Arc<SuperBlock>) -> StrResult<StatFs> {
-    let mut name = [0u8; 32];
-    let fs_type = sb_blk.file_system_type.upgrade().unwrap();
-    let fs_type = fs_type.name.as_bytes();
-    let min = min(fs_type.len(), name.len());
-    name[..min].copy_from_slice(&fs_type[..min]);
     let stat = StatFs {
-        fs_type: sb_blk.magic,
-        block_size: sb_blk.block_size as u64,
-        total_blocks: 0,
-        free_blocks: 0,
-        total_inodes: 0,
-        name_len: 0,
-        name,
+        f_type: sb_blk.magic as u64,
+        f_bsize: sb_blk.block_size as u64,
+        f_fsid: [sb_blk.dev_desc, 0],
+        f_namelen: NAME_MAX,
+        f_frsize: sb_blk.block_size as u64,
+        ..Default::default()
     };
     Ok(stat)
 }
 
+/// set the owner of a new inode created in dir
+///
+/// the new inode takes the gid of a setgid directory and a new subdirectory
+/// inherits the setgid bit, otherwise the gid of the caller is used
+pub fn inode_init_owner<T: ProcessFs>(dir: &Arc<Inode>, inode: &Arc<Inode>) {
+    let cred = T::get_cred();
+    let (dir_mode, dir_gid) = {
+        let dir_inner = dir.access_inner();
+        (dir_inner.mode, dir_inner.gid)
+    };
+    let mut inner = inode.access_inner();
+    inner.uid = cred.euid;
+    if dir_mode.contains(Mode::S_ISGID) {
+        inner.gid = dir_gid;
+        if inner.mode.is_dir() {
+            inner.mode |= Mode::S_ISGID;
+        }
+    } else {
+        inner.gid = cred.egid;
+    }
+}
+
+/// check the permission bits of the inode for the user and group
+///
+/// the privileged user can read and write everything, but it can execute a file
+/// only if one of the execute bits is set
+pub fn generic_permission(inode: &Arc<Inode>, mask: AccessMode, uid: u32, gid: u32) -> bool {
+    let inner = inode.access_inner();
+    let mode = inner.mode;
+    if uid == 0 {
+        if !mask.contains(AccessMode::X_OK) || mode.is_dir() {
+            return true;
+        }
+        return mode.intersects(Mode::S_IXUSR | Mode::S_IXGRP | Mode::S_IXOTH);
+    }
+    let perm = if uid == inner.uid {
+        mode.bits() >> 6
+    } else if gid == inner.gid {
+        mode.bits() >> 3
+    } else {
+        mode.bits()
+    };
+    AccessMode::from_bits_truncate(perm & 0o7).contains(mask)
+}
+
+/// the special bits which should be removed when the file is modified
+///
+/// the setgid bit without group execute permission marks mandatory locking, so it is kept
+pub fn should_remove_suid(mode: Mode) -> Mode {
+    let mut kill = Mode::empty();
+    if mode.contains(Mode::S_ISUID) {
+        kill |= Mode::S_ISUID;
+    }
+    if mode.contains(Mode::S_ISGID | Mode::S_IXGRP) {
+        kill |= Mode::S_ISGID;
+    }
+    kill
+}
+
+/// clear the setuid and setgid bits after the file is written by an unprivileged user
+pub fn file_remove_privs<T: ProcessFs>(inode: &Arc<Inode>) {
+    if T::get_cred().is_privileged() {
+        return;
+    }
+    let mut inner = inode.access_inner();
+    let kill = should_remove_suid(inner.mode);
+    inner.mode -= kill;
+    drop(inner);
+    if !kill.is_empty() {
+        mark_inode_dirty(inode, DirtyFlags::I_DIRTY_SYNC);
+    }
+}
+
 /// create inode from super block
 pub fn create_tmp_inode_from_sb_blk(
     sb_blk: Arc<SuperBlock>,
     number: usize,
-    mode: InodeMode,
+    mode: Mode,
     dev_desc: u32,
     inode_ops: InodeOps,
     file_ops: FileOps,
@@ -252,15 +446,11 @@ pub fn create_tmp_inode_from_sb_blk(
         _ => return Err("create inode failed"),
     };
     // 设置硬链接数
-    match mode {
-        InodeMode::S_DIR => inode.access_inner().hard_links = 2,
-        _ => inode.access_inner().hard_links = 1,
+    if mode.is_dir() {
+        inode.access_inner().hard_links = 2;
+    } else {
+        inode.access_inner().hard_links = 1;
     }
     ddebug!("create_tmp_inode_from_sb_blk end");
     Ok(inode)
 }
-
-// pub fn vfs_mknod(dir: Arc<Inode>, dentry: Arc<DirEntry>, type_: InodeMode, mode: FileMode, dev: u32)->StrResult<()>{
-//     let mknod = dir.inode_ops.mknod;
-//     mknod(dir, dentry, type_, mode, dev)
-// }
@@ -7,17 +7,23 @@
 //! virtual file system framework
 #[macro_use]
 extern crate downcast;
+pub mod buffer_cache;
 pub mod dentry;
 pub mod devfs;
 pub mod file;
 pub mod info;
 pub mod inode;
 pub mod link;
+pub mod mmap;
 pub mod mount;
+pub mod page_cache;
 pub mod path;
+pub mod pipe;
+pub mod poll;
 pub mod ramfs;
 pub mod stat;
 pub mod superblock;
+pub mod writeback;
 
 extern crate alloc;
 extern crate log;
@@ -27,7 +33,8 @@ use crate::ramfs::rootfs::ROOTFS_TYPE;
 use crate::superblock::{register_filesystem, FileSystemType};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
-use info::{ProcessFs, ProcessFsInfo, VfsTime};
+use info::{ProcessCred, ProcessFs, ProcessFsInfo, VfsTime};
+use inode::Mode;
 use lazy_static::lazy_static;
 pub use log::{debug, info, warn};
 use spin::{Mutex, RwLock};
@@ -78,6 +85,10 @@ pub struct ProcessFsContext {
     pub cmnt: Arc<VfsMount>,
     /// 根挂载点
     pub rmnt: Arc<VfsMount>,
+    /// 文件创建掩码
+    pub umask: Mode,
+    /// 用户凭证
+    pub cred: ProcessCred,
 }
 
 lazy_static! {
@@ -86,6 +97,8 @@ lazy_static! {
         root: Arc::new(DirEntry::empty()),
         cmnt: Arc::new(VfsMount::empty()),
         rmnt: Arc::new(VfsMount::empty()),
+        umask: Mode::S_IWGRP | Mode::S_IWOTH,
+        cred: ProcessCred::default(),
     });
 }
 
@@ -99,6 +112,7 @@ impl ProcessFs for FakeFSC {
             lock.root.clone(),
             lock.cwd.clone(),
             lock.cmnt.clone(),
+            lock.umask,
         )
     }
     fn check_nested_link() -> bool {
@@ -106,12 +120,21 @@ impl ProcessFs for FakeFSC {
     }
     fn update_link_data() {}
     fn max_link_count() -> u32 {
-        0
+        // the same as MAXSYMLINKS in linux
+        40
     }
 
     fn current_time() -> VfsTime {
         VfsTime::new(0, 0, 0, 0, 0, 0)
     }
+
+    fn update_umask(umask: Mode) {
+        PROCESS_FS_CONTEXT.lock().umask = umask;
+    }
+
+    fn get_cred() -> ProcessCred {
+        PROCESS_FS_CONTEXT.lock().cred
+    }
 }
 
 #[macro_export]
@@ -1,6 +1,7 @@
-use crate::dentry::{find_file_indir, path_walk, DirEntry, LookUpFlags, PathType};
+use crate::dentry::{find_file_indir, may_delete, path_walk, DirEntry, LookUpFlags, PathType};
 use crate::info::ProcessFs;
-use crate::inode::{InodeFlags, InodeMode};
+use crate::inode::{DirtyFlags, InodeFlags};
+use crate::writeback::mark_inode_dirty;
 use crate::{ddebug, StrResult};
 use alloc::sync::Arc;
 use log::debug;
@@ -15,7 +16,7 @@ pub fn vfs_unlink<T: ProcessFs>(name: &str) -> StrResult<()> {
     if lookup_data.path_type == PathType::PATH_ROOT {
         return Err("Can not delete root directory");
     }
-    if inode.mode != InodeMode::S_DIR {
+    if !inode.mode().is_dir() {
         return Err("It is not a directory");
     }
     // 搜索子目录
@@ -29,17 +30,26 @@ pub fn vfs_unlink<T: ProcessFs>(name: &str) -> StrResult<()> {
     let (_, sub_dentry) = sub_dentry.unwrap();
     // 判断是否是目录
     let sub_inode = sub_dentry.access_inner().d_inode.clone();
-    if sub_inode.mode == InodeMode::S_DIR {
+    if sub_inode.mode().is_dir() {
         return Err("It is a directory");
     }
+    may_delete::<T>(inode.clone(), sub_dentry.clone(), false)?;
     // 调用函数删除文件
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let unlink = inode.inode_ops.unlink;
     unlink(inode.clone(), sub_dentry.clone())?;
-    // mark the inode as deleted
-    sub_dentry.access_inner().d_inode.access_inner().flags = InodeFlags::S_INVALID;
+    // mark the inode as deleted if there is no other hard link
+    let mut sub_inode_inner = sub_inode.access_inner();
+    if sub_inode_inner.hard_links == 0 {
+        sub_inode_inner.flags = InodeFlags::S_INVALID;
+    }
+    drop(sub_inode_inner);
     // remove the dentry from the parent
     dentry.remove_child(&last);
     inode.access_inner().file_size -= 1;
+    mark_inode_dirty(&sub_inode, DirtyFlags::I_DIRTY_SYNC);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
     Ok(())
 }
 
@@ -52,7 +62,7 @@ pub fn vfs_link<T: ProcessFs>(old: &str, new: &str) -> StrResult<()> {
     let old_lookup_data = path_walk::<T>(old, LookUpFlags::READ_LINK)?;
     // whether it is a directory
     let old_inode = old_lookup_data.dentry.access_inner().d_inode.clone();
-    if old_inode.mode == InodeMode::S_DIR {
+    if old_inode.mode().is_dir() {
         return Err("It is a directory");
     }
     // 查找new的inode
@@ -87,6 +97,8 @@ pub fn vfs_link<T: ProcessFs>(old: &str, new: &str) -> StrResult<()> {
 
     let target_dentry = Arc::new(DirEntry::from_lookup_data(&new_lookup_data));
     // 调用函数创建一个链接文件
+    let sb = new_lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let do_link = inode.inode_ops.link;
     do_link(
         old_lookup_data.dentry.clone(),
@@ -95,6 +107,9 @@ pub fn vfs_link<T: ProcessFs>(old: &str, new: &str) -> StrResult<()> {
     )?;
     // 确保文件系统完成功能再加入到缓存中
     dentry.insert_child(target_dentry);
+    // the link count of the file and the entries of the dir are changed
+    mark_inode_dirty(&old_inode, DirtyFlags::I_DIRTY_SYNC);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
     ddebug!("vfs_link: ok");
     Ok(())
 }
@@ -1,6 +1,7 @@
 use crate::dentry::{find_file_indir, path_walk, DirEntry, LookUpFlags, PathType};
+use crate::file::apply_umask;
 use crate::info::ProcessFs;
-use crate::inode::{Inode, InodeMode};
+use crate::inode::{inode_init_owner, Inode, Mode};
 use crate::{ddebug, StrResult};
 use alloc::borrow::ToOwned;
 use alloc::sync::Arc;
@@ -34,17 +35,24 @@ pub fn vfs_symlink<T: ProcessFs>(target: &str, link: &str) -> StrResult<()> {
     let target_dentry = Arc::new(DirEntry::from_lookup_data(&new_lookup_data));
     let dir = new_lookup_data.dentry.access_inner().d_inode.clone();
     let dentry = new_lookup_data.dentry.clone();
-    do_symlink(dir, target_dentry.clone(), target)?;
+    let mode = apply_umask::<T>(
+        Mode::S_SYMLINK,
+        Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO,
+    );
+    let sb = new_lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    do_symlink(dir.clone(), target_dentry.clone(), target, mode)?;
+    inode_init_owner::<T>(&dir, &target_dentry.access_inner().d_inode);
     dentry.insert_child(target_dentry);
     ddebug!("vfs_symlink: end");
     Ok(())
 }
 
-fn do_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult<()> {
+fn do_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str, mode: Mode) -> StrResult<()> {
     ddebug!("do_symlink");
     may_create(dir.clone(), dentry.clone())?;
     let fs_symlink = dir.inode_ops.symlink;
-    fs_symlink(dir, dentry, target)?;
+    fs_symlink(dir, dentry, target, mode)?;
     ddebug!("do_symlink: end");
     Ok(())
 }
@@ -53,10 +61,10 @@ fn do_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult
 #[inline]
 fn may_create(dir: Arc<Inode>, child: Arc<DirEntry>) -> StrResult<()> {
     ddebug!("may_create");
-    if child.access_inner().d_inode.mode != InodeMode::empty() {
+    if child.access_inner().d_inode.mode() != Mode::empty() {
         return Err("The file already exists");
     }
-    if dir.mode != InodeMode::S_DIR {
+    if !dir.mode().is_dir() {
         return Err("It is not a directory");
     }
     // if dir.lock().uid != 0 && dir.lock().uid != child.lock().uid {
@@ -71,9 +79,9 @@ pub fn vfs_readlink<T: ProcessFs>(path: &str, buf: &mut [u8]) -> StrResult<usize
     let lookup_data = path_walk::<T>(path, LookUpFlags::empty())?;
     let dentry = lookup_data.dentry.clone();
     let inode = lookup_data.dentry.access_inner().d_inode.clone();
-    let mode = inode.mode;
+    let mode = inode.mode().file_type();
     let len = match mode {
-        InodeMode::S_SYMLINK => {
+        Mode::S_SYMLINK => {
             let readlink = inode.inode_ops.readlink;
             readlink(dentry, buf)?
         }
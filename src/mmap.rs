@@ -0,0 +1,245 @@
+//! 文件映射
+//!
+//! The kernel maps a file with [vfs_mmap], which checks the access and lets the
+//! filesystem install its [VmOps] in the [VmArea]. The page fault handler of the
+//! kernel then asks [vfs_fault] for the page of the file and maps it:
+//! * `MAP_SHARED`: the page is mapped directly, `page_mkwrite` is called before it
+//!   becomes writable, and the dirty pages are written back by [vfs_msync]/[vfs_munmap]
+//! * `MAP_PRIVATE`: the page is mapped read only, the kernel copies it on the first write
+//!
+//! The pages are aligned to [PAGE_SIZE]. A [VmArea] keeps the frames it faulted in
+//! until it is unmapped, so they stay valid even if the file is truncated.
+use crate::file::{File, OpenFlags, OpenMode};
+use crate::inode::DirtyFlags;
+use crate::mount::MountFlags;
+use crate::page_cache::{find_or_read_page, PageFrame, PAGE_SIZE};
+use crate::writeback::mark_inode_dirty;
+use crate::{ddebug, StrResult};
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use bitflags::bitflags;
+use core::fmt::{Debug, Formatter};
+use spin::Mutex;
+
+bitflags! {
+    /// the protection of a mapping, the same value as linux
+    pub struct ProtFlags: u32 {
+        const PROT_READ = 0x1;
+        const PROT_WRITE = 0x2;
+        const PROT_EXEC = 0x4;
+    }
+}
+
+bitflags! {
+    /// the type of a mapping, the same value as linux
+    pub struct MapFlags: u32 {
+        /// the changes are visible to other mappings and written back to the file
+        const MAP_SHARED = 0x1;
+        /// the changes are private to the mapping
+        const MAP_PRIVATE = 0x2;
+        const MAP_FIXED = 0x10;
+    }
+}
+
+bitflags! {
+    /// the flags of msync, the same value as linux
+    pub struct MsyncFlags: u32 {
+        const MS_ASYNC = 0x1;
+        const MS_INVALIDATE = 0x2;
+        const MS_SYNC = 0x4;
+    }
+}
+
+pub struct VmOps {
+    /// return the page `pgoff` of the file, called by the page fault handler
+    pub fault: fn(file: Arc<File>, pgoff: usize) -> StrResult<Arc<PageFrame>>,
+    /// the page of a shared mapping is about to become writable
+    pub page_mkwrite: fn(file: Arc<File>, pgoff: usize) -> StrResult<()>,
+}
+
+impl Debug for VmOps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("VmOps").finish()
+    }
+}
+
+impl VmOps {
+    pub const fn empty() -> Self {
+        VmOps {
+            fault: |_, _| Err("Not support"),
+            page_mkwrite: |_, _| Ok(()),
+        }
+    }
+}
+
+/// a mapping of the file
+#[derive(Debug)]
+pub struct VmArea {
+    pub vm_start: usize,
+    pub vm_end: usize,
+    /// the offset in the file, in pages
+    pub vm_pgoff: usize,
+    pub vm_prot: ProtFlags,
+    pub vm_flags: MapFlags,
+    pub vm_file: Arc<File>,
+    /// set by `FileOps::mmap`
+    pub vm_ops: VmOps,
+    /// the frames mapped by the kernel, indexed by the offset in the file
+    vm_pages: Mutex<BTreeMap<usize, Arc<PageFrame>>>,
+}
+
+impl VmArea {
+    pub fn is_shared(&self) -> bool {
+        self.vm_flags.contains(MapFlags::MAP_SHARED)
+    }
+}
+
+/// map the file
+/// * start: the address chosen by the kernel, page aligned
+/// * pgoff: the offset in the file, in pages
+pub fn vfs_mmap(
+    file: Arc<File>,
+    start: usize,
+    len: usize,
+    prot: ProtFlags,
+    flags: MapFlags,
+    pgoff: usize,
+) -> StrResult<VmArea> {
+    ddebug!("vfs_mmap");
+    if len == 0 || !start.is_multiple_of(PAGE_SIZE) {
+        return Err("Invalid argument");
+    }
+    let shared = flags.contains(MapFlags::MAP_SHARED);
+    if shared == flags.contains(MapFlags::MAP_PRIVATE) {
+        return Err("Invalid argument");
+    }
+    if !file.f_mode.contains(OpenMode::FMODE_READ) {
+        return Err("Permission denied");
+    }
+    if shared && prot.contains(ProtFlags::PROT_WRITE) {
+        // the shared writes go to the file
+        if !file.f_mode.contains(OpenMode::FMODE_WRITE)
+            || file.access_inner().flags.contains(OpenFlags::O_APPEND)
+        {
+            return Err("Permission denied");
+        }
+    }
+    if prot.contains(ProtFlags::PROT_EXEC) && file.f_mnt.flag.contains(MountFlags::MNT_NO_EXEC) {
+        return Err("Operation not permitted");
+    }
+    let mut vma = VmArea {
+        vm_start: start,
+        vm_end: start + len.div_ceil(PAGE_SIZE) * PAGE_SIZE,
+        vm_pgoff: pgoff,
+        vm_prot: prot,
+        vm_flags: flags,
+        vm_file: file.clone(),
+        vm_ops: VmOps::empty(),
+        vm_pages: Mutex::new(BTreeMap::new()),
+    };
+    let mmap = file.f_ops.mmap;
+    mmap(file, &mut vma).map_err(|e| match e {
+        "Not support" => "No such device",
+        e => e,
+    })?;
+    ddebug!("vfs_mmap end");
+    Ok(vma)
+}
+
+/// handle the page fault at `addr`, return the address of the page to map
+///
+/// For a write to a private mapping, the kernel maps a copy of the page.
+pub fn vfs_fault(vma: &VmArea, addr: usize, write: bool) -> StrResult<usize> {
+    ddebug!("vfs_fault");
+    if addr < vma.vm_start || addr >= vma.vm_end {
+        return Err("Bad address");
+    }
+    if write && !vma.vm_prot.contains(ProtFlags::PROT_WRITE) {
+        return Err("Bad address");
+    }
+    let pgoff = vma.vm_pgoff + (addr - vma.vm_start) / PAGE_SIZE;
+    let fault = vma.vm_ops.fault;
+    let page = fault(vma.vm_file.clone(), pgoff)?;
+    if write && vma.is_shared() {
+        let page_mkwrite = vma.vm_ops.page_mkwrite;
+        page_mkwrite(vma.vm_file.clone(), pgoff)?;
+    }
+    let addr = page.addr();
+    // the frame mapped before is replaced by the kernel
+    vma.vm_pages.lock().insert(pgoff, page);
+    ddebug!("vfs_fault end");
+    Ok(addr)
+}
+
+/// write back the shared mapping
+///
+/// The whole file is written by the fsync of the file.
+pub fn vfs_msync(vma: &VmArea, start: usize, len: usize, flags: MsyncFlags) -> StrResult<()> {
+    ddebug!("vfs_msync");
+    if !start.is_multiple_of(PAGE_SIZE)
+        || flags.contains(MsyncFlags::MS_ASYNC | MsyncFlags::MS_SYNC)
+    {
+        return Err("Invalid argument");
+    }
+    if start < vma.vm_start || start + len > vma.vm_end {
+        return Err("Cannot allocate memory");
+    }
+    if vma.is_shared() && vma.vm_prot.contains(ProtFlags::PROT_WRITE) {
+        let fsync = vma.vm_file.f_ops.fsync;
+        fsync(vma.vm_file.clone(), true)?;
+    }
+    ddebug!("vfs_msync end");
+    Ok(())
+}
+
+/// unmap the file, the dirty pages of a shared mapping are written back
+pub fn vfs_munmap(vma: VmArea) -> StrResult<()> {
+    ddebug!("vfs_munmap");
+    vfs_msync(
+        &vma,
+        vma.vm_start,
+        vma.vm_end - vma.vm_start,
+        MsyncFlags::MS_SYNC,
+    )?;
+    ddebug!("vfs_munmap end");
+    Ok(())
+}
+
+pub const GENERIC_FILE_VM_OPS: VmOps = VmOps {
+    fault: filemap_fault,
+    page_mkwrite: filemap_page_mkwrite,
+};
+
+/// map the file through the page cache
+pub fn generic_file_mmap(_file: Arc<File>, vma: &mut VmArea) -> StrResult<()> {
+    vma.vm_ops = GENERIC_FILE_VM_OPS;
+    Ok(())
+}
+
+/// find the page in the page cache or read it with readpage
+pub fn filemap_fault(file: Arc<File>, pgoff: usize) -> StrResult<Arc<PageFrame>> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let size = inode.access_inner().file_size;
+    if pgoff * PAGE_SIZE >= size {
+        return Err("Bad address");
+    }
+    let mut pages = inode.mapping.access_pages();
+    let page = find_or_read_page(&inode, &mut pages, pgoff, size)?;
+    Ok(page.frame().clone())
+}
+
+/// mark the page dirty, it is written back with the other dirty pages of the inode
+pub fn filemap_page_mkwrite(file: Arc<File>, pgoff: usize) -> StrResult<()> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    // a frozen filesystem blocks the page dirtying
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    inode
+        .mapping
+        .access_pages()
+        .get_mut(&pgoff)
+        .ok_or("Bad address")?
+        .dirty = true;
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_PAGES);
+    Ok(())
+}
@@ -1,7 +1,8 @@
 use crate::dentry::{path_walk, DirEntry, LookUpData, LookUpFlags};
 use crate::info::{ProcessFs, VfsError, VfsResult};
-use crate::inode::{InodeFlags, InodeMode};
+use crate::inode::InodeFlags;
 use crate::superblock::{lookup_filesystem, DataOps, SuperBlock};
+use crate::writeback::vfs_syncfs;
 use crate::{ddebug, StrResult, GLOBAL_HASH_MOUNT};
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
@@ -9,6 +10,7 @@ use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{AtomicUsize, Ordering};
 use log::debug;
 use spin::{Mutex, MutexGuard};
 
@@ -21,8 +23,13 @@ bitflags! {
         const MNT_INTERNAL = 0x10;
     }
 }
+/// 挂载点编号，从1开始分配
+static MNT_ID: AtomicUsize = AtomicUsize::new(1);
+
 /// 挂载点描述符
 pub struct VfsMount {
+    /// 挂载点唯一编号
+    pub mnt_id: usize,
     /// 挂载点标志
     pub flag: MountFlags,
     /// 设备名
@@ -46,6 +53,7 @@ pub struct VfsMountInner {
 impl Debug for VfsMount {
     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("VfsMount")
+            .field("mnt_id", &self.mnt_id)
             .field("flag", &self.flag)
             .field("dev_name", &self.dev_name)
             .field("root", &self.root)
@@ -60,6 +68,7 @@ impl VfsMount {
     #[doc(hidden)]
     pub fn empty() -> Self {
         Self {
+            mnt_id: 0,
             flag: MountFlags::empty(),
             dev_name: String::new(),
             root: Arc::new(DirEntry::empty()),
@@ -80,6 +89,7 @@ impl VfsMount {
         // 设置挂载点所在目录与挂载的文件系统根目录相同
         let dir = super_block.access_inner().root.clone();
         let vfs_mount = VfsMount {
+            mnt_id: MNT_ID.fetch_add(1, Ordering::SeqCst),
             flag: mnt_flags,
             dev_name: dev_name.to_string(),
             root: dir.clone(),
@@ -143,6 +153,9 @@ pub fn do_mount<T: ProcessFs>(
     if flags.contains(MountFlags::MNT_NO_EXEC) {
         mnt_flags |= MountFlags::MNT_NO_EXEC;
     }
+    if flags.contains(MountFlags::MNT_READ_ONLY) {
+        mnt_flags |= MountFlags::MNT_READ_ONLY;
+    }
     flags -= MountFlags::MNT_NOSUID & MountFlags::MNT_NO_DEV & MountFlags::MNT_NO_EXEC;
     //  查找找安装点的 dentry 数据结构
     let ret = path_walk::<T>(dir_name, LookUpFlags::READ_LINK);
@@ -234,13 +247,7 @@ fn check_and_graft_tree(new_mount: Arc<VfsMount>, look: &LookUpData) -> StrResul
     //     return Err("fs exist");
     // }
     // 或者该安装点是一个符号链接，则释放读写信号量并返回错误
-    if new_mount
-        .root
-        .access_inner()
-        .d_inode
-        .mode
-        .contains(InodeMode::S_SYMLINK)
-    {
+    if new_mount.root.access_inner().d_inode.mode().is_symlink() {
         return Err("mnt is symlink");
     }
     graft_tree(new_mount.clone(), look)?;
@@ -254,18 +261,8 @@ fn graft_tree(new_mount: Arc<VfsMount>, look: &LookUpData) -> StrResult<()> {
     ddebug!("graft_tree");
     // mount点应该是目录
     // 被mount的对象也应当(根)目录
-    if !look
-        .dentry
-        .access_inner()
-        .d_inode
-        .mode
-        .contains(InodeMode::S_DIR)
-        || !new_mount
-            .root
-            .access_inner()
-            .d_inode
-            .mode
-            .contains(InodeMode::S_DIR)
+    if !look.dentry.access_inner().d_inode.mode().is_dir()
+        || !new_mount.root.access_inner().d_inode.mode().is_dir()
     {
         return Err("not dir");
     }
@@ -312,6 +309,8 @@ fn graft_tree(new_mount: Arc<VfsMount>, look: &LookUpData) -> StrResult<()> {
 /// 将超级块信息写回磁盘。
 /// TODO do_unmount
 pub fn do_unmount(mount: Arc<VfsMount>, _flags: MountFlags) -> StrResult<()> {
+    // write the dirty data back before the filesystem goes away
+    vfs_syncfs(mount.super_block.clone())?;
     let mut global_mount_lock = GLOBAL_HASH_MOUNT.write();
     // 检查是否有子挂载点
     if !mount.access_inner().child.is_empty() {
@@ -0,0 +1,298 @@
+//! 页缓存
+//!
+//! Every inode has an [AddressSpace] caching its data in pages. A filesystem
+//! plugs [generic_file_read]/[generic_file_write]/[generic_file_fsync] into its
+//! [FileOps](crate::file::FileOps) and provides the [AddressSpaceOps] to move
+//! the pages from/to the backing store.
+use crate::file::File;
+use crate::inode::{DirtyFlags, Inode};
+use crate::writeback::mark_inode_dirty;
+use crate::{ddebug, StrResult};
+use alloc::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use alloc::collections::btree_map::Entry;
+use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
+use core::cmp::min;
+use core::fmt::{Debug, Formatter};
+use core::ptr::NonNull;
+use core::slice;
+use spin::{Mutex, MutexGuard};
+
+pub const PAGE_SIZE: usize = 4096;
+
+/// a page of memory aligned to [PAGE_SIZE], so the kernel can map it
+///
+/// The frame is shared by the page cache and the [VmArea](crate::mmap::VmArea)s
+/// mapping it, it is freed when the last of them drops it.
+pub struct PageFrame {
+    ptr: NonNull<u8>,
+}
+
+impl PageFrame {
+    const LAYOUT: Layout = match Layout::from_size_align(PAGE_SIZE, PAGE_SIZE) {
+        Ok(layout) => layout,
+        Err(_) => panic!("bad page layout"),
+    };
+    /// allocate a page filled with zero
+    pub fn new() -> Arc<Self> {
+        // the layout has a non-zero size
+        let ptr = unsafe { alloc_zeroed(Self::LAYOUT) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| handle_alloc_error(Self::LAYOUT));
+        Arc::new(PageFrame { ptr })
+    }
+    /// the address of the page, mapped by the kernel
+    pub fn addr(&self) -> usize {
+        self.ptr.as_ptr() as usize
+    }
+}
+
+impl Drop for PageFrame {
+    fn drop(&mut self) {
+        // the page is allocated by new with the same layout
+        unsafe { dealloc(self.ptr.as_ptr(), Self::LAYOUT) }
+    }
+}
+
+impl Debug for PageFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PageFrame")
+            .field("addr", &self.addr())
+            .finish()
+    }
+}
+
+unsafe impl Send for PageFrame {}
+unsafe impl Sync for PageFrame {}
+
+/// a cached page of the file
+pub struct Page {
+    frame: Arc<PageFrame>,
+    /// the page is modified and not written back
+    pub dirty: bool,
+}
+
+impl Page {
+    /// a clean page filled with zero
+    pub fn new() -> Self {
+        Page {
+            frame: PageFrame::new(),
+            dirty: false,
+        }
+    }
+    /// the frame of the page, the mappings keep it alive
+    pub fn frame(&self) -> &Arc<PageFrame> {
+        &self.frame
+    }
+    pub fn data(&self) -> &[u8] {
+        // the page is accessed with the pages of the inode locked,
+        // the mappings only touch it through the address given to the kernel
+        unsafe { slice::from_raw_parts(self.frame.ptr.as_ptr(), PAGE_SIZE) }
+    }
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        // the same as data
+        unsafe { slice::from_raw_parts_mut(self.frame.ptr.as_ptr(), PAGE_SIZE) }
+    }
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Page {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Page").field("dirty", &self.dirty).finish()
+    }
+}
+
+pub struct AddressSpaceOps {
+    /// read the page `index` of the file, the buf is always [PAGE_SIZE] bytes
+    pub readpage: fn(inode: Arc<Inode>, index: usize, buf: &mut [u8]) -> StrResult<()>,
+    /// write the page `index` back, the buf doesn't go beyond the end of file
+    pub writepage: fn(inode: Arc<Inode>, index: usize, buf: &[u8]) -> StrResult<()>,
+    /// prepare for writing `len` bytes at `pos`, e.g. allocate the blocks
+    pub write_begin: fn(inode: Arc<Inode>, pos: usize, len: usize) -> StrResult<()>,
+    /// called after `copied` bytes have been copied to the pages
+    pub write_end: fn(inode: Arc<Inode>, pos: usize, copied: usize) -> StrResult<()>,
+}
+
+impl Debug for AddressSpaceOps {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("AddressSpaceOps").finish()
+    }
+}
+
+impl AddressSpaceOps {
+    pub const fn empty() -> Self {
+        AddressSpaceOps {
+            readpage: |_, _, _| Err("Not support"),
+            writepage: |_, _, _| Err("Not support"),
+            write_begin: |_, _, _| Ok(()),
+            write_end: |_, _, _| Ok(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct AddressSpace {
+    pub a_ops: AddressSpaceOps,
+    pages: Mutex<BTreeMap<usize, Page>>,
+    /// the error of a write back that was not reported, the next fsync reports it
+    wb_err: Mutex<Option<&'static str>>,
+}
+
+impl AddressSpace {
+    pub const fn empty() -> Self {
+        AddressSpace {
+            a_ops: AddressSpaceOps::empty(),
+            pages: Mutex::new(BTreeMap::new()),
+            wb_err: Mutex::new(None),
+        }
+    }
+    pub fn access_pages(&self) -> MutexGuard<'_, BTreeMap<usize, Page>> {
+        self.pages.lock()
+    }
+    /// the number of cached pages
+    pub fn nr_pages(&self) -> usize {
+        self.pages.lock().len()
+    }
+    /// the number of dirty pages
+    pub fn nr_dirty(&self) -> usize {
+        self.pages.lock().values().filter(|p| p.dirty).count()
+    }
+    /// keep the error of a write back for the next fsync
+    pub fn set_wb_err(&self, err: &'static str) {
+        *self.wb_err.lock() = Some(err);
+    }
+    /// the kept error of the write back, it is reported only once
+    pub fn take_wb_err(&self) -> Option<&'static str> {
+        self.wb_err.lock().take()
+    }
+}
+
+/// find the page in the cache, or read it from the backing store
+///
+/// The page beyond the end of file is filled with zero without reading.
+pub(crate) fn find_or_read_page<'a>(
+    inode: &Arc<Inode>,
+    pages: &'a mut BTreeMap<usize, Page>,
+    index: usize,
+    size: usize,
+) -> StrResult<&'a mut Page> {
+    match pages.entry(index) {
+        Entry::Occupied(page) => Ok(page.into_mut()),
+        Entry::Vacant(entry) => {
+            let mut page = Page::new();
+            let start = index * PAGE_SIZE;
+            if start < size {
+                let readpage = inode.mapping.a_ops.readpage;
+                readpage(inode.clone(), index, page.data_mut())?;
+                // the data beyond the end of file is not valid
+                let valid = min(size - start, PAGE_SIZE);
+                page.data_mut()[valid..].fill(0);
+            }
+            Ok(entry.insert(page))
+        }
+    }
+}
+
+/// read file through the page cache
+pub fn generic_file_read(file: Arc<File>, buf: &mut [u8], offset: u64) -> StrResult<usize> {
+    ddebug!("generic_file_read");
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let size = inode.access_inner().file_size;
+    let offset = offset as usize;
+    if offset >= size {
+        return Ok(0);
+    }
+    let len = min(buf.len(), size - offset);
+    let mut pages = inode.mapping.access_pages();
+    let mut copied = 0;
+    while copied < len {
+        let pos = offset + copied;
+        let (index, in_page) = (pos / PAGE_SIZE, pos % PAGE_SIZE);
+        let count = min(PAGE_SIZE - in_page, len - copied);
+        let page = find_or_read_page(&inode, &mut pages, index, size)?;
+        buf[copied..copied + count].copy_from_slice(&page.data()[in_page..in_page + count]);
+        copied += count;
+    }
+    ddebug!("generic_file_read end");
+    Ok(len)
+}
+
+/// write file through the page cache, the pages are marked dirty
+///
+/// The caller updates the file size.
+pub fn generic_file_write(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usize> {
+    ddebug!("generic_file_write");
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let size = inode.access_inner().file_size;
+    let offset = offset as usize;
+    let a_ops = &inode.mapping.a_ops;
+    (a_ops.write_begin)(inode.clone(), offset, buf.len())?;
+    {
+        let mut pages = inode.mapping.access_pages();
+        let mut copied = 0;
+        while copied < buf.len() {
+            let pos = offset + copied;
+            let (index, in_page) = (pos / PAGE_SIZE, pos % PAGE_SIZE);
+            let count = min(PAGE_SIZE - in_page, buf.len() - copied);
+            let page = find_or_read_page(&inode, &mut pages, index, size)?;
+            page.data_mut()[in_page..in_page + count].copy_from_slice(&buf[copied..copied + count]);
+            page.dirty = true;
+            copied += count;
+        }
+    }
+    (a_ops.write_end)(inode.clone(), offset, buf.len())?;
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_PAGES);
+    ddebug!("generic_file_write end");
+    Ok(buf.len())
+}
+
+/// write the dirty pages back and flush the device
+pub fn generic_file_fsync(file: Arc<File>, _datasync: bool) -> StrResult<()> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    filemap_write(&inode)?;
+    if let Some(dev) = &inode.blk_dev {
+        dev.flush();
+    }
+    Ok(())
+}
+
+/// write all dirty pages of the inode back with writepage
+pub fn filemap_write(inode: &Arc<Inode>) -> StrResult<()> {
+    ddebug!("filemap_write");
+    let size = inode.access_inner().file_size;
+    let writepage = inode.mapping.a_ops.writepage;
+    let mut pages = inode.mapping.access_pages();
+    for (index, page) in pages.iter_mut().filter(|(_, p)| p.dirty) {
+        let start = index * PAGE_SIZE;
+        let len = min(size.saturating_sub(start), PAGE_SIZE);
+        writepage(inode.clone(), *index, &page.data()[..len])?;
+        page.dirty = false;
+    }
+    ddebug!("filemap_write end");
+    Ok(())
+}
+
+/// drop the pages beyond the new size and zero the tail of the last page,
+/// the dirty data in them is discarded
+///
+/// The frames still mapped by a [VmArea](crate::mmap::VmArea) are freed when it is unmapped.
+///
+/// The zeroed tail is dirty, so the stale data on the backing store is overwritten
+/// before the file grows again.
+pub fn truncate_inode_pages(inode: &Arc<Inode>, size: usize) {
+    let mut pages = inode.mapping.access_pages();
+    let first = size.div_ceil(PAGE_SIZE);
+    pages.retain(|index, _| *index < first);
+    if !size.is_multiple_of(PAGE_SIZE) {
+        if let Some(page) = pages.get_mut(&(size / PAGE_SIZE)) {
+            page.data_mut()[size % PAGE_SIZE..].fill(0);
+            page.dirty = true;
+            drop(pages);
+            mark_inode_dirty(inode, DirtyFlags::I_DIRTY_PAGES);
+        }
+    }
+}
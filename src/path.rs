@@ -1,10 +1,12 @@
-use crate::dentry::{DirEntry, LookUpFlags};
-use crate::inode::InodeMode;
+use crate::dentry::{path_walk, DirEntry, LookUpFlags};
+use crate::info::ProcessFs;
+use crate::inode::InodeFlags;
 use crate::mount::VfsMount;
-use crate::StrResult;
+use crate::{ddebug, StrResult};
 use alloc::collections::VecDeque;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
+use alloc::vec::Vec;
 
 /// find the full path of the dentry
 pub fn vfs_lookup_path(
@@ -17,7 +19,7 @@ pub fn vfs_lookup_path(
     let mut res = VecDeque::new();
     let mut current = dentry;
     let mut mnt = mnt;
-    let is_dir = current.access_inner().d_inode.mode == InodeMode::S_DIR;
+    let is_dir = current.access_inner().d_inode.mode().is_dir();
     // /f1/f2
     loop {
         let inner = current.access_inner();
@@ -65,16 +67,131 @@ pub fn vfs_lookup_path(
         acc.push_str(x);
         acc
     });
-    // the path is relative
-    assert!(path.is_relative());
-
-    let res = stitching_path(f_path, path.path());
+    // an absolute path does not depend on the dentry
+    let res = match path {
+        ParsePathType::Relative(p) => stitching_path(f_path, p),
+        ParsePathType::Absolute(p) => {
+            stitching_path("/".to_string(), p.trim_start_matches('/').to_string())
+        }
+    };
     if res.is_none() {
         return Err("path error");
     }
     Ok(res.unwrap())
 }
 
+/// get the absolute path of the dentry in the mount
+///
+/// The path is built relative to the root of the process. If the dentry has been
+/// deleted or it can't be reached from the root of the process, the path ends with " (deleted)".
+pub fn vfs_d_path<T: ProcessFs>(mnt: Arc<VfsMount>, dentry: Arc<DirEntry>) -> StrResult<String> {
+    ddebug!("vfs_d_path");
+    let (mut path, deleted) = __d_path::<T>(mnt, dentry);
+    if deleted {
+        path.push_str(" (deleted)");
+    }
+    ddebug!("vfs_d_path end");
+    Ok(path)
+}
+
+/// get the current working directory of the process
+pub fn vfs_getcwd<T: ProcessFs>() -> StrResult<String> {
+    ddebug!("vfs_getcwd");
+    let fs_info = T::get_fs_info();
+    let (path, deleted) = __d_path::<T>(fs_info.current_mount, fs_info.current_dir);
+    if deleted {
+        return Err("current directory has been deleted");
+    }
+    ddebug!("vfs_getcwd end");
+    Ok(path)
+}
+
+/// get the canonical absolute path of the file
+///
+/// All symbolic links, "." and ".." in the path are resolved, and every component must exist.
+pub fn vfs_realpath<T: ProcessFs>(path: &str) -> StrResult<String> {
+    ddebug!("vfs_realpath");
+    let lookup_data = path_walk::<T>(path, LookUpFlags::READ_LINK)?;
+    let (path, _) = __d_path::<T>(lookup_data.mnt, lookup_data.dentry);
+    ddebug!("vfs_realpath end");
+    Ok(path)
+}
+
+/// walk up from the dentry to the root of the process, crossing the mount points
+///
+/// return the path and whether the dentry is deleted or unreachable
+fn __d_path<T: ProcessFs>(mnt: Arc<VfsMount>, dentry: Arc<DirEntry>) -> (String, bool) {
+    let fs_info = T::get_fs_info();
+    let mut deleted = is_deleted(&dentry);
+    let mut names = Vec::new();
+    let mut mnt = mnt;
+    let mut current = dentry;
+    loop {
+        if Arc::ptr_eq(&current, &fs_info.root_dir) && Arc::ptr_eq(&mnt, &fs_info.root_mount) {
+            break;
+        }
+        if Arc::ptr_eq(&current, &mnt.root) {
+            // the root of the mount, we should go to the mount point in the parent mount
+            let parent_mnt = mnt.access_inner().parent.upgrade();
+            match parent_mnt {
+                Some(parent_mnt) if !Arc::ptr_eq(&parent_mnt, &mnt) => {
+                    current = mnt.access_inner().mount_point.clone();
+                    mnt = parent_mnt;
+                    continue;
+                }
+                // we reach the top mount but not the root of the process
+                _ => {
+                    deleted = true;
+                    break;
+                }
+            }
+        }
+        let parent = current.access_inner().parent.upgrade();
+        match parent {
+            Some(parent) if !Arc::ptr_eq(&parent, &current) => {
+                names.push(current.access_inner().d_name.clone());
+                current = parent;
+            }
+            // the dentry has been detached from the tree
+            _ => {
+                deleted = true;
+                break;
+            }
+        }
+    }
+    let path = if names.is_empty() {
+        "/".to_string()
+    } else {
+        names.iter().rev().fold(String::new(), |mut acc, x| {
+            acc.push('/');
+            acc.push_str(x);
+            acc
+        })
+    };
+    (path, deleted)
+}
+
+/// whether the dentry has been removed from its parent
+fn is_deleted(dentry: &Arc<DirEntry>) -> bool {
+    let inode = dentry.access_inner().d_inode.clone();
+    if inode
+        .access_inner()
+        .flags
+        .intersects(InodeFlags::S_DEL | InodeFlags::S_INVALID)
+    {
+        return true;
+    }
+    let parent = dentry.access_inner().parent.upgrade();
+    match parent {
+        Some(parent) if !Arc::ptr_eq(&parent, dentry) => !parent
+            .access_inner()
+            .children
+            .iter()
+            .any(|x| Arc::ptr_eq(x, dentry)),
+        _ => false,
+    }
+}
+
 /// we try to stitching path
 /// # Example
 /// * /bin/mytool/ + ../t1 == /bin/mytool/../t1 == /bin/t1
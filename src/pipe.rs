@@ -0,0 +1,912 @@
+//! 管道
+//!
+//! [vfs_pipe2] creates a pipe on the pipefs, the data of the pipe is kept in a ring of
+//! page buffers. The readers and writers sleep on the [WaitQueue] supplied by the kernel.
+//! A FIFO created by mknod on any filesystem is opened as a pipe kept in its inode.
+//! [vfs_splice] and [vfs_tee] move or share the buffers between the pipes without copying
+//! the data, the data of a file is copied once between the file and the buffers.
+use crate::dentry::{DirEntry, DirEntryOps, DirFlags};
+use crate::file::{
+    __vfs_read, __vfs_write, File, FileExtOps, FileOps, OpenFlags, OpenMode, RwfFlags,
+};
+use crate::info::{ProcessFs, WaitQueue};
+use crate::inode::{Inode, InodeOps, Mode, SpecialData};
+use crate::mount::{MountFlags, VfsMount};
+use crate::page_cache::PAGE_SIZE;
+use crate::poll::{PollEvents, PollWaitQueue};
+use crate::superblock::SuperBlock;
+use crate::{ddebug, StrResult};
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::format;
+use alloc::sync::{Arc, Weak};
+use alloc::vec;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use core::cmp::min;
+use core::fmt::{Debug, Formatter};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use lazy_static::lazy_static;
+use spin::{Mutex, MutexGuard};
+
+/// the magic of pipefs, the same value as linux
+pub const PIPEFS_MAGIC: u32 = 0x50495045;
+/// the default number of buffers of a pipe
+pub const PIPE_DEF_BUFFERS: usize = 16;
+/// the writes not larger than it are atomic
+pub const PIPE_BUF: usize = PAGE_SIZE;
+/// the max size of a pipe set by an unprivileged user, like /proc/sys/fs/pipe-max-size
+pub const PIPE_MAX_SIZE: usize = 1024 * 1024;
+
+bitflags! {
+    /// the flags of splice and tee, the same value as linux
+    pub struct SpliceFlags: u32 {
+        /// move the pages instead of copying, a hint
+        const SPLICE_F_MOVE = 0x1;
+        /// don't block on the pipes
+        const SPLICE_F_NONBLOCK = 0x2;
+        /// more data will be spliced, a hint
+        const SPLICE_F_MORE = 0x4;
+        /// the pages are gifted, a hint
+        const SPLICE_F_GIFT = 0x8;
+    }
+}
+
+static PIPE_INODE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+lazy_static! {
+    /// pipefs isn't mounted in the directory tree, the pipes are created on it
+    static ref PIPE_MNT: Arc<VfsMount> = {
+        let sb = Arc::new(SuperBlock {
+            magic: PIPEFS_MAGIC,
+            block_size: PAGE_SIZE as u32,
+            ..SuperBlock::empty()
+        });
+        VfsMount::new("pipe:", sb, Weak::new(), MountFlags::empty())
+    };
+}
+
+/// a page of the pipe, the page is shared by the buffers duplicated by tee
+#[derive(Clone)]
+struct PipeBuffer {
+    page: Arc<Vec<u8>>,
+    offset: usize,
+    len: usize,
+}
+
+impl PipeBuffer {
+    fn new(page: Vec<u8>, len: usize) -> Self {
+        PipeBuffer {
+            page: Arc::new(page),
+            offset: 0,
+            len,
+        }
+    }
+    fn data(&self) -> &[u8] {
+        &self.page[self.offset..self.offset + self.len]
+    }
+    /// split the first `len` bytes into a new buffer sharing the page
+    fn split(&mut self, len: usize) -> PipeBuffer {
+        let mut head = self.clone();
+        head.len = len;
+        self.offset += len;
+        self.len -= len;
+        head
+    }
+}
+
+struct PipeInner {
+    bufs: VecDeque<PipeBuffer>,
+    /// the max number of buffers
+    max_usage: usize,
+    readers: usize,
+    writers: usize,
+    /// the number of readers ever opened, a FIFO writer waits for it to change
+    r_counter: usize,
+    /// the number of writers ever opened, a FIFO reader waits for it to change
+    w_counter: usize,
+    /// a splice is writing the head of the pipe to a file, the other readers wait for it
+    splicing: bool,
+}
+
+impl PipeInner {
+    fn is_full(&self) -> bool {
+        self.bufs.len() >= self.max_usage
+    }
+    /// there is data that can be taken
+    fn is_readable(&self) -> bool {
+        !self.bufs.is_empty() && !self.splicing
+    }
+    /// no data will come any more
+    fn is_eof(&self) -> bool {
+        self.bufs.is_empty() && self.writers == 0
+    }
+    /// the bytes can be written without blocking
+    fn free_space(&self) -> usize {
+        let tail = match self.bufs.back() {
+            // the shared page can't be appended
+            Some(buf) if Arc::strong_count(&buf.page) == 1 => PAGE_SIZE - buf.offset - buf.len,
+            _ => 0,
+        };
+        self.max_usage.saturating_sub(self.bufs.len()) * PAGE_SIZE + tail
+    }
+    /// read the data and free the empty buffers
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut total = 0;
+        while let Some(pipe_buf) = self.bufs.front() {
+            let len = min(pipe_buf.len, buf.len() - total);
+            buf[total..total + len].copy_from_slice(&pipe_buf.data()[..len]);
+            total += len;
+            self.consume(len);
+            if total == buf.len() {
+                break;
+            }
+        }
+        total
+    }
+    /// write as much as the space allows, the last page is appended first
+    fn write(&mut self, buf: &[u8]) -> usize {
+        let mut total = 0;
+        if let Some(last) = self.bufs.back_mut() {
+            let start = last.offset + last.len;
+            if let Some(page) = Arc::get_mut(&mut last.page) {
+                total = min(PAGE_SIZE - start, buf.len());
+                page[start..start + total].copy_from_slice(&buf[..total]);
+                last.len += total;
+            }
+        }
+        while total < buf.len() && !self.is_full() {
+            let len = min(PAGE_SIZE, buf.len() - total);
+            let mut page = vec![0; PAGE_SIZE];
+            page[..len].copy_from_slice(&buf[total..total + len]);
+            self.bufs.push_back(PipeBuffer::new(page, len));
+            total += len;
+        }
+        total
+    }
+    /// drop `len` bytes from the head of the pipe
+    fn consume(&mut self, mut len: usize) {
+        while len > 0 {
+            let buf = self.bufs.front_mut().unwrap();
+            if buf.len > len {
+                buf.offset += len;
+                buf.len -= len;
+                return;
+            }
+            len -= buf.len;
+            self.bufs.pop_front();
+        }
+    }
+    /// take at most `len` bytes from the head, the buffer is split if it is too large
+    fn take(&mut self, len: usize) -> PipeBuffer {
+        let buf = self.bufs.front_mut().unwrap();
+        if buf.len > len {
+            buf.split(len)
+        } else {
+            self.bufs.pop_front().unwrap()
+        }
+    }
+}
+
+pub struct Pipe {
+    inner: Mutex<PipeInner>,
+    /// the readers wait for the data
+    rd_wait: Box<dyn WaitQueue>,
+    /// the writers wait for the space
+    wr_wait: Box<dyn WaitQueue>,
+    /// the pollers of the pipe
+    poll_wait: PollWaitQueue,
+}
+
+impl Debug for Pipe {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("Pipe").finish()
+    }
+}
+
+impl Pipe {
+    /// create a pipe without readers and writers
+    pub fn new<T: ProcessFs>() -> Self {
+        Pipe {
+            inner: Mutex::new(PipeInner {
+                bufs: VecDeque::new(),
+                max_usage: PIPE_DEF_BUFFERS,
+                readers: 0,
+                writers: 0,
+                r_counter: 0,
+                w_counter: 0,
+                splicing: false,
+            }),
+            rd_wait: T::new_wait_queue(),
+            wr_wait: T::new_wait_queue(),
+            poll_wait: PollWaitQueue::new(),
+        }
+    }
+    /// the size of the pipe in bytes
+    pub fn size(&self) -> usize {
+        self.inner.lock().max_usage * PAGE_SIZE
+    }
+    /// the bytes in the pipe
+    pub fn len(&self) -> usize {
+        self.inner.lock().bufs.iter().map(|buf| buf.len).sum()
+    }
+    /// a reader or a writer opens the pipe
+    pub(crate) fn open(&self, mode: OpenMode) {
+        let mut inner = self.inner.lock();
+        if mode.contains(OpenMode::FMODE_READ) {
+            inner.readers += 1;
+            inner.r_counter += 1;
+        }
+        if mode.contains(OpenMode::FMODE_WRITE) {
+            inner.writers += 1;
+            inner.w_counter += 1;
+        }
+        drop(inner);
+        self.wake_up_all();
+    }
+    /// a reader or a writer closes the pipe, the others see EOF or EPIPE
+    ///
+    /// Return true if the pipe has no reader and writer any more.
+    pub(crate) fn release(&self, mode: OpenMode) -> bool {
+        let mut inner = self.inner.lock();
+        if mode.contains(OpenMode::FMODE_READ) {
+            inner.readers -= 1;
+        }
+        if mode.contains(OpenMode::FMODE_WRITE) {
+            inner.writers -= 1;
+        }
+        let unused = inner.readers == 0 && inner.writers == 0;
+        drop(inner);
+        self.wake_up_all();
+        unused
+    }
+    /// a reader or a writer comes or goes
+    fn wake_up_all(&self) {
+        self.rd_wait.wake_up();
+        self.wr_wait.wake_up();
+        self.poll_wait.notify(
+            PollEvents::POLLIN | PollEvents::POLLOUT | PollEvents::POLLERR | PollEvents::POLLHUP,
+        );
+    }
+    /// the data is added
+    fn wake_up_readers(&self) {
+        self.rd_wait.wake_up();
+        self.poll_wait.notify(PollEvents::POLLIN);
+    }
+    /// the space is freed
+    fn wake_up_writers(&self) {
+        self.wr_wait.wake_up();
+        self.poll_wait.notify(PollEvents::POLLOUT);
+    }
+    /// wait until there is data or no writer
+    fn wait_readable(&self) -> StrResult<()> {
+        self.rd_wait.wait_event(&mut || {
+            let inner = self.inner.lock();
+            inner.is_readable() || inner.is_eof()
+        })
+    }
+    /// wait until `need` bytes can be written or there is no reader
+    fn wait_writable(&self, need: usize) -> StrResult<()> {
+        self.wr_wait.wait_event(&mut || {
+            let inner = self.inner.lock();
+            inner.free_space() >= need || inner.readers == 0
+        })
+    }
+    /// the FIFO reader waits until a writer opens the pipe
+    fn wait_for_writer(&self) -> StrResult<()> {
+        let counter = {
+            let inner = self.inner.lock();
+            if inner.writers > 0 {
+                return Ok(());
+            }
+            inner.w_counter
+        };
+        self.rd_wait
+            .wait_event(&mut || self.inner.lock().w_counter != counter)
+    }
+    /// the FIFO writer waits until a reader opens the pipe
+    fn wait_for_reader(&self) -> StrResult<()> {
+        let counter = {
+            let inner = self.inner.lock();
+            if inner.readers > 0 {
+                return Ok(());
+            }
+            inner.r_counter
+        };
+        self.wr_wait
+            .wait_event(&mut || self.inner.lock().r_counter != counter)
+    }
+    /// wait until a buffer can be added or there is no reader
+    fn wait_slot(&self) -> StrResult<()> {
+        self.wr_wait.wait_event(&mut || {
+            let inner = self.inner.lock();
+            !inner.is_full() || inner.readers == 0
+        })
+    }
+}
+
+/// lock two pipes in the order of their address, so the splices in both directions don't deadlock
+fn lock_pipes<'a>(
+    a: &'a Pipe,
+    b: &'a Pipe,
+) -> (MutexGuard<'a, PipeInner>, MutexGuard<'a, PipeInner>) {
+    if (a as *const Pipe) < (b as *const Pipe) {
+        let a = a.inner.lock();
+        (a, b.inner.lock())
+    } else {
+        let b = b.inner.lock();
+        (a.inner.lock(), b)
+    }
+}
+
+pub const PIPE_FILE_OPS: FileOps = {
+    let mut ops = FileOps::empty();
+    ops.llseek = |_, _| Err("Illegal seek");
+    ops.read = pipe_read;
+    ops.write = pipe_write;
+    ops.open = |_| Ok(());
+    ops.release = pipe_release;
+    ops
+};
+
+/// the readiness of the pipe for poll
+pub const PIPE_FILE_EXT_OPS: FileExtOps = FileExtOps {
+    // only the read end is readable and the write end is writable
+    is_ready_read: |file| {
+        file.f_mode.contains(OpenMode::FMODE_READ)
+            && get_pipe_info(&file).is_some_and(|pipe| {
+                let inner = pipe.inner.lock();
+                !inner.bufs.is_empty() || inner.writers == 0
+            })
+    },
+    is_ready_write: |file| {
+        file.f_mode.contains(OpenMode::FMODE_WRITE)
+            && get_pipe_info(&file).is_some_and(|pipe| {
+                let inner = pipe.inner.lock();
+                inner.free_space() >= PIPE_BUF || inner.readers == 0
+            })
+    },
+    // the writer gets an error if there is no reader
+    is_ready_exception: |file| {
+        file.f_mode.contains(OpenMode::FMODE_WRITE)
+            && get_pipe_info(&file).is_some_and(|pipe| pipe.inner.lock().readers == 0)
+    },
+    // the reader is hung up if there is no writer
+    is_hang_up: |file| {
+        file.f_mode.contains(OpenMode::FMODE_READ)
+            && get_pipe_info(&file).is_some_and(|pipe| pipe.inner.lock().writers == 0)
+    },
+    poll_wait: |file, waiter| {
+        if let Some(pipe) = get_pipe_info(&file) {
+            pipe.poll_wait.add(waiter);
+        }
+    },
+    ..FileExtOps::empty()
+};
+
+/// the pipe of the file, None if the file isn't a pipe
+pub fn get_pipe_info(file: &File) -> Option<Arc<Pipe>> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let inner = inode.access_inner();
+    match &inner.special_data {
+        Some(SpecialData::PipeData(pipe)) => Some(pipe.clone()),
+        _ => None,
+    }
+}
+
+fn is_nonblock(file: &File) -> bool {
+    file.access_inner().flags.contains(OpenFlags::O_NONBLOCK)
+}
+
+/// read the data in the pipe, wait if it is empty and there are writers
+fn pipe_read(file: Arc<File>, buf: &mut [u8], _offset: u64) -> StrResult<usize> {
+    let pipe = get_pipe_info(&file).ok_or("Bad file descriptor")?;
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    loop {
+        let mut inner = pipe.inner.lock();
+        if inner.is_readable() {
+            let len = inner.read(buf);
+            drop(inner);
+            pipe.wake_up_writers();
+            return Ok(len);
+        }
+        // all the writers are gone
+        if inner.is_eof() {
+            return Ok(0);
+        }
+        drop(inner);
+        if is_nonblock(&file) {
+            return Err("Resource temporarily unavailable");
+        }
+        pipe.wait_readable()?;
+    }
+}
+
+/// write the data to the pipe, wait if it is full
+///
+/// The write not larger than [PIPE_BUF] is atomic. "Broken pipe" is returned
+/// if there is no reader, the vfs sends SIGPIPE for it.
+fn pipe_write(file: Arc<File>, buf: &[u8], _offset: u64) -> StrResult<usize> {
+    let pipe = get_pipe_info(&file).ok_or("Bad file descriptor")?;
+    if buf.is_empty() {
+        return Ok(0);
+    }
+    let need = if buf.len() <= PIPE_BUF { buf.len() } else { 1 };
+    let mut total = 0;
+    loop {
+        let mut inner = pipe.inner.lock();
+        if inner.readers == 0 {
+            return match total {
+                0 => Err("Broken pipe"),
+                _ => Ok(total),
+            };
+        }
+        let len = match inner.free_space() >= need {
+            true => inner.write(&buf[total..]),
+            false => 0,
+        };
+        drop(inner);
+        if len > 0 {
+            total += len;
+            pipe.wake_up_readers();
+        }
+        if total == buf.len() {
+            return Ok(total);
+        }
+        if is_nonblock(&file) {
+            return match total {
+                0 => Err("Resource temporarily unavailable"),
+                _ => Ok(total),
+            };
+        }
+        match pipe.wait_writable(need) {
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => return Ok(total),
+            Ok(()) => {}
+        }
+    }
+}
+
+fn pipe_release(file: Arc<File>) -> StrResult<()> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    pipe_put(&inode, file.f_mode)
+}
+
+/// drop a reader or a writer of the pipe, the pipe of a FIFO is freed with its data
+/// when the last one is gone
+fn pipe_put(inode: &Inode, mode: OpenMode) -> StrResult<()> {
+    // the inode is locked so fifo_open doesn't get the freed pipe
+    let mut inner = inode.access_inner();
+    let pipe = match &inner.special_data {
+        Some(SpecialData::PipeData(pipe)) => pipe.clone(),
+        _ => return Err("Bad file descriptor"),
+    };
+    if pipe.release(mode) {
+        inner.special_data = None;
+    }
+    Ok(())
+}
+
+/// open a FIFO as a pipe, the pipe is created by the first opener
+///
+/// The reader waits for a writer and the writer waits for a reader. With O_NONBLOCK
+/// the reader doesn't wait, and the writer fails with "No such device or address"
+/// if there is no reader. O_RDWR never waits.
+pub(crate) fn fifo_open<T: ProcessFs>(file: &Arc<File>) -> StrResult<()> {
+    ddebug!("fifo_open");
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let mode = file.f_mode & (OpenMode::FMODE_READ | OpenMode::FMODE_WRITE);
+    let nonblock = is_nonblock(file);
+    let pipe = {
+        let mut inner = inode.access_inner();
+        let pipe = match &inner.special_data {
+            Some(SpecialData::PipeData(pipe)) => pipe.clone(),
+            _ => Arc::new(Pipe::new::<T>()),
+        };
+        if mode == OpenMode::FMODE_WRITE && nonblock && pipe.inner.lock().readers == 0 {
+            return Err("No such device or address");
+        }
+        pipe.open(mode);
+        inner.special_data = Some(SpecialData::PipeData(pipe.clone()));
+        pipe
+    };
+    file.access_inner().f_ops_ext = PIPE_FILE_EXT_OPS;
+    let res = match mode {
+        OpenMode::FMODE_READ if !nonblock => pipe.wait_for_writer(),
+        OpenMode::FMODE_WRITE => pipe.wait_for_reader(),
+        _ => Ok(()),
+    };
+    if let Err(e) = res {
+        pipe_put(&inode, mode)?;
+        return Err(e);
+    }
+    ddebug!("fifo_open end");
+    Ok(())
+}
+
+/// create a pipe, return the read end and the write end
+/// * flags: O_NONBLOCK and O_CLOSEEXEC
+pub fn vfs_pipe2<T: ProcessFs>(flags: OpenFlags) -> StrResult<(Arc<File>, Arc<File>)> {
+    ddebug!("vfs_pipe2");
+    if !(flags - (OpenFlags::O_NONBLOCK | OpenFlags::O_CLOSEEXEC)).is_empty() {
+        return Err("Invalid argument");
+    }
+    let mnt = PIPE_MNT.clone();
+    let number = PIPE_INODE_COUNT.fetch_add(1, Ordering::SeqCst);
+    let inode = Inode::new(
+        mnt.super_block.clone(),
+        number,
+        0,
+        InodeOps::empty(),
+        PIPE_FILE_OPS,
+        None,
+        Mode::S_FIFO | Mode::from_bits_truncate(0o600),
+    );
+    let pipe = Arc::new(Pipe::new::<T>());
+    let cred = T::get_cred();
+    let mut inner = inode.access_inner();
+    inner.hard_links = 1;
+    inner.uid = cred.euid;
+    inner.gid = cred.egid;
+    inner.special_data = Some(SpecialData::PipeData(pipe.clone()));
+    drop(inner);
+    #[allow(clippy::arc_with_non_send_sync)]
+    let inode = Arc::new(inode);
+    let dentry = Arc::new(DirEntry::new(
+        DirFlags::empty(),
+        inode,
+        DirEntryOps::empty(),
+        Weak::new(),
+        &format!("pipe:[{}]", number),
+    ));
+    let new_file = |flags: OpenFlags, mode: OpenMode| {
+        pipe.open(mode);
+        let file = File::new(dentry.clone(), mnt.clone(), flags, mode, PIPE_FILE_OPS);
+        file.access_inner().f_ops_ext = PIPE_FILE_EXT_OPS;
+        Arc::new(file)
+    };
+    let read_end = new_file(flags | OpenFlags::O_RDONLY, OpenMode::FMODE_READ);
+    let write_end = new_file(flags | OpenFlags::O_WRONLY, OpenMode::FMODE_WRITE);
+    ddebug!("vfs_pipe2 end");
+    Ok((read_end, write_end))
+}
+
+/// the size of the pipe in bytes (F_GETPIPE_SZ)
+pub fn vfs_pipe_get_size(file: Arc<File>) -> StrResult<usize> {
+    let pipe = get_pipe_info(&file).ok_or("Bad file descriptor")?;
+    Ok(pipe.size())
+}
+
+/// change the size of the pipe (F_SETPIPE_SZ), return the new size
+///
+/// The size is rounded up to a power of two pages. Only the privileged user can
+/// make it larger than [PIPE_MAX_SIZE], and it can't be smaller than the data in the pipe.
+pub fn vfs_pipe_set_size<T: ProcessFs>(file: Arc<File>, size: usize) -> StrResult<usize> {
+    let pipe = get_pipe_info(&file).ok_or("Bad file descriptor")?;
+    if size > 1 << 31 {
+        return Err("Invalid argument");
+    }
+    let nr_bufs = size.div_ceil(PAGE_SIZE).max(1).next_power_of_two();
+    let size = nr_bufs * PAGE_SIZE;
+    if size > PIPE_MAX_SIZE && !T::get_cred().is_privileged() {
+        return Err("Operation not permitted");
+    }
+    let mut inner = pipe.inner.lock();
+    if inner.bufs.len() > nr_bufs {
+        return Err("Device or resource busy");
+    }
+    inner.max_usage = nr_bufs;
+    drop(inner);
+    pipe.wake_up_writers();
+    Ok(size)
+}
+
+/// move the data between a pipe and a file or two pipes, return the bytes moved
+/// * off_in/off_out: the offset of the file, updated after the splice. If it is None,
+///   the current offset of the file is used and advanced. It must be None for a pipe
+///
+/// The buffers are moved between the pipes without copying.
+pub fn vfs_splice<T: ProcessFs>(
+    in_file: Arc<File>,
+    off_in: Option<&mut u64>,
+    out_file: Arc<File>,
+    off_out: Option<&mut u64>,
+    len: usize,
+    flags: SpliceFlags,
+) -> StrResult<usize> {
+    ddebug!("vfs_splice");
+    if !in_file.f_mode.contains(OpenMode::FMODE_READ)
+        || !out_file.f_mode.contains(OpenMode::FMODE_WRITE)
+    {
+        return Err("Bad file descriptor");
+    }
+    let nonblock = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK)
+        || is_nonblock(&in_file)
+        || is_nonblock(&out_file);
+    let res = match (get_pipe_info(&in_file), get_pipe_info(&out_file)) {
+        (Some(ipipe), Some(opipe)) => {
+            if off_in.is_some() || off_out.is_some() {
+                return Err("Illegal seek");
+            }
+            if Arc::ptr_eq(&ipipe, &opipe) {
+                return Err("Invalid argument");
+            }
+            splice_pipe_to_pipe::<T>(&ipipe, &opipe, len, nonblock)
+        }
+        (Some(ipipe), None) => {
+            if off_in.is_some() {
+                return Err("Illegal seek");
+            }
+            if out_file.access_inner().flags.contains(OpenFlags::O_APPEND) {
+                return Err("Invalid argument");
+            }
+            with_offset(&out_file, off_out, |pos| {
+                splice_from_pipe::<T>(&ipipe, &out_file, pos, len, nonblock)
+            })
+        }
+        (None, Some(opipe)) => {
+            if off_out.is_some() {
+                return Err("Illegal seek");
+            }
+            with_offset(&in_file, off_in, |pos| {
+                splice_to_pipe::<T>(&in_file, pos, &opipe, len, nonblock)
+            })
+        }
+        (None, None) => Err("Invalid argument"),
+    };
+    ddebug!("vfs_splice end");
+    res
+}
+
+/// duplicate the data of a pipe to another pipe without consuming it, return the bytes duplicated
+///
+/// The pages are shared by the two pipes.
+pub fn vfs_tee<T: ProcessFs>(
+    in_file: Arc<File>,
+    out_file: Arc<File>,
+    len: usize,
+    flags: SpliceFlags,
+) -> StrResult<usize> {
+    ddebug!("vfs_tee");
+    if !in_file.f_mode.contains(OpenMode::FMODE_READ)
+        || !out_file.f_mode.contains(OpenMode::FMODE_WRITE)
+    {
+        return Err("Bad file descriptor");
+    }
+    let (ipipe, opipe) = match (get_pipe_info(&in_file), get_pipe_info(&out_file)) {
+        (Some(ipipe), Some(opipe)) if !Arc::ptr_eq(&ipipe, &opipe) => (ipipe, opipe),
+        _ => return Err("Invalid argument"),
+    };
+    let nonblock = flags.contains(SpliceFlags::SPLICE_F_NONBLOCK)
+        || is_nonblock(&in_file)
+        || is_nonblock(&out_file);
+    if len == 0 {
+        return Ok(0);
+    }
+    loop {
+        let (ipipe_inner, mut opipe_inner) = lock_pipes(&ipipe, &opipe);
+        if opipe_inner.readers == 0 {
+            drop((ipipe_inner, opipe_inner));
+            T::send_sigpipe();
+            return Err("Broken pipe");
+        }
+        if ipipe_inner.is_eof() {
+            return Ok(0);
+        }
+        if !ipipe_inner.bufs.is_empty() && !opipe_inner.is_full() {
+            let mut total = 0;
+            for buf in ipipe_inner.bufs.iter() {
+                if total == len || opipe_inner.is_full() {
+                    break;
+                }
+                let mut buf = buf.clone();
+                buf.len = min(buf.len, len - total);
+                total += buf.len;
+                opipe_inner.bufs.push_back(buf);
+            }
+            drop((ipipe_inner, opipe_inner));
+            opipe.wake_up_readers();
+            ddebug!("vfs_tee end");
+            return Ok(total);
+        }
+        drop((ipipe_inner, opipe_inner));
+        if nonblock {
+            return Err("Resource temporarily unavailable");
+        }
+        ipipe.wait_readable()?;
+        opipe.wait_slot()?;
+    }
+}
+
+/// use the offset given or the offset of the file, and update it with the bytes moved
+fn with_offset(
+    file: &Arc<File>,
+    offset: Option<&mut u64>,
+    f: impl FnOnce(u64) -> StrResult<usize>,
+) -> StrResult<usize> {
+    match offset {
+        Some(offset) => {
+            let len = f(*offset)?;
+            *offset += len as u64;
+            Ok(len)
+        }
+        None => {
+            let _guard = file.lock_pos();
+            let pos = file.access_inner().f_pos;
+            let len = f(pos as u64)?;
+            file.access_inner().f_pos = pos + len;
+            Ok(len)
+        }
+    }
+}
+
+fn splice_pipe_to_pipe<T: ProcessFs>(
+    ipipe: &Pipe,
+    opipe: &Pipe,
+    len: usize,
+    nonblock: bool,
+) -> StrResult<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    loop {
+        let (mut ipipe_inner, mut opipe_inner) = lock_pipes(ipipe, opipe);
+        if opipe_inner.readers == 0 {
+            drop((ipipe_inner, opipe_inner));
+            T::send_sigpipe();
+            return Err("Broken pipe");
+        }
+        if ipipe_inner.is_eof() {
+            return Ok(0);
+        }
+        if ipipe_inner.is_readable() && !opipe_inner.is_full() {
+            let mut total = 0;
+            while total < len && !ipipe_inner.bufs.is_empty() && !opipe_inner.is_full() {
+                let buf = ipipe_inner.take(len - total);
+                total += buf.len;
+                opipe_inner.bufs.push_back(buf);
+            }
+            drop((ipipe_inner, opipe_inner));
+            ipipe.wake_up_writers();
+            opipe.wake_up_readers();
+            return Ok(total);
+        }
+        drop((ipipe_inner, opipe_inner));
+        if nonblock {
+            return Err("Resource temporarily unavailable");
+        }
+        ipipe.wait_readable()?;
+        opipe.wait_slot()?;
+    }
+}
+
+/// write the data of the pipe to the file
+fn splice_from_pipe<T: ProcessFs>(
+    ipipe: &Pipe,
+    out_file: &Arc<File>,
+    pos: u64,
+    len: usize,
+    nonblock: bool,
+) -> StrResult<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    let bufs = loop {
+        let mut inner = ipipe.inner.lock();
+        if inner.is_readable() {
+            // the buffers are shared with the pipe, it isn't locked during the write
+            let mut rest = len;
+            let bufs = inner
+                .bufs
+                .iter()
+                .map_while(|buf| {
+                    let mut buf = buf.clone();
+                    buf.len = min(buf.len, rest);
+                    rest -= buf.len;
+                    (buf.len > 0).then_some(buf)
+                })
+                .collect::<Vec<_>>();
+            inner.splicing = true;
+            break bufs;
+        }
+        if inner.is_eof() {
+            return Ok(0);
+        }
+        drop(inner);
+        if nonblock {
+            return Err("Resource temporarily unavailable");
+        }
+        ipipe.wait_readable()?;
+    };
+    let data = bufs.iter().map(|buf| buf.data()).collect::<Vec<_>>();
+    let res = __vfs_write::<T>(out_file.clone(), &data, pos, RwfFlags::empty());
+    // only the data written is consumed
+    let mut inner = ipipe.inner.lock();
+    if let Ok((_, written)) = res {
+        inner.consume(written);
+    }
+    inner.splicing = false;
+    drop(inner);
+    // the readers waiting for the splice, no new data for the pollers
+    ipipe.rd_wait.wake_up();
+    let written = res?.1;
+    if written > 0 {
+        ipipe.wake_up_writers();
+    }
+    Ok(written)
+}
+
+/// read the data of the file into the new buffers of the pipe
+fn splice_to_pipe<T: ProcessFs>(
+    in_file: &Arc<File>,
+    pos: u64,
+    opipe: &Pipe,
+    len: usize,
+    nonblock: bool,
+) -> StrResult<usize> {
+    if len == 0 {
+        return Ok(0);
+    }
+    let slots = loop {
+        let inner = opipe.inner.lock();
+        if inner.readers == 0 {
+            drop(inner);
+            T::send_sigpipe();
+            return Err("Broken pipe");
+        }
+        if !inner.is_full() {
+            break inner.max_usage - inner.bufs.len();
+        }
+        drop(inner);
+        if nonblock {
+            return Err("Resource temporarily unavailable");
+        }
+        opipe.wait_slot()?;
+    };
+    // the file is read without the pipe locked
+    let mut bufs = Vec::new();
+    let mut total = 0;
+    while total < len && bufs.len() < slots {
+        let chunk = min(PAGE_SIZE, len - total);
+        let mut page = vec![0; PAGE_SIZE];
+        let read = match __vfs_read(
+            in_file.clone(),
+            &mut [&mut page[..chunk]],
+            pos + total as u64,
+        ) {
+            Ok(read) => read,
+            Err(e) if total == 0 => return Err(e),
+            Err(_) => break,
+        };
+        if read == 0 {
+            break;
+        }
+        bufs.push(PipeBuffer::new(page, read));
+        total += read;
+        if read < chunk {
+            break;
+        }
+    }
+    let mut inner = opipe.inner.lock();
+    if inner.readers == 0 {
+        drop(inner);
+        T::send_sigpipe();
+        return Err("Broken pipe");
+    }
+    // the other writers may have taken the slots, the data left is read again next time
+    let mut pushed = 0;
+    for buf in bufs {
+        if inner.is_full() {
+            break;
+        }
+        pushed += buf.len;
+        inner.bufs.push_back(buf);
+    }
+    drop(inner);
+    if pushed > 0 {
+        opipe.wake_up_readers();
+    }
+    Ok(pushed)
+}
@@ -0,0 +1,319 @@
+//! poll 与 epoll
+//!
+//! [vfs_poll] checks the readiness of a file with its [FileExtOps](crate::file::FileExtOps).
+//! A file whose state can change keeps a [PollWaitQueue], the waiters registered by
+//! `poll_wait` are woken up when the state changes. [Epoll] keeps a set of files and
+//! reports the ready ones, level-triggered by default, or edge-triggered with EPOLLET.
+use crate::file::File;
+use crate::info::{ProcessFs, WaitQueue};
+use crate::{ddebug, StrResult};
+use alloc::boxed::Box;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use spin::Mutex;
+
+bitflags! {
+    /// the events of poll, the same value as linux
+    pub struct PollEvents: u16 {
+        /// there is data to read
+        const POLLIN = 0x1;
+        /// there is urgent data to read
+        const POLLPRI = 0x2;
+        /// writing is possible
+        const POLLOUT = 0x4;
+        /// error condition, always reported
+        const POLLERR = 0x8;
+        /// hang up, always reported
+        const POLLHUP = 0x10;
+        /// the file isn't open
+        const POLLNVAL = 0x20;
+        const POLLRDNORM = 0x40;
+        const POLLRDBAND = 0x80;
+        const POLLWRNORM = 0x100;
+        const POLLWRBAND = 0x200;
+    }
+}
+
+bitflags! {
+    /// the events of epoll, the same value as linux
+    pub struct EpollEvents: u32 {
+        const EPOLLIN = 0x1;
+        const EPOLLPRI = 0x2;
+        const EPOLLOUT = 0x4;
+        const EPOLLERR = 0x8;
+        const EPOLLHUP = 0x10;
+        const EPOLLRDNORM = 0x40;
+        const EPOLLRDBAND = 0x80;
+        const EPOLLWRNORM = 0x100;
+        const EPOLLWRBAND = 0x200;
+        /// report the file only once until it is modified again
+        const EPOLLONESHOT = 1 << 30;
+        /// report the file only when its state changes
+        const EPOLLET = 1 << 31;
+    }
+}
+
+/// the waiter registered on the files by [vfs_poll]
+pub trait PollWaiter: Send + Sync {
+    /// the state of the file is changed, `events` may be ready now
+    fn wake_up(&self, events: PollEvents);
+}
+
+/// the waiters of a file or a device
+///
+/// The waiters are kept as weak references, a waiter is removed when it is dropped.
+pub struct PollWaitQueue {
+    waiters: Mutex<Vec<Weak<dyn PollWaiter>>>,
+}
+
+impl Default for PollWaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PollWaitQueue {
+    pub const fn new() -> Self {
+        PollWaitQueue {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+    /// register a waiter, it is woken up until it is dropped
+    pub fn add(&self, waiter: Arc<dyn PollWaiter>) {
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|w| w.strong_count() > 0);
+        let waiter = Arc::downgrade(&waiter);
+        if !waiters.iter().any(|w| w.ptr_eq(&waiter)) {
+            waiters.push(waiter);
+        }
+    }
+    /// wake up the waiters, the filesystem or the device calls it when the state changes
+    pub fn notify(&self, events: PollEvents) {
+        let waiters = {
+            let mut waiters = self.waiters.lock();
+            waiters.retain(|w| w.strong_count() > 0);
+            waiters.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        // the waiters may poll the file again, the queue isn't locked
+        waiters.iter().for_each(|w| w.wake_up(events));
+    }
+}
+
+/// check the readiness of the file, return the events ready
+/// * events: the events interested, POLLERR and POLLHUP are always reported
+/// * waiter: registered on the file to be woken up when its state changes
+///
+/// The file without `FileExtOps` is always ready for reading and writing.
+pub fn vfs_poll(
+    file: Arc<File>,
+    events: PollEvents,
+    waiter: Option<Arc<dyn PollWaiter>>,
+) -> PollEvents {
+    let ext_ops = file.access_inner().f_ops_ext.clone();
+    if let Some(waiter) = waiter {
+        (ext_ops.poll_wait)(file.clone(), waiter);
+    }
+    let mut revents = PollEvents::empty();
+    if (ext_ops.is_ready_read)(file.clone()) {
+        revents |= PollEvents::POLLIN | PollEvents::POLLRDNORM;
+    }
+    if (ext_ops.is_ready_write)(file.clone()) {
+        revents |= PollEvents::POLLOUT | PollEvents::POLLWRNORM;
+    }
+    if (ext_ops.is_ready_exception)(file.clone()) {
+        revents |= PollEvents::POLLERR;
+    }
+    if (ext_ops.is_hang_up)(file) {
+        revents |= PollEvents::POLLHUP;
+    }
+    revents & (events | PollEvents::POLLERR | PollEvents::POLLHUP)
+}
+
+/// the event of epoll, `data` is returned as it is given
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EpollEvent {
+    pub events: EpollEvents,
+    pub data: u64,
+}
+
+impl EpollEvent {
+    pub fn new(events: EpollEvents, data: u64) -> Self {
+        EpollEvent { events, data }
+    }
+}
+
+/// the operation of [Epoll::ctl]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EpollCtlOp {
+    Add,
+    Del,
+    Mod,
+}
+
+struct EpollItem {
+    /// the epoll doesn't keep the file open, the item is dropped after the file is closed
+    file: Weak<File>,
+    epoll: Weak<Epoll>,
+    inner: Mutex<EpollItemInner>,
+}
+
+struct EpollItemInner {
+    event: EpollEvent,
+    /// the state of the file changed since it was reported, for EPOLLET
+    triggered: bool,
+    /// the EPOLLONESHOT file has been reported
+    disabled: bool,
+}
+
+impl PollWaiter for EpollItem {
+    fn wake_up(&self, events: PollEvents) {
+        // the events not interested don't change the state reported, e.g. POLLOUT of the read end
+        if !events.intersects(self.poll_events() | PollEvents::POLLERR | PollEvents::POLLHUP) {
+            return;
+        }
+        self.inner.lock().triggered = true;
+        if let Some(epoll) = self.epoll.upgrade() {
+            epoll.wait_queue.wake_up();
+        }
+    }
+}
+
+impl EpollItem {
+    /// the poll events interested
+    fn poll_events(&self) -> PollEvents {
+        let events = self.inner.lock().event.events;
+        PollEvents::from_bits_truncate(events.bits() as u16)
+    }
+    /// the event to report, the state of EPOLLET and EPOLLONESHOT is updated
+    fn check(&self) -> Option<EpollEvent> {
+        let file = self.file.upgrade()?;
+        let interest = self.poll_events();
+        let mut inner = self.inner.lock();
+        let edge = inner.event.events.contains(EpollEvents::EPOLLET);
+        if inner.disabled || (edge && !inner.triggered) {
+            return None;
+        }
+        // the state is checked after the flag is cleared, so a change is not missed
+        inner.triggered = false;
+        drop(inner);
+        let revents = vfs_poll(file, interest, None);
+        if revents.is_empty() {
+            return None;
+        }
+        let mut inner = self.inner.lock();
+        if inner.event.events.contains(EpollEvents::EPOLLONESHOT) {
+            inner.disabled = true;
+        }
+        let events = EpollEvents::from_bits_truncate(revents.bits() as u32);
+        Some(EpollEvent::new(events, inner.event.data))
+    }
+}
+
+/// a set of files watched together
+pub struct Epoll {
+    items: Mutex<Vec<Arc<EpollItem>>>,
+    wait_queue: Box<dyn WaitQueue>,
+}
+
+impl Epoll {
+    /// create an empty epoll (epoll_create)
+    pub fn new<T: ProcessFs>() -> Arc<Epoll> {
+        Arc::new(Epoll {
+            items: Mutex::new(Vec::new()),
+            wait_queue: T::new_wait_queue(),
+        })
+    }
+    /// add, modify or delete a file (epoll_ctl), the event is ignored by Del
+    ///
+    /// EPOLLERR and EPOLLHUP are always watched.
+    pub fn ctl(
+        self: &Arc<Self>,
+        op: EpollCtlOp,
+        file: Arc<File>,
+        event: EpollEvent,
+    ) -> StrResult<()> {
+        ddebug!("epoll_ctl");
+        let mut event = event;
+        event.events |= EpollEvents::EPOLLERR | EpollEvents::EPOLLHUP;
+        let mut items = self.items.lock();
+        // the closed files are gone
+        items.retain(|item| item.file.strong_count() > 0);
+        let weak = Arc::downgrade(&file);
+        let index = items
+            .iter()
+            .position(|item| Weak::ptr_eq(&item.file, &weak));
+        match (op, index) {
+            (EpollCtlOp::Add, Some(_)) => return Err("File exists"),
+            (EpollCtlOp::Add, None) => {
+                let item = Arc::new(EpollItem {
+                    file: weak,
+                    epoll: Arc::downgrade(self),
+                    inner: Mutex::new(EpollItemInner {
+                        event,
+                        triggered: true,
+                        disabled: false,
+                    }),
+                });
+                vfs_poll(file, item.poll_events(), Some(item.clone()));
+                items.push(item);
+            }
+            (EpollCtlOp::Mod, Some(index)) => {
+                // the file is checked again like it is just added
+                let mut inner = items[index].inner.lock();
+                inner.event = event;
+                inner.triggered = true;
+                inner.disabled = false;
+            }
+            (EpollCtlOp::Del, Some(index)) => {
+                // the waiter on the file is gone with the item
+                items.remove(index);
+            }
+            (_, None) => return Err("No such file or directory"),
+        }
+        drop(items);
+        self.wait_queue.wake_up();
+        ddebug!("epoll_ctl end");
+        Ok(())
+    }
+    /// fill the events of the ready files (epoll_wait), return the number of events
+    /// * block: wait until a file is ready, otherwise return at once
+    pub fn wait(&self, events: &mut [EpollEvent], block: bool) -> StrResult<usize> {
+        ddebug!("epoll_wait");
+        if events.is_empty() {
+            return Err("Invalid argument");
+        }
+        let mut count = 0;
+        let mut collect = || {
+            let items = {
+                let mut items = self.items.lock();
+                // the closed files are gone
+                items.retain(|item| item.file.strong_count() > 0);
+                items.clone()
+            };
+            for item in items {
+                if count == events.len() {
+                    break;
+                }
+                if let Some(event) = item.check() {
+                    events[count] = event;
+                    count += 1;
+                }
+            }
+            count > 0
+        };
+        if !collect() && block {
+            self.wait_queue.wait_event(&mut collect)?;
+        }
+        ddebug!("epoll_wait end");
+        Ok(count)
+    }
+    /// the number of files watched, the closed files are not counted
+    pub fn len(&self) -> usize {
+        self.items
+            .lock()
+            .iter()
+            .filter(|item| item.file.strong_count() > 0)
+            .count()
+    }
+}
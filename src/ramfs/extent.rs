@@ -0,0 +1,229 @@
+//! 稀疏文件的数据
+//!
+//! The data of a file is kept in extents of whole blocks, the holes between them
+//! are not allocated and read as zero.
+use super::RAM_BLOCK_SIZE;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::{max, min};
+
+const BLOCK: usize = RAM_BLOCK_SIZE as usize;
+
+fn align_down(pos: usize) -> usize {
+    pos / BLOCK * BLOCK
+}
+
+fn align_up(pos: usize) -> usize {
+    pos.div_ceil(BLOCK) * BLOCK
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ExtentData {
+    /// the start offset -> the data, both are multiple of the block size,
+    /// the extents don't overlap
+    extents: BTreeMap<usize, Vec<u8>>,
+    /// the size of the file
+    size: usize,
+}
+
+impl ExtentData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the size of the file
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// the allocated bytes
+    pub fn allocated(&self) -> usize {
+        self.extents.values().map(|v| v.len()).sum()
+    }
+
+    /// the extents overlapping [start, end), in descending order
+    fn overlaps(&self, start: usize, end: usize) -> Vec<usize> {
+        self.extents
+            .range(..end)
+            .rev()
+            .take_while(|(k, v)| *k + v.len() > start)
+            .map(|(k, _)| *k)
+            .collect()
+    }
+
+    /// allocate the blocks of [start, end), the extents overlapping or adjacent to
+    /// the range are merged into one extent
+    fn allocate_blocks(&mut self, start: usize, end: usize) -> (usize, &mut Vec<u8>) {
+        let (start, end) = (align_down(start), align_up(end));
+        let mut keys = self.overlaps(start.saturating_sub(1), end + 1);
+        let new_start = keys.last().map_or(start, |k| min(*k, start));
+        // the lowest extent is extended in place, so the appends don't copy the data
+        let mut data = match keys.last() {
+            Some(&k) if k == new_start => {
+                keys.pop();
+                self.extents.remove(&k).unwrap()
+            }
+            _ => Vec::new(),
+        };
+        let new_end = keys
+            .first()
+            .map_or(end, |k| max(end, k + self.extents[k].len()))
+            .max(new_start + data.len());
+        data.resize(new_end - new_start, 0);
+        for k in keys {
+            let v = self.extents.remove(&k).unwrap();
+            data[k - new_start..k - new_start + v.len()].copy_from_slice(&v);
+        }
+        (new_start, self.extents.entry(new_start).or_insert(data))
+    }
+
+    /// free the blocks of [start, end), both are multiple of the block size
+    fn free_blocks(&mut self, start: usize, end: usize) {
+        for k in self.overlaps(start, end) {
+            let mut v = self.extents.remove(&k).unwrap();
+            if k + v.len() > end {
+                self.extents.insert(end, v[end - k..].to_vec());
+            }
+            if k < start {
+                v.truncate(start - k);
+                self.extents.insert(k, v);
+            }
+        }
+    }
+
+    /// fill the allocated bytes of [start, end) with zero
+    fn zero(&mut self, start: usize, end: usize) {
+        for k in self.overlaps(start, end) {
+            let v = self.extents.get_mut(&k).unwrap();
+            let (s, e) = (max(k, start), min(k + v.len(), end));
+            v[s - k..e - k].fill(0);
+        }
+    }
+
+    /// read the data at offset, return the bytes read
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> usize {
+        if offset >= self.size {
+            return 0;
+        }
+        let len = min(buf.len(), self.size - offset);
+        let end = offset + len;
+        buf[..len].fill(0);
+        for k in self.overlaps(offset, end) {
+            let v = &self.extents[&k];
+            let (s, e) = (max(k, offset), min(k + v.len(), end));
+            buf[s - offset..e - offset].copy_from_slice(&v[s - k..e - k]);
+        }
+        len
+    }
+
+    /// write the data at offset, the file grows if it is written beyond the end
+    pub fn write(&mut self, offset: usize, buf: &[u8]) {
+        if buf.is_empty() {
+            return;
+        }
+        let (k, v) = self.allocate_blocks(offset, offset + buf.len());
+        v[offset - k..offset - k + buf.len()].copy_from_slice(buf);
+        self.size = max(self.size, offset + buf.len());
+    }
+
+    /// change the size of the file, the growth is a hole
+    pub fn set_len(&mut self, size: usize) {
+        if size < self.size {
+            self.free_blocks(align_up(size), usize::MAX);
+            // the tail of the last block is read as zero if the file grows again
+            self.zero(size, align_up(size));
+        }
+        self.size = size;
+    }
+
+    /// allocate the blocks of [offset, offset + len), the data doesn't change
+    pub fn allocate(&mut self, offset: usize, len: usize) {
+        self.allocate_blocks(offset, offset + len);
+    }
+
+    /// free the blocks of [offset, offset + len), the partial blocks are zeroed
+    pub fn punch_hole(&mut self, offset: usize, len: usize) {
+        let end = offset + len;
+        self.zero(offset, end);
+        let (start, stop) = (align_up(offset), align_down(end));
+        if start < stop {
+            self.free_blocks(start, stop);
+        }
+    }
+
+    /// zero the range and allocate its blocks
+    pub fn zero_range(&mut self, offset: usize, len: usize) {
+        self.allocate(offset, len);
+        self.zero(offset, offset + len);
+    }
+
+    /// remove the range and move the data after it down, both are multiple of the block size
+    pub fn collapse(&mut self, offset: usize, len: usize) {
+        let end = offset + len;
+        self.free_blocks(offset, end);
+        let moved = self.extents.split_off(&end);
+        self.extents
+            .extend(moved.into_iter().map(|(k, v)| (k - len, v)));
+        self.size -= len;
+    }
+
+    /// the first data at or after offset, None if there is no data
+    pub fn seek_data(&self, offset: usize) -> Option<usize> {
+        if offset >= self.size {
+            return None;
+        }
+        if let Some((k, v)) = self.extents.range(..=offset).next_back() {
+            if k + v.len() > offset {
+                return Some(offset);
+            }
+        }
+        self.extents
+            .range(offset..)
+            .next()
+            .map(|(k, _)| *k)
+            .filter(|k| *k < self.size)
+    }
+
+    /// the first hole at or after offset, the end of file is a hole
+    pub fn seek_hole(&self, offset: usize) -> Option<usize> {
+        if offset >= self.size {
+            return None;
+        }
+        let mut pos = offset;
+        while let Some((k, v)) = self.extents.range(..=pos).next_back() {
+            if k + v.len() <= pos {
+                break;
+            }
+            pos = k + v.len();
+        }
+        Some(min(pos, self.size))
+    }
+
+    /// the allocated data of [offset, offset + len) before the end of file,
+    /// the offsets are relative to `offset`
+    pub fn segments(&self, offset: usize, len: usize) -> Vec<(usize, Vec<u8>)> {
+        let end = min(offset.saturating_add(len), self.size);
+        if offset >= end {
+            return Vec::new();
+        }
+        let mut segments = self
+            .overlaps(offset, end)
+            .into_iter()
+            .map(|k| {
+                let v = &self.extents[&k];
+                let (s, e) = (max(k, offset), min(k + v.len(), end));
+                (s - offset, v[s - k..e - k].to_vec())
+            })
+            .collect::<Vec<_>>();
+        segments.reverse();
+        segments
+    }
+
+    /// the whole data, for the small file like a symlink
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = vec![0; self.size];
+        self.read(0, &mut data);
+        data
+    }
+}
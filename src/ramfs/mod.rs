@@ -1,17 +1,27 @@
+mod extent;
 pub mod rootfs;
 pub mod tmpfs;
-use crate::dentry::{DirEntry, LookUpData};
-use crate::file::{File, FileMode, FileOps};
-use crate::inode::{create_tmp_inode_from_sb_blk, simple_statfs, Inode, InodeMode, InodeOps};
+use crate::dentry::{DirEntry, DirEntryOps, DirFlags, LookUpData, RenameFlag};
+use crate::file::{File, FileOps, SeekFrom};
+use crate::inode::{
+    create_tmp_inode_from_sb_blk, simple_statfs, FallocFlags, Inode, InodeFlags, InodeOps, Mode,
+};
 use crate::mount::MountFlags;
+use crate::page_cache::{Page, PageFrame, PAGE_SIZE};
+use crate::stat::{Statx, StatxMask};
 use crate::superblock::{
-    find_super_blk, DataOps, FileSystemType, SuperBlock, SuperBlockInner, SuperBlockOps,
+    find_super_blk, free_anon_dev, get_anon_dev, DataOps, FileSystemType, SbWriters, StatFs,
+    SuperBlock, SuperBlockInner, SuperBlockOps,
 };
 use crate::{ddebug, StrResult};
 use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
+use core::cmp::{max, min};
+use core::ops::{Deref, DerefMut, Range};
+use extent::ExtentData;
 use hashbrown::HashMap;
 use log::debug;
 use spin::Mutex;
@@ -20,53 +30,121 @@ use spin::Mutex;
 pub struct RamFsInode {
     // inode number
     number: usize,
-    // may be for normal file
-    data: Vec<u8>,
+    // may be for normal file, the holes are not allocated
+    data: ExtentData,
     // may be for dir to store sub_file
     dentries: HashMap<String, usize>,
-    // type
-    mode: InodeMode,
+    // type and permission bits
+    mode: Mode,
     hard_links: u32,
-    // write/read mod
-    #[allow(unused)]
-    attr: FileMode,
     // extra attribute
     ex_attr: HashMap<String, Vec<u8>>,
 }
 
 impl RamFsInode {
-    pub fn new(mode: InodeMode, attr: FileMode, number: usize) -> Self {
-        let h_link = if mode == InodeMode::S_DIR { 2 } else { 1 };
+    /// create a ram inode, the mode contains the file type and the permission bits
+    pub fn new(mode: Mode, number: usize) -> Self {
+        let h_link = if mode.is_dir() { 2 } else { 1 };
         Self {
             number,
-            data: Vec::new(),
+            data: ExtentData::new(),
             dentries: HashMap::new(),
             mode,
             hard_links: h_link,
-            attr,
             ex_attr: HashMap::new(),
         }
     }
 }
-const RAMFS_SB_OPS: SuperBlockOps = {
-    let mut sb_ops = SuperBlockOps::empty();
-    sb_ops.stat_fs = simple_statfs;
-    sb_ops
-};
-
 const RAM_BLOCK_SIZE: u32 = 4096;
 const RAM_FILE_MAX_SIZE: usize = 4096;
 const RAM_MAGIC: u32 = 0x12345678;
 
+/// the inodes of a memory file system and the blocks used by their data
+pub struct RamFs {
+    inodes: HashMap<usize, RamFsInode>,
+    // the blocks allocated by the data of all inodes
+    blocks: u64,
+}
+
+impl RamFs {
+    pub fn new() -> Self {
+        Self {
+            inodes: HashMap::new(),
+            blocks: 0,
+        }
+    }
+    /// the data of the inode, the blocks allocated or freed are counted when it is dropped
+    fn data_mut(&mut self, number: usize) -> DataMut<'_> {
+        let data = &mut self.inodes.get_mut(&number).unwrap().data;
+        let before = ramfs_blocks(data.allocated());
+        DataMut {
+            data,
+            blocks: &mut self.blocks,
+            before,
+        }
+    }
+    /// remove the inode, the blocks of its data are freed
+    fn remove(&mut self, number: &usize) -> Option<RamFsInode> {
+        let ram_inode = self.inodes.remove(number)?;
+        self.blocks -= ramfs_blocks(ram_inode.data.allocated());
+        Some(ram_inode)
+    }
+}
+
+impl Default for RamFs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Deref for RamFs {
+    type Target = HashMap<usize, RamFsInode>;
+    fn deref(&self) -> &Self::Target {
+        &self.inodes
+    }
+}
+
+impl DerefMut for RamFs {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.inodes
+    }
+}
+
+struct DataMut<'a> {
+    data: &'a mut ExtentData,
+    blocks: &'a mut u64,
+    before: u64,
+}
+
+impl Deref for DataMut<'_> {
+    type Target = ExtentData;
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
+impl DerefMut for DataMut<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.data
+    }
+}
+
+impl Drop for DataMut<'_> {
+    fn drop(&mut self) {
+        *self.blocks = *self.blocks + ramfs_blocks(self.data.allocated()) - self.before;
+    }
+}
+
 /// 创建一个内存文件系统的超级块
 fn create_simple_ram_super_blk(
     fs_type: Arc<FileSystemType>,
     flags: MountFlags,
     dev_name: &str,
     data: Option<Box<dyn DataOps>>,
+    sb_ops: SuperBlockOps,
 ) -> StrResult<Arc<SuperBlock>> {
     let sb_blk = SuperBlock {
-        dev_desc: 0,
+        dev_desc: get_anon_dev(),
         device: None,
         block_size: RAM_BLOCK_SIZE,
         dirty_flag: false,
@@ -74,7 +152,8 @@ fn create_simple_ram_super_blk(
         mount_flag: flags,
         magic: RAM_MAGIC,
         file_system_type: Arc::downgrade(&fs_type),
-        super_block_ops: RAMFS_SB_OPS,
+        super_block_ops: sb_ops,
+        writers: SbWriters::new(),
         inner: Mutex::new(SuperBlockInner::empty()),
         blk_dev_name: dev_name.to_string(),
         data,
@@ -88,6 +167,7 @@ fn ramfs_simple_super_blk(
     flags: MountFlags,
     dev_name: &str,
     data: Option<Box<dyn DataOps>>,
+    sb_ops: SuperBlockOps,
 ) -> StrResult<Arc<SuperBlock>> {
     ddebug!("ramfs_simple_super_blk");
     let find_sb_blk = find_super_blk(fs_type.clone(), None);
@@ -98,20 +178,37 @@ fn ramfs_simple_super_blk(
             // 没有找到旧超级快需要重新分配
             debug!("create new super block for ramfs");
 
-            create_simple_ram_super_blk(fs_type, flags, dev_name, data)?
+            create_simple_ram_super_blk(fs_type, flags, dev_name, data, sb_ops)?
         }
     };
     ddebug!("ramfs_simple_super_blk end");
     Ok(sb_blk)
 }
 
-fn ramfs_kill_super_blk(_super_blk: Arc<SuperBlock>) {}
+fn ramfs_kill_super_blk(super_blk: Arc<SuperBlock>) {
+    free_anon_dev(super_blk.dev_desc);
+}
+
+/// the number of blocks used by len bytes
+fn ramfs_blocks(len: usize) -> u64 {
+    len.div_ceil(RAM_BLOCK_SIZE as usize) as u64
+}
+
+/// 统计内存文件系统的块与inode的使用情况
+fn ramfs_stat_fs(fs: Arc<Mutex<RamFs>>, sb_blk: Arc<SuperBlock>) -> StrResult<StatFs> {
+    let mut stat = simple_statfs(sb_blk)?;
+    // there is no limit of the capacity, only the usage is reported
+    let fs = fs.lock();
+    stat.f_blocks = fs.blocks;
+    stat.f_files = fs.len() as u64;
+    Ok(stat)
+}
 
 /// 创建内存文件系统的根inode
 fn ramfs_create_root_inode(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     sb_blk: Arc<SuperBlock>,
-    mode: InodeMode,
+    mode: Mode,
     inode_ops: InodeOps,
     file_ops: FileOps,
     number: usize,
@@ -119,11 +216,13 @@ fn ramfs_create_root_inode(
     let inode = create_tmp_inode_from_sb_blk(sb_blk, 0, mode, 0, inode_ops, file_ops, None)?;
     // 设置inode的编号
     assert_eq!(number, 0);
-    inode.access_inner().hard_links = 0;
+    // the root dir is linked by "." and ".."
+    inode.access_inner().hard_links = 2;
     // TODO 设置uid/gid
     // 插入根inode
-    let mut ram_inode = RamFsInode::new(mode, FileMode::FMODE_WRITE, 0);
-    ram_inode.hard_links = 0;
+    let mode = mode | Mode::S_IRWXU | Mode::S_IRGRP | Mode::S_IXGRP;
+    let mode = mode | Mode::S_IROTH | Mode::S_IXOTH;
+    let ram_inode = RamFsInode::new(mode, 0);
     fs.lock().insert(0, ram_inode);
     Ok(inode)
 }
@@ -143,10 +242,10 @@ fn ramfs_create_root_dentry(
 }
 
 fn ramfs_create_inode(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     dir: Arc<Inode>,
-    mode: InodeMode,
-    attr: FileMode,
+    mode: Mode,
+    dev: u32,
     number: usize,
     inode_ops: InodeOps,
     file_ops: FileOps,
@@ -154,7 +253,7 @@ fn ramfs_create_inode(
 ) -> StrResult<Arc<Inode>> {
     ddebug!("ramfs_create_inode");
     // 创建raminode
-    let ram_inode = RamFsInode::new(mode, attr, number);
+    let ram_inode = RamFsInode::new(mode, number);
     fs.lock().insert(number, ram_inode.clone());
 
     // 根据ramfs的inode创建inode
@@ -164,7 +263,7 @@ fn ramfs_create_inode(
         sb_blk,
         ram_inode.number,
         ram_inode.mode,
-        0,
+        dev,
         inode_ops,
         file_ops,
         None,
@@ -186,38 +285,40 @@ fn ramfs_create_inode(
 /// 创建内存文件系统的目录并返回目录项
 /// * dir: 父目录的inode
 /// * dentry: 需要填充的目录项
-/// * attr: 目录的属性
+/// * mode: 目录的类型与权限
 fn ramfs_mkdir(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     dir: Arc<Inode>,
     dentry: Arc<DirEntry>,
-    attr: FileMode,
+    mode: Mode,
     number: usize,
     inode_ops: InodeOps,
     file_ops: FileOps,
 ) -> StrResult<()> {
     ddebug!("ramfs_mkdir");
     let inode = ramfs_create_inode(
-        fs,
-        dir,
-        InodeMode::S_DIR,
-        attr,
+        fs.clone(),
+        dir.clone(),
+        mode,
+        0,
         number,
         inode_ops,
         file_ops,
         dentry.access_inner().d_name.clone(),
     )?;
     dentry.access_inner().d_inode = inode;
+    // the ".." of the new dir links to the parent
+    ramfs_add_links(&mut fs.lock(), &dir, 1);
     ddebug!("ramfs_mkdir end");
     Ok(())
 }
 
 /// 创建内存文件系统的文件并返回目录项
 fn ramfs_create(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     dir: Arc<Inode>,
     dentry: Arc<DirEntry>,
-    mode: FileMode,
+    mode: Mode,
     number: usize,
     inode_ops: InodeOps,
     file_ops: FileOps,
@@ -226,8 +327,8 @@ fn ramfs_create(
     let inode = ramfs_create_inode(
         fs,
         dir,
-        InodeMode::S_FILE,
         mode,
+        0,
         number,
         inode_ops,
         file_ops,
@@ -238,8 +339,35 @@ fn ramfs_create(
     Ok(())
 }
 
+/// 创建内存文件系统的特殊文件, 设备号保存在inode中
+fn ramfs_mknod(
+    fs: Arc<Mutex<RamFs>>,
+    dir: Arc<Inode>,
+    dentry: Arc<DirEntry>,
+    mode: Mode,
+    dev: u32,
+    number: usize,
+    inode_ops: InodeOps,
+    file_ops: FileOps,
+) -> StrResult<()> {
+    ddebug!("ramfs_mknod");
+    let inode = ramfs_create_inode(
+        fs,
+        dir,
+        mode,
+        dev,
+        number,
+        inode_ops,
+        file_ops,
+        dentry.access_inner().d_name.clone(),
+    )?;
+    dentry.access_inner().d_inode = inode;
+    ddebug!("ramfs_mknod end");
+    Ok(())
+}
+
 fn ramfs_read_file(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     file: Arc<File>,
     buf: &mut [u8],
     offset: u64,
@@ -248,24 +376,26 @@ fn ramfs_read_file(
     let inode = &dentry.access_inner().d_inode;
     // 获取inode的编号
     let number = inode.number;
+    // the pages are locked before the fs, like the page fault
+    let pages = inode.mapping.access_pages();
     let mut binding = fs.lock();
     let ram_inode = binding.get_mut(&number).unwrap();
-    let read_len = core::cmp::min(
-        buf.len(),
-        ram_inode.data.len().saturating_sub(offset as usize),
-    );
-    unsafe {
-        core::ptr::copy(
-            ram_inode.data.as_ptr().add(offset as usize),
-            buf.as_mut_ptr(),
-            read_len,
-        );
+    let read_len = ram_inode.data.read(offset as usize, buf);
+    // the pages written through a shared mapping are newer than the data
+    let offset = offset as usize;
+    let end = offset + read_len;
+    for (index, page) in pages.range(offset / PAGE_SIZE..end.div_ceil(PAGE_SIZE)) {
+        let start = max(index * PAGE_SIZE, offset);
+        let stop = min((index + 1) * PAGE_SIZE, end);
+        let in_page = start - index * PAGE_SIZE;
+        buf[start - offset..stop - offset]
+            .copy_from_slice(&page.data()[in_page..in_page + stop - start]);
     }
     Ok(read_len)
 }
 
 fn ramfs_write_file(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     file: Arc<File>,
     buf: &[u8],
     offset: u64,
@@ -276,28 +406,216 @@ fn ramfs_write_file(
     // 获取inode的编号
     let number = inode.number;
     debug!("number: {}", number);
+    let mut pages = inode.mapping.access_pages();
     let mut binding = fs.lock();
-    let ram_inode = binding.get_mut(&number);
-    if ram_inode.is_none() {
+    if !binding.contains_key(&number) {
         return Err("ramfs_write_file: ram_inode is none");
     }
-    let ram_inode = ram_inode.unwrap();
-    if offset as usize + buf.len() > ram_inode.data.len() {
-        ram_inode.data.resize(offset as usize + buf.len(), 0);
+    let offset = offset as usize;
+    let new_len = offset + buf.len();
+    binding.data_mut(number).write(offset, buf);
+    // keep the mapped pages coherent with the data
+    for (index, page) in pages.range_mut(offset / PAGE_SIZE..new_len.div_ceil(PAGE_SIZE)) {
+        let start = max(index * PAGE_SIZE, offset);
+        let stop = min((index + 1) * PAGE_SIZE, new_len);
+        let in_page = start - index * PAGE_SIZE;
+        page.data_mut()[in_page..in_page + stop - start]
+            .copy_from_slice(&buf[start - offset..stop - offset]);
     }
-    unsafe {
-        core::ptr::copy(
-            buf.as_ptr(),
-            ram_inode.data.as_mut_ptr().add(offset as usize),
-            buf.len(),
-        );
+    Ok(buf.len())
+}
+
+/// serve the page of the file from the data, the page is cached in the inode
+/// so all mappings share it
+fn ramfs_fault(fs: Arc<Mutex<RamFs>>, file: Arc<File>, pgoff: usize) -> StrResult<Arc<PageFrame>> {
+    ddebug!("ramfs_fault");
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let mut pages = inode.mapping.access_pages();
+    let binding = fs.lock();
+    let data = &binding.get(&inode.number).unwrap().data;
+    let start = pgoff * PAGE_SIZE;
+    if start >= data.len() {
+        return Err("Bad address");
     }
+    let page = pages.entry(pgoff).or_insert_with(|| {
+        let mut page = Page::new();
+        data.read(start, page.data_mut());
+        page
+    });
+    ddebug!("ramfs_fault end");
+    Ok(page.frame().clone())
+}
 
-    Ok(buf.len())
+/// the page is written back to the data by fsync
+fn ramfs_page_mkwrite(file: Arc<File>, pgoff: usize) -> StrResult<()> {
+    // a frozen filesystem blocks the page dirtying
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    inode
+        .mapping
+        .access_pages()
+        .get_mut(&pgoff)
+        .ok_or("Bad address")?
+        .dirty = true;
+    Ok(())
+}
+
+/// copy the dirty pages of the shared mappings to the data
+fn ramfs_fsync(fs: Arc<Mutex<RamFs>>, file: Arc<File>) -> StrResult<()> {
+    ddebug!("ramfs_fsync");
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let mut pages = inode.mapping.access_pages();
+    let mut binding = fs.lock();
+    ramfs_sync_pages(&mut pages, &mut binding.data_mut(inode.number));
+    ddebug!("ramfs_fsync end");
+    Ok(())
+}
+
+fn ramfs_sync_pages(pages: &mut BTreeMap<usize, Page>, data: &mut ExtentData) {
+    for (index, page) in pages.iter_mut().filter(|(_, p)| p.dirty) {
+        let start = index * PAGE_SIZE;
+        if start < data.len() {
+            let len = min(PAGE_SIZE, data.len() - start);
+            data.write(start, &page.data()[..len]);
+        }
+        page.dirty = false;
+    }
+}
+
+/// manipulate the space of the file, the cached pages are updated in place
+/// so the mappings keep them
+fn ramfs_fallocate(
+    fs: Arc<Mutex<RamFs>>,
+    inode: Arc<Inode>,
+    mode: FallocFlags,
+    offset: usize,
+    len: usize,
+) -> StrResult<()> {
+    ddebug!("ramfs_fallocate");
+    let end = offset + len;
+    let mut pages = inode.mapping.access_pages();
+    let mut binding = fs.lock();
+    let mut data = binding.data_mut(inode.number);
+    let collapse = mode.contains(FallocFlags::FALLOC_FL_COLLAPSE_RANGE);
+    let punch = mode.contains(FallocFlags::FALLOC_FL_PUNCH_HOLE);
+    if collapse {
+        let block = RAM_BLOCK_SIZE as usize;
+        // the range must be whole blocks and can't reach the end of file
+        if !offset.is_multiple_of(block) || !len.is_multiple_of(block) || end >= data.len() {
+            return Err("Invalid argument");
+        }
+    }
+    // the dirty pages of the shared mappings go to the data first
+    ramfs_sync_pages(&mut pages, &mut data);
+    let first = offset / PAGE_SIZE;
+    let last = if collapse {
+        data.collapse(offset, len);
+        usize::MAX
+    } else if punch {
+        data.punch_hole(offset, len);
+        end.div_ceil(PAGE_SIZE)
+    } else if mode.contains(FallocFlags::FALLOC_FL_ZERO_RANGE) {
+        data.zero_range(offset, len);
+        end.div_ceil(PAGE_SIZE)
+    } else {
+        data.allocate(offset, len);
+        first
+    };
+    if !collapse && !mode.contains(FallocFlags::FALLOC_FL_KEEP_SIZE) && end > data.len() {
+        data.set_len(end);
+    }
+    ramfs_refresh_pages(&mut pages, &data, first..last);
+    inode.access_inner().file_size = data.len();
+    ddebug!("ramfs_fallocate end");
+    Ok(())
+}
+
+/// read the cached pages in the range again after the data is changed,
+/// so the shared mappings see the new data
+fn ramfs_refresh_pages(pages: &mut BTreeMap<usize, Page>, data: &ExtentData, range: Range<usize>) {
+    for (index, page) in pages.range_mut(range) {
+        page.data_mut().fill(0);
+        data.read(index * PAGE_SIZE, page.data_mut());
+    }
+}
+
+/// copy the data in the filesystem, the holes of the source stay holes
+///
+/// The blocks can't be shared, so remap_file_range is not supported.
+fn ramfs_copy_file_range(
+    fs: Arc<Mutex<RamFs>>,
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    ddebug!("ramfs_copy_file_range");
+    // the inode numbers of other super blocks mean other files
+    if !Arc::ptr_eq(&src.f_mnt.super_block, &dst.f_mnt.super_block) {
+        return Err("Not support");
+    }
+    let src_inode = src.f_dentry.access_inner().d_inode.clone();
+    let dst_inode = dst.f_dentry.access_inner().d_inode.clone();
+    let (src_off, dst_off) = (src_off as usize, dst_off as usize);
+    let segments = {
+        let mut pages = src_inode.mapping.access_pages();
+        let mut binding = fs.lock();
+        let mut data = binding.data_mut(src_inode.number);
+        // the dirty pages of the shared mappings go to the data first
+        ramfs_sync_pages(&mut pages, &mut data);
+        data.segments(src_off, len)
+    };
+    let mut pages = dst_inode.mapping.access_pages();
+    let mut binding = fs.lock();
+    let mut data = binding.data_mut(dst_inode.number);
+    ramfs_sync_pages(&mut pages, &mut data);
+    data.punch_hole(dst_off, len);
+    for (off, v) in segments {
+        data.write(dst_off + off, &v);
+    }
+    let end = dst_off + len;
+    if end > data.len() {
+        data.set_len(end);
+    }
+    ramfs_refresh_pages(
+        &mut pages,
+        &data,
+        dst_off / PAGE_SIZE..end.div_ceil(PAGE_SIZE),
+    );
+    dst_inode.access_inner().file_size = data.len();
+    ddebug!("ramfs_copy_file_range end");
+    Ok(len)
+}
+
+/// find the data or the hole, the other seeks are done by the vfs
+fn ramfs_llseek(fs: Arc<Mutex<RamFs>>, file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
+    let inode = file.f_dentry.access_inner().d_inode.clone();
+    let binding = fs.lock();
+    let data = &binding.get(&inode.number).unwrap().data;
+    let pos = match whence {
+        SeekFrom::Data(off) => data.seek_data(off as usize),
+        SeekFrom::Hole(off) => data.seek_hole(off as usize),
+        _ => return Err("Not support"),
+    }
+    .ok_or("No such device or address")?;
+    file.access_inner().f_pos = pos;
+    Ok(pos as u64)
+}
+
+/// the blocks of the file are the allocated ones
+fn ramfs_getattr(fs: Arc<Mutex<RamFs>>, dentry: Arc<DirEntry>, stat: &mut Statx) -> StrResult<()> {
+    let inode = dentry.access_inner().d_inode.clone();
+    let binding = fs.lock();
+    let data = &binding.get(&inode.number).unwrap().data;
+    stat.stx_blocks = data.allocated().div_ceil(512) as u64;
+    stat.stx_mask |= StatxMask::STATX_BLOCKS;
+    Ok(())
 }
 
 fn ramfs_link(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
+    fs: Arc<Mutex<RamFs>>,
     old_dentry: Arc<DirEntry>,
     dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
@@ -312,7 +630,7 @@ fn ramfs_link(
 
     new_dentry.access_inner().d_inode = old_inode;
     let dir_lock = dir;
-    assert_eq!(dir_lock.mode, InodeMode::S_DIR);
+    assert!(dir_lock.mode().is_dir());
 
     // create a new inode
     let number = dir_lock.number;
@@ -324,13 +642,9 @@ fn ramfs_link(
     Ok(())
 }
 
-fn ramfs_unlink(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
-    dir: Arc<Inode>,
-    dentry: Arc<DirEntry>,
-) -> StrResult<()> {
+fn ramfs_unlink(fs: Arc<Mutex<RamFs>>, dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
     ddebug!("ramfs_unlink");
-    assert_eq!(dir.mode, InodeMode::S_DIR);
+    assert!(dir.mode().is_dir());
     let name = dentry.access_inner().d_name.clone();
 
     let inode = dentry.access_inner().d_inode.clone();
@@ -355,9 +669,183 @@ fn ramfs_unlink(
     Ok(())
 }
 
+/// remove an empty dir, the parent loses the link from ".." of the dir
+fn ramfs_rmdir(fs: Arc<Mutex<RamFs>>, dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
+    ddebug!("ramfs_rmdir");
+    let mut bind = fs.lock();
+    let ram_inode = bind.get_mut(&dir.number).unwrap();
+    // check if the dir is empty
+    assert!(!ram_inode.dentries.is_empty());
+    let name = dentry.access_inner().d_name.clone();
+    ram_inode.dentries.remove(&name);
+    dir.access_inner().file_size = ram_inode.dentries.len();
+    ramfs_add_links(&mut bind, &dir, -1);
+
+    let sub_dir = dentry.access_inner().d_inode.clone();
+    sub_dir.access_inner().hard_links = 0;
+    // delete the sub dir
+    bind.remove(&sub_dir.number);
+    ddebug!("ramfs_rmdir end");
+    Ok(())
+}
+
+/// move old_dentry in old_dir to new_dentry in new_dir, the existing target is replaced
+///
+/// the link count of the dirs changes when a dir is moved or replaced
+/// * flags: RENAME_EXCHANGE swaps the two entries, RENAME_WHITEOUT leaves a whiteout
+///   with the inode number `whiteout` at the old place
+fn ramfs_rename(
+    fs: Arc<Mutex<RamFs>>,
+    old_dir: Arc<Inode>,
+    old_dentry: Arc<DirEntry>,
+    new_dir: Arc<Inode>,
+    new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
+    whiteout: Option<usize>,
+) -> StrResult<()> {
+    ddebug!("ramfs_rename");
+    let old_inode = old_dentry.access_inner().d_inode.clone();
+    let old_name = old_dentry.access_inner().d_name.clone();
+    let new_name = new_dentry.access_inner().d_name.clone();
+    let mut bind = fs.lock();
+    let target = bind
+        .get(&new_dir.number)
+        .unwrap()
+        .dentries
+        .get(&new_name)
+        .cloned();
+    if flags.contains(RenameFlag::RENAME_NOREPLACE) && target.is_some() {
+        return Err("File exists");
+    }
+    if flags.contains(RenameFlag::RENAME_EXCHANGE) {
+        let target = target.ok_or("No such file or directory")?;
+        ramfs_exchange(
+            &mut bind,
+            &old_dir,
+            &old_dentry,
+            &new_dir,
+            &new_dentry,
+            target,
+        );
+        ddebug!("ramfs_rename end");
+        return Ok(());
+    }
+    let old_dir_inode = bind.get_mut(&old_dir.number).unwrap();
+    old_dir_inode.dentries.remove(&old_name);
+    old_dir.access_inner().file_size = old_dir_inode.dentries.len();
+    debug!("update old dir over ....");
+
+    let new_dir_inode = bind.get_mut(&new_dir.number).unwrap();
+    let replaced = new_dir_inode.dentries.insert(new_name, old_inode.number);
+    new_dir.access_inner().file_size = new_dir_inode.dentries.len();
+    let mut replaced_dir = false;
+    if replaced.is_some() {
+        debug!("replace the old target");
+        let new_file = new_dentry.access_inner().d_inode.clone();
+        replaced_dir = new_file.mode().is_dir();
+        let mut new_file_inner = new_file.access_inner();
+        if replaced_dir {
+            new_file_inner.hard_links = 0;
+        } else {
+            new_file_inner.hard_links -= 1;
+            bind.get_mut(&new_file.number).unwrap().hard_links -= 1;
+        }
+        // mark the target as invalid if there is no other hard link
+        if new_file_inner.hard_links == 0 {
+            new_file_inner.flags = InodeFlags::S_INVALID;
+            bind.remove(&new_file.number);
+        }
+    }
+    if old_inode.mode().is_dir() {
+        ramfs_add_links(&mut bind, &old_dir, -1);
+        ramfs_add_links(&mut bind, &new_dir, 1);
+    }
+    if replaced_dir {
+        ramfs_add_links(&mut bind, &new_dir, -1);
+    }
+    if let Some(number) = whiteout {
+        ramfs_whiteout(&mut bind, &old_dir, &old_dentry, old_name, number)?;
+    }
+    ddebug!("ramfs_rename end");
+    Ok(())
+}
+
+/// swap the two entries, a dir moved to another dir changes the link counts
+fn ramfs_exchange(
+    fs: &mut HashMap<usize, RamFsInode>,
+    old_dir: &Arc<Inode>,
+    old_dentry: &Arc<DirEntry>,
+    new_dir: &Arc<Inode>,
+    new_dentry: &Arc<DirEntry>,
+    target: usize,
+) {
+    let old_inode = old_dentry.access_inner().d_inode.clone();
+    let new_inode = new_dentry.access_inner().d_inode.clone();
+    let old_name = old_dentry.access_inner().d_name.clone();
+    let new_name = new_dentry.access_inner().d_name.clone();
+    let old_dir_inode = fs.get_mut(&old_dir.number).unwrap();
+    old_dir_inode.dentries.insert(old_name, target);
+    let new_dir_inode = fs.get_mut(&new_dir.number).unwrap();
+    new_dir_inode.dentries.insert(new_name, old_inode.number);
+    if Arc::ptr_eq(old_dir, new_dir) {
+        return;
+    }
+    let (old_is_dir, new_is_dir) = (old_inode.mode().is_dir(), new_inode.mode().is_dir());
+    if old_is_dir && !new_is_dir {
+        ramfs_add_links(fs, old_dir, -1);
+        ramfs_add_links(fs, new_dir, 1);
+    } else if !old_is_dir && new_is_dir {
+        ramfs_add_links(fs, old_dir, 1);
+        ramfs_add_links(fs, new_dir, -1);
+    }
+}
+
+/// create a whiteout, a character device with device number 0, named name in dir
+fn ramfs_whiteout(
+    fs: &mut HashMap<usize, RamFsInode>,
+    dir: &Arc<Inode>,
+    old_dentry: &Arc<DirEntry>,
+    name: String,
+    number: usize,
+) -> StrResult<()> {
+    let mode = Mode::S_CHARDEV;
+    let sb_blk = dir.super_blk.upgrade().unwrap();
+    let inode = create_tmp_inode_from_sb_blk(
+        sb_blk,
+        number,
+        mode,
+        0,
+        InodeOps::empty(),
+        FileOps::empty(),
+        None,
+    )?;
+    fs.insert(number, RamFsInode::new(mode, number));
+    let dir_inode = fs.get_mut(&dir.number).unwrap();
+    dir_inode.dentries.insert(name.clone(), number);
+    dir.access_inner().file_size = dir_inode.dentries.len();
+    // the whiteout takes the place of the old dentry in the cache
+    let parent = old_dentry.access_inner().parent.clone();
+    let dentry = DirEntry::new(
+        DirFlags::empty(),
+        inode,
+        DirEntryOps::empty(),
+        parent.clone(),
+        &name,
+    );
+    parent.upgrade().unwrap().insert_child(Arc::new(dentry));
+    Ok(())
+}
+
+/// change the link count of the inode and the ram inode together
+fn ramfs_add_links(fs: &mut HashMap<usize, RamFsInode>, inode: &Arc<Inode>, delta: i32) {
+    let ram_inode = fs.get_mut(&inode.number).unwrap();
+    ram_inode.hard_links = ram_inode.hard_links.saturating_add_signed(delta);
+    inode.access_inner().hard_links = ram_inode.hard_links;
+}
+
 fn ramfs_symlink(
-    fs: Arc<Mutex<HashMap<usize, RamFsInode>>>,
-    mode: FileMode,
+    fs: Arc<Mutex<RamFs>>,
+    mode: Mode,
     number: usize,
     dir: Arc<Inode>,
     dentry: Arc<DirEntry>,
@@ -369,28 +857,32 @@ fn ramfs_symlink(
     let inode = ramfs_create_inode(
         fs.clone(),
         dir,
-        InodeMode::S_SYMLINK,
         mode,
+        0,
         number,
         inode_ops,
         file_ops,
         dentry.access_inner().d_name.clone(),
     )?;
-    let mut fs_lk = fs.lock();
-    let ram_inode = fs_lk.get_mut(&number).unwrap();
-    ram_inode.data.extend_from_slice(target.as_bytes());
+    fs.lock().data_mut(number).write(0, target.as_bytes());
     inode.access_inner().file_size = target.len();
     dentry.access_inner().d_inode = inode;
     ddebug!("ramfs_symlink end");
     Ok(())
 }
 
+fn ramfs_truncate(fs: Arc<Mutex<RamFs>>, inode: Arc<Inode>) -> StrResult<()> {
+    let number = inode.number;
+    let mut bind = fs.lock();
+    let new_size = inode.access_inner().file_size;
+    // the file grows with a hole, no block is allocated
+    bind.data_mut(number).set_len(new_size);
+    Ok(())
+}
+
 fn ramfs_read_link(ram_inode: &RamFsInode, buf: &mut [u8]) -> StrResult<usize> {
     ddebug!("ramfs_read_link");
-    let read_len = core::cmp::min(buf.len(), ram_inode.data.len());
-    unsafe {
-        core::ptr::copy(ram_inode.data.as_ptr(), buf.as_mut_ptr(), read_len);
-    }
+    let read_len = ram_inode.data.read(0, buf);
     ddebug!("ramfs_read_link end");
     Ok(read_len)
 }
@@ -398,7 +890,7 @@ fn ramfs_read_link(ram_inode: &RamFsInode, buf: &mut [u8]) -> StrResult<usize> {
 /// TODO
 fn ramfs_follow_link(ram_inode: &RamFsInode, lookup_data: &mut LookUpData) -> StrResult<()> {
     ddebug!("ramfs_follow_link");
-    let target_name = ram_inode.data.clone();
+    let target_name = ram_inode.data.to_vec();
     let name = String::from_utf8(target_name).unwrap();
     lookup_data.symlink_names.push(name);
     ddebug!("ramfs_follow_link end");
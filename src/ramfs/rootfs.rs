@@ -1,13 +1,20 @@
 use super::{
-    ramfs_create, ramfs_create_root_dentry, ramfs_create_root_inode, ramfs_follow_link,
-    ramfs_kill_super_blk, ramfs_link, ramfs_mkdir, ramfs_read_file, ramfs_read_link,
-    ramfs_simple_super_blk, ramfs_symlink, ramfs_unlink, ramfs_write_file, RamFsInode,
+    ramfs_copy_file_range, ramfs_create, ramfs_create_root_dentry, ramfs_create_root_inode,
+    ramfs_fallocate, ramfs_fault, ramfs_follow_link, ramfs_fsync, ramfs_getattr,
+    ramfs_kill_super_blk, ramfs_link, ramfs_llseek, ramfs_mkdir, ramfs_mknod, ramfs_page_mkwrite,
+    ramfs_read_file, ramfs_read_link, ramfs_rename, ramfs_rmdir, ramfs_simple_super_blk,
+    ramfs_stat_fs, ramfs_symlink, ramfs_truncate, ramfs_unlink, ramfs_write_file, RamFs,
 };
-use crate::dentry::{DirEntry, Dirent64, DirentType, LookUpData};
-use crate::file::{File, FileMode, FileOps};
-use crate::inode::{Inode, InodeFlags, InodeMode, InodeOps};
+use crate::dentry::{DirEntry, Dirent64, DirentType, LookUpData, RenameFlag};
+use crate::file::{File, FileOps, SeekFrom};
+use crate::inode::{FallocFlags, Inode, InodeOps, Mode};
+use crate::mmap::{VmArea, VmOps};
 use crate::mount::MountFlags;
-use crate::superblock::{DataOps, FileSystemAttr, FileSystemType, FileSystemTypeInner, SuperBlock};
+use crate::page_cache::PageFrame;
+use crate::stat::{StatFlags, Statx, StatxMask};
+use crate::superblock::{
+    DataOps, FileSystemAttr, FileSystemType, FileSystemTypeInner, StatFs, SuperBlock, SuperBlockOps,
+};
 use crate::{ddebug, StrResult};
 use alloc::boxed::Box;
 use alloc::string::ToString;
@@ -15,16 +22,13 @@ use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::min;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use hashbrown::HashMap;
 use lazy_static::lazy_static;
-use log::debug;
 use spin::Mutex;
 
 static INODE_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 lazy_static! {
-    static ref ROOT_FS: Arc<Mutex<HashMap<usize, RamFsInode>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    static ref ROOT_FS: Arc<Mutex<RamFs>> = Arc::new(Mutex::new(RamFs::new()));
 }
 
 pub const ROOTFS_TYPE: FileSystemType = root_fs_type();
@@ -41,6 +45,12 @@ const fn root_fs_type() -> FileSystemType {
     }
 }
 
+const ROOTFS_SB_OPS: SuperBlockOps = {
+    let mut ops = SuperBlockOps::empty();
+    ops.stat_fs = rootfs_stat_fs;
+    ops
+};
+
 const ROOTFS_DIR_INODE_OPS: InodeOps = {
     let mut ops = InodeOps::empty();
     // ops.lookup = rootfs_lookup;
@@ -49,6 +59,7 @@ const ROOTFS_DIR_INODE_OPS: InodeOps = {
     ops.link = rootfs_link;
     ops.unlink = rootfs_unlink;
     ops.symlink = rootfs_symlink;
+    ops.mknod = rootfs_mknod;
     ops.rmdir = rootfs_rmdir;
     ops.get_attr = rootfs_get_attr;
     ops.set_attr = rootfs_set_attr;
@@ -65,6 +76,19 @@ const ROOTFS_FILE_INODE_OPS: InodeOps = {
     ops.remove_attr = rootfs_remove_attr;
     ops.list_attr = rootfs_list_attr;
     ops.truncate = rootfs_truncate;
+    ops.fallocate = rootfs_fallocate;
+    ops.getattr = rootfs_getattr;
+    ops
+};
+
+/// the inode of FIFO, socket and device keeps no data in rootfs
+const ROOTFS_SPECIAL_INODE_OPS: InodeOps = {
+    let mut ops = InodeOps::empty();
+    ops.get_attr = rootfs_get_attr;
+    ops.set_attr = rootfs_set_attr;
+    ops.remove_attr = rootfs_remove_attr;
+    ops.list_attr = rootfs_list_attr;
+    ops.getattr = rootfs_getattr;
     ops
 };
 
@@ -76,6 +100,7 @@ const ROOTFS_SYMLINK_INODE_OPS: InodeOps = {
     ops.set_attr = rootfs_set_attr;
     ops.remove_attr = rootfs_remove_attr;
     ops.list_attr = rootfs_list_attr;
+    ops.getattr = rootfs_getattr;
     ops
 };
 
@@ -84,6 +109,10 @@ const ROOTFS_FILE_FILE_OPS: FileOps = {
     ops.read = rootfs_read_file;
     ops.write = rootfs_write_file;
     ops.open = |_| Ok(());
+    ops.mmap = rootfs_mmap;
+    ops.llseek = rootfs_llseek;
+    ops.fsync = rootfs_fsync;
+    ops.copy_file_range = rootfs_copy_file_range;
     ops.release = rootfs_release;
     ops
 };
@@ -95,6 +124,14 @@ const ROOTFS_SYMLINK_FILE_OPS: FileOps = {
     ops
 };
 
+/// the FIFO is opened as a pipe by the vfs
+const ROOTFS_SPECIAL_FILE_OPS: FileOps = {
+    let mut ops = FileOps::empty();
+    ops.open = |_| Ok(());
+    ops.release = rootfs_release;
+    ops
+};
+
 const ROOTFS_DIR_FILE_OPS: FileOps = {
     let mut ops = FileOps::empty();
     ops.readdir = rootfs_readdir;
@@ -103,6 +140,11 @@ const ROOTFS_DIR_FILE_OPS: FileOps = {
     ops
 };
 
+const ROOTFS_VM_OPS: VmOps = VmOps {
+    fault: rootfs_fault,
+    page_mkwrite: ramfs_page_mkwrite,
+};
+
 fn rootfs_get_super_blk(
     fs_type: Arc<FileSystemType>,
     flags: MountFlags,
@@ -110,19 +152,19 @@ fn rootfs_get_super_blk(
     data: Option<Box<dyn DataOps>>,
 ) -> StrResult<Arc<SuperBlock>> {
     ddebug!("rootfs_get_super_blk");
-    let sb_blk = ramfs_simple_super_blk(fs_type.clone(), flags, dev_name, data)?;
+    let sb_blk = ramfs_simple_super_blk(fs_type.clone(), flags, dev_name, data, ROOTFS_SB_OPS)?;
     assert_eq!(INODE_COUNT.load(Ordering::SeqCst), 0);
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     let inode = ramfs_create_root_inode(
         ROOT_FS.clone(),
         sb_blk.clone(),
-        InodeMode::S_DIR,
+        Mode::S_DIR,
         ROOTFS_DIR_INODE_OPS,
         ROOTFS_DIR_FILE_OPS,
         number,
     )?;
-    // 根目录硬链接计数不用自增1
-    assert_eq!(inode.access_inner().hard_links, 0);
+    // 根目录硬链接计数为2
+    assert_eq!(inode.access_inner().hard_links, 2);
     // 创建目录项
     let dentry = ramfs_create_root_dentry(None, inode)?;
     sb_blk.update_root(dentry);
@@ -130,14 +172,18 @@ fn rootfs_get_super_blk(
     Ok(sb_blk)
 }
 
-fn rootfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, attr: FileMode) -> StrResult<()> {
+fn rootfs_stat_fs(sb_blk: Arc<SuperBlock>) -> StrResult<StatFs> {
+    ramfs_stat_fs(ROOT_FS.clone(), sb_blk)
+}
+
+fn rootfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()> {
     ddebug!("rootfs_mkdir");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_mkdir(
         ROOT_FS.clone(),
         dir,
         dentry,
-        attr,
+        mode,
         number,
         ROOTFS_DIR_INODE_OPS,
         ROOTFS_DIR_FILE_OPS,
@@ -146,7 +192,7 @@ fn rootfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, attr: FileMode) -> StrRe
     Ok(())
 }
 
-fn rootfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrResult<()> {
+fn rootfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()> {
     ddebug!("rootfs_create");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_create(
@@ -162,6 +208,31 @@ fn rootfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrR
     Ok(())
 }
 
+/// create a regular file or a special file
+fn rootfs_mknod(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode, dev: u32) -> StrResult<()> {
+    ddebug!("rootfs_mknod");
+    let (inode_ops, file_ops) = match mode.file_type() {
+        Mode::S_FILE => (ROOTFS_FILE_INODE_OPS, ROOTFS_FILE_FILE_OPS),
+        Mode::S_FIFO | Mode::S_CHARDEV | Mode::S_BLKDEV | Mode::S_SOCK => {
+            (ROOTFS_SPECIAL_INODE_OPS, ROOTFS_SPECIAL_FILE_OPS)
+        }
+        _ => return Err("Invalid argument"),
+    };
+    let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
+    ramfs_mknod(
+        ROOT_FS.clone(),
+        dir,
+        dentry,
+        mode,
+        dev,
+        number,
+        inode_ops,
+        file_ops,
+    )?;
+    ddebug!("rootfs_mknod end");
+    Ok(())
+}
+
 fn rootfs_read_file(file: Arc<File>, buf: &mut [u8], offset: u64) -> StrResult<usize> {
     ddebug!("rootfs_read_file");
     let len = ramfs_read_file(ROOT_FS.clone(), file, buf, offset)?;
@@ -175,6 +246,29 @@ fn rootfs_write_file(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usiz
     Ok(len)
 }
 
+fn rootfs_llseek(file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
+    ramfs_llseek(ROOT_FS.clone(), file, whence)
+}
+fn rootfs_copy_file_range(
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    ramfs_copy_file_range(ROOT_FS.clone(), src, src_off, dst, dst_off, len)
+}
+fn rootfs_mmap(_file: Arc<File>, vma: &mut VmArea) -> StrResult<()> {
+    vma.vm_ops = ROOTFS_VM_OPS;
+    Ok(())
+}
+fn rootfs_fault(file: Arc<File>, pgoff: usize) -> StrResult<Arc<PageFrame>> {
+    ramfs_fault(ROOT_FS.clone(), file, pgoff)
+}
+fn rootfs_fsync(file: Arc<File>, _datasync: bool) -> StrResult<()> {
+    ramfs_fsync(ROOT_FS.clone(), file)
+}
+
 /// create a hard link to the inode
 fn rootfs_link(
     old_dentry: Arc<DirEntry>,
@@ -197,12 +291,17 @@ fn rootfs_unlink(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
 }
 
 /// create a symbolic link
-fn rootfs_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult<()> {
+fn rootfs_symlink(
+    dir: Arc<Inode>,
+    dentry: Arc<DirEntry>,
+    target: &str,
+    mode: Mode,
+) -> StrResult<()> {
     ddebug!("rootfs_symlink");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_symlink(
         ROOT_FS.clone(),
-        FileMode::FMODE_READ,
+        mode,
         number,
         dir,
         dentry,
@@ -284,24 +383,7 @@ fn rootfs_readdir(file: Arc<File>, dirents: &mut [u8]) -> StrResult<usize> {
 }
 
 fn rootfs_rmdir(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
-    ddebug!("rootfs_rmdir");
-    let inode = dir;
-    let number = inode.number;
-    let mut bind = ROOT_FS.lock();
-    let ram_inode = bind.get_mut(&number).unwrap();
-    // check if the dir is empty
-    assert!(!ram_inode.dentries.is_empty());
-    // delete the sub dir
-    // find name from data
-    let name = dentry.access_inner().d_name.clone();
-    ram_inode.dentries.remove(&name);
-
-    let sub_dir = dentry.access_inner().d_inode.clone();
-    let sub_number = sub_dir.number;
-    // delete the sub dir
-    bind.remove(&sub_number);
-    ddebug!("rootfs_rmdir end");
-    Ok(())
+    ramfs_rmdir(ROOT_FS.clone(), dir, dentry)
 }
 
 fn rootfs_get_attr(dentry: Arc<DirEntry>, key: &str, val: &mut [u8]) -> StrResult<usize> {
@@ -370,16 +452,23 @@ fn rootfs_list_attr(dentry: Arc<DirEntry>, buf: &mut [u8]) -> StrResult<usize> {
 }
 
 fn rootfs_truncate(inode: Arc<Inode>) -> StrResult<()> {
-    let number = inode.number;
-    let mut bind = ROOT_FS.lock();
-    let ram_inode = bind.get_mut(&number).unwrap();
-    let new_size = inode.access_inner().file_size;
-    if new_size > ram_inode.data.len() {
-        ram_inode.data.resize(new_size, 0);
-    } else {
-        ram_inode.data.truncate(new_size);
-    }
-    Ok(())
+    ramfs_truncate(ROOT_FS.clone(), inode)
+}
+fn rootfs_fallocate(
+    inode: Arc<Inode>,
+    mode: FallocFlags,
+    offset: usize,
+    len: usize,
+) -> StrResult<()> {
+    ramfs_fallocate(ROOT_FS.clone(), inode, mode, offset, len)
+}
+fn rootfs_getattr(
+    dentry: Arc<DirEntry>,
+    stat: &mut Statx,
+    _mask: StatxMask,
+    _flags: StatFlags,
+) -> StrResult<()> {
+    ramfs_getattr(ROOT_FS.clone(), dentry, stat)
 }
 
 fn rootfs_rename(
@@ -387,35 +476,20 @@ fn rootfs_rename(
     old_dentry: Arc<DirEntry>,
     new_dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
 ) -> StrResult<()> {
-    ddebug!("rootfs_rename");
-    let old_dir_number = old_dir.number;
-    let mut bind = ROOT_FS.lock();
-    let old_dir_inode = bind.get_mut(&old_dir_number).unwrap();
-    let old_name = old_dentry.access_inner().d_name.clone();
-    old_dir_inode.dentries.remove(&old_name);
-    old_dir.access_inner().file_size -= 1;
-
-    debug!("{:?}", old_dir_inode.dentries);
-    debug!("update old dir over ....");
-    let new_dir_number = new_dir.number;
-    let new_dir_inode = bind.get_mut(&new_dir_number).unwrap();
-    let new_name = new_dentry.access_inner().d_name.clone();
-    let is_new = new_dir_inode
-        .dentries
-        .insert(new_name, old_dentry.access_inner().d_inode.number);
-    if is_new.is_some() {
-        debug!("is new file");
-        //mark the new file as invalid
-        let new_file = new_dentry.access_inner().d_inode.clone();
-        new_file.access_inner().flags = InodeFlags::S_INVALID;
-        let new_file_number = new_file.number;
-        bind.remove(&new_file_number);
-    } else {
-        new_dir.access_inner().file_size += 1;
-    }
-    ddebug!("rootfs_rename end");
-    Ok(())
+    let whiteout = flags
+        .contains(RenameFlag::RENAME_WHITEOUT)
+        .then(|| INODE_COUNT.fetch_add(1, Ordering::SeqCst));
+    ramfs_rename(
+        ROOT_FS.clone(),
+        old_dir,
+        old_dentry,
+        new_dir,
+        new_dentry,
+        flags,
+        whiteout,
+    )
 }
 
 fn rootfs_release(file: Arc<File>) -> StrResult<()> {
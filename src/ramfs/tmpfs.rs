@@ -1,24 +1,29 @@
-use crate::dentry::{DirEntry, Dirent64, DirentType, LookUpData};
-use crate::inode::{Inode, InodeFlags, InodeMode, InodeOps};
+use crate::dentry::{DirEntry, Dirent64, DirentType, LookUpData, RenameFlag};
+use crate::inode::{FallocFlags, Inode, InodeOps, Mode};
 use alloc::boxed::Box;
 use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::cmp::min;
 use core::sync::atomic::{AtomicUsize, Ordering};
-use hashbrown::HashMap;
 
 use lazy_static::lazy_static;
-use log::debug;
 
 use super::{
-    ramfs_create, ramfs_create_root_dentry, ramfs_create_root_inode, ramfs_follow_link,
-    ramfs_kill_super_blk, ramfs_link, ramfs_mkdir, ramfs_read_file, ramfs_read_link,
-    ramfs_simple_super_blk, ramfs_symlink, ramfs_unlink, ramfs_write_file, RamFsInode,
+    ramfs_copy_file_range, ramfs_create, ramfs_create_root_dentry, ramfs_create_root_inode,
+    ramfs_fallocate, ramfs_fault, ramfs_follow_link, ramfs_fsync, ramfs_getattr,
+    ramfs_kill_super_blk, ramfs_link, ramfs_llseek, ramfs_mkdir, ramfs_mknod, ramfs_page_mkwrite,
+    ramfs_read_file, ramfs_read_link, ramfs_rename, ramfs_rmdir, ramfs_simple_super_blk,
+    ramfs_stat_fs, ramfs_symlink, ramfs_truncate, ramfs_unlink, ramfs_write_file, RamFs,
 };
-use crate::file::{File, FileMode, FileOps};
+use crate::file::{File, FileOps, SeekFrom};
+use crate::mmap::{VmArea, VmOps};
 use crate::mount::MountFlags;
-use crate::superblock::{DataOps, FileSystemAttr, FileSystemType, FileSystemTypeInner, SuperBlock};
+use crate::page_cache::PageFrame;
+use crate::stat::{StatFlags, Statx, StatxMask};
+use crate::superblock::{
+    DataOps, FileSystemAttr, FileSystemType, FileSystemTypeInner, StatFs, SuperBlock, SuperBlockOps,
+};
 use crate::{ddebug, StrResult};
 use spin::Mutex;
 
@@ -35,10 +40,15 @@ pub const TMP_FS_TYPE: FileSystemType = FileSystemType {
 };
 
 lazy_static! {
-    static ref TMP_FS: Arc<Mutex<HashMap<usize, RamFsInode>>> =
-        Arc::new(Mutex::new(HashMap::new()));
+    static ref TMP_FS: Arc<Mutex<RamFs>> = Arc::new(Mutex::new(RamFs::new()));
 }
 
+const TMPFS_SB_OPS: SuperBlockOps = {
+    let mut ops = SuperBlockOps::empty();
+    ops.stat_fs = tmpfs_stat_fs;
+    ops
+};
+
 const TMPFS_DIR_INODE_OPS: InodeOps = {
     let mut ops = InodeOps::empty();
     ops.mkdir = tmpfs_mkdir;
@@ -46,6 +56,7 @@ const TMPFS_DIR_INODE_OPS: InodeOps = {
     ops.link = tmpfs_link;
     ops.unlink = tmpfs_unlink;
     ops.symlink = tmpfs_symlink;
+    ops.mknod = tmpfs_mknod;
     ops.rmdir = tmpfs_rmdir;
     ops.get_attr = tmpfs_get_attr;
     ops.set_attr = tmpfs_set_attr;
@@ -62,6 +73,19 @@ const TMPFS_FILE_INODE_OPS: InodeOps = {
     ops.remove_attr = tmpfs_remove_attr;
     ops.list_attr = tmpfs_list_attr;
     ops.truncate = tmpfs_truncate;
+    ops.fallocate = tmpfs_fallocate;
+    ops.getattr = tmpfs_getattr;
+    ops
+};
+
+/// the inode of FIFO, socket and device keeps no data in tmpfs
+const TMPFS_SPECIAL_INODE_OPS: InodeOps = {
+    let mut ops = InodeOps::empty();
+    ops.get_attr = tmpfs_get_attr;
+    ops.set_attr = tmpfs_set_attr;
+    ops.remove_attr = tmpfs_remove_attr;
+    ops.list_attr = tmpfs_list_attr;
+    ops.getattr = tmpfs_getattr;
     ops
 };
 
@@ -73,6 +97,7 @@ const TMPFS_SYMLINK_INODE_OPS: InodeOps = {
     ops.set_attr = tmpfs_set_attr;
     ops.remove_attr = tmpfs_remove_attr;
     ops.list_attr = tmpfs_list_attr;
+    ops.getattr = tmpfs_getattr;
     ops
 };
 
@@ -81,6 +106,10 @@ const TMPFS_FILE_FILE_OPS: FileOps = {
     ops.read = tmpfs_read_file;
     ops.write = tmpfs_write_file;
     ops.open = |_| Ok(());
+    ops.mmap = tmpfs_mmap;
+    ops.llseek = tmpfs_llseek;
+    ops.fsync = tmpfs_fsync;
+    ops.copy_file_range = tmpfs_copy_file_range;
     ops
 };
 const TMPFS_SYMLINK_FILE_OPS: FileOps = {
@@ -89,6 +118,13 @@ const TMPFS_SYMLINK_FILE_OPS: FileOps = {
     ops
 };
 
+/// the FIFO is opened as a pipe by the vfs
+const TMPFS_SPECIAL_FILE_OPS: FileOps = {
+    let mut ops = FileOps::empty();
+    ops.open = |_| Ok(());
+    ops
+};
+
 const TMPFS_DIR_FILE_OPS: FileOps = {
     let mut ops = FileOps::empty();
     ops.open = |_| Ok(());
@@ -96,6 +132,11 @@ const TMPFS_DIR_FILE_OPS: FileOps = {
     ops
 };
 
+const TMPFS_VM_OPS: VmOps = VmOps {
+    fault: tmpfs_fault,
+    page_mkwrite: ramfs_page_mkwrite,
+};
+
 fn tmpfs_get_super_blk(
     fs_type: Arc<FileSystemType>,
     flags: MountFlags,
@@ -103,13 +144,13 @@ fn tmpfs_get_super_blk(
     data: Option<Box<dyn DataOps>>,
 ) -> StrResult<Arc<SuperBlock>> {
     ddebug!("tmpfs_get_super_blk");
-    let sb_blk = ramfs_simple_super_blk(fs_type.clone(), flags, dev_name, data)?;
+    let sb_blk = ramfs_simple_super_blk(fs_type.clone(), flags, dev_name, data, TMPFS_SB_OPS)?;
     assert_eq!(INODE_COUNT.load(Ordering::SeqCst), 0);
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     let inode = ramfs_create_root_inode(
         TMP_FS.clone(),
         sb_blk.clone(),
-        InodeMode::S_DIR,
+        Mode::S_DIR,
         TMPFS_DIR_INODE_OPS,
         TMPFS_DIR_FILE_OPS,
         number,
@@ -121,14 +162,18 @@ fn tmpfs_get_super_blk(
     Ok(sb_blk)
 }
 
-fn tmpfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, attr: FileMode) -> StrResult<()> {
+fn tmpfs_stat_fs(sb_blk: Arc<SuperBlock>) -> StrResult<StatFs> {
+    ramfs_stat_fs(TMP_FS.clone(), sb_blk)
+}
+
+fn tmpfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()> {
     ddebug!("tmpfs_mkdir");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_mkdir(
         TMP_FS.clone(),
         dir,
         dentry,
-        attr,
+        mode,
         number,
         TMPFS_DIR_INODE_OPS,
         TMPFS_DIR_FILE_OPS,
@@ -137,7 +182,7 @@ fn tmpfs_mkdir(dir: Arc<Inode>, dentry: Arc<DirEntry>, attr: FileMode) -> StrRes
     Ok(())
 }
 
-fn tmpfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrResult<()> {
+fn tmpfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode) -> StrResult<()> {
     ddebug!("tmpfs_create");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_create(
@@ -153,6 +198,31 @@ fn tmpfs_create(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: FileMode) -> StrRe
     Ok(())
 }
 
+/// create a regular file or a special file
+fn tmpfs_mknod(dir: Arc<Inode>, dentry: Arc<DirEntry>, mode: Mode, dev: u32) -> StrResult<()> {
+    ddebug!("tmpfs_mknod");
+    let (inode_ops, file_ops) = match mode.file_type() {
+        Mode::S_FILE => (TMPFS_FILE_INODE_OPS, TMPFS_FILE_FILE_OPS),
+        Mode::S_FIFO | Mode::S_CHARDEV | Mode::S_BLKDEV | Mode::S_SOCK => {
+            (TMPFS_SPECIAL_INODE_OPS, TMPFS_SPECIAL_FILE_OPS)
+        }
+        _ => return Err("Invalid argument"),
+    };
+    let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
+    ramfs_mknod(
+        TMP_FS.clone(),
+        dir,
+        dentry,
+        mode,
+        dev,
+        number,
+        inode_ops,
+        file_ops,
+    )?;
+    ddebug!("tmpfs_mknod end");
+    Ok(())
+}
+
 fn tmpfs_read_file(file: Arc<File>, buf: &mut [u8], offset: u64) -> StrResult<usize> {
     ddebug!("tmpfs_read_file");
     let len = ramfs_read_file(TMP_FS.clone(), file, buf, offset);
@@ -166,6 +236,29 @@ fn tmpfs_write_file(file: Arc<File>, buf: &[u8], offset: u64) -> StrResult<usize
     len
 }
 
+fn tmpfs_llseek(file: Arc<File>, whence: SeekFrom) -> StrResult<u64> {
+    ramfs_llseek(TMP_FS.clone(), file, whence)
+}
+fn tmpfs_copy_file_range(
+    src: Arc<File>,
+    src_off: u64,
+    dst: Arc<File>,
+    dst_off: u64,
+    len: usize,
+) -> StrResult<usize> {
+    ramfs_copy_file_range(TMP_FS.clone(), src, src_off, dst, dst_off, len)
+}
+fn tmpfs_mmap(_file: Arc<File>, vma: &mut VmArea) -> StrResult<()> {
+    vma.vm_ops = TMPFS_VM_OPS;
+    Ok(())
+}
+fn tmpfs_fault(file: Arc<File>, pgoff: usize) -> StrResult<Arc<PageFrame>> {
+    ramfs_fault(TMP_FS.clone(), file, pgoff)
+}
+fn tmpfs_fsync(file: Arc<File>, _datasync: bool) -> StrResult<()> {
+    ramfs_fsync(TMP_FS.clone(), file)
+}
+
 /// 创建硬链接
 fn tmpfs_link(
     old_dentry: Arc<DirEntry>,
@@ -188,12 +281,17 @@ fn tmpfs_unlink(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
 }
 
 /// create a symbolic link
-fn tmpfs_symlink(dir: Arc<Inode>, dentry: Arc<DirEntry>, target: &str) -> StrResult<()> {
+fn tmpfs_symlink(
+    dir: Arc<Inode>,
+    dentry: Arc<DirEntry>,
+    target: &str,
+    mode: Mode,
+) -> StrResult<()> {
     ddebug!("tmpfs_symlink");
     let number = INODE_COUNT.fetch_add(1, Ordering::SeqCst);
     ramfs_symlink(
         TMP_FS.clone(),
-        FileMode::FMODE_READ,
+        mode,
         number,
         dir,
         dentry,
@@ -275,24 +373,7 @@ fn tmpfs_readdir(file: Arc<File>, dirents: &mut [u8]) -> StrResult<usize> {
 }
 
 fn tmpfs_rmdir(dir: Arc<Inode>, dentry: Arc<DirEntry>) -> StrResult<()> {
-    ddebug!("tmpfs_rmdir");
-    let inode = dir;
-    let number = inode.number;
-    let mut bind = TMP_FS.lock();
-    let ram_inode = bind.get_mut(&number).unwrap();
-    // check if the dir is empty
-    assert!(!ram_inode.dentries.is_empty());
-    // delete the sub dir
-    // find name from data
-    let name = dentry.access_inner().d_name.clone();
-    ram_inode.dentries.remove(&name);
-
-    let sub_dir = dentry.access_inner().d_inode.clone();
-    let sub_number = sub_dir.number;
-    // delete the sub dir
-    bind.remove(&sub_number);
-    ddebug!("tmpfs_rmdir end");
-    Ok(())
+    ramfs_rmdir(TMP_FS.clone(), dir, dentry)
 }
 
 fn tmpfs_get_attr(dentry: Arc<DirEntry>, key: &str, val: &mut [u8]) -> StrResult<usize> {
@@ -338,50 +419,41 @@ fn tmpfs_list_attr(dentry: Arc<DirEntry>, buf: &mut [u8]) -> StrResult<usize> {
     Ok(len)
 }
 fn tmpfs_truncate(inode: Arc<Inode>) -> StrResult<()> {
-    let number = inode.number;
-    let mut bind = TMP_FS.lock();
-    let ram_inode = bind.get_mut(&number).unwrap();
-    let new_size = inode.access_inner().file_size;
-    if new_size > ram_inode.data.len() {
-        ram_inode.data.resize(new_size, 0);
-    } else {
-        ram_inode.data.truncate(new_size);
-    }
-    Ok(())
+    ramfs_truncate(TMP_FS.clone(), inode)
+}
+fn tmpfs_fallocate(
+    inode: Arc<Inode>,
+    mode: FallocFlags,
+    offset: usize,
+    len: usize,
+) -> StrResult<()> {
+    ramfs_fallocate(TMP_FS.clone(), inode, mode, offset, len)
+}
+fn tmpfs_getattr(
+    dentry: Arc<DirEntry>,
+    stat: &mut Statx,
+    _mask: StatxMask,
+    _flags: StatFlags,
+) -> StrResult<()> {
+    ramfs_getattr(TMP_FS.clone(), dentry, stat)
 }
 fn tmpfs_rename(
     old_dir: Arc<Inode>,
     old_dentry: Arc<DirEntry>,
     new_dir: Arc<Inode>,
     new_dentry: Arc<DirEntry>,
+    flags: RenameFlag,
 ) -> StrResult<()> {
-    ddebug!("tmpfs_rename");
-    let old_dir_number = old_dir.number;
-    let mut bind = TMP_FS.lock();
-    let old_dir_inode = bind.get_mut(&old_dir_number).unwrap();
-    let old_name = old_dentry.access_inner().d_name.clone();
-    old_dir_inode.dentries.remove(&old_name);
-    old_dir.access_inner().file_size -= 1;
-
-    debug!("{:?}", old_dir_inode.dentries);
-    drop(old_dir);
-    debug!("update old dir over ....");
-    let new_dir_number = new_dir.number;
-    let new_dir_inode = bind.get_mut(&new_dir_number).unwrap();
-    let new_name = new_dentry.access_inner().d_name.clone();
-    let is_new = new_dir_inode
-        .dentries
-        .insert(new_name, old_dentry.access_inner().d_inode.number);
-    if is_new.is_some() {
-        debug!("is new file");
-        //mark the new file as invalid
-        let new_file = new_dentry.access_inner().d_inode.clone();
-        new_file.access_inner().flags = InodeFlags::S_INVALID;
-        let new_file_number = new_file.number;
-        bind.remove(&new_file_number);
-    } else {
-        new_dir.access_inner().file_size += 1;
-    }
-    ddebug!("tmpfs_rename end");
-    Ok(())
+    let whiteout = flags
+        .contains(RenameFlag::RENAME_WHITEOUT)
+        .then(|| INODE_COUNT.fetch_add(1, Ordering::SeqCst));
+    ramfs_rename(
+        TMP_FS.clone(),
+        old_dir,
+        old_dentry,
+        new_dir,
+        new_dentry,
+        flags,
+        whiteout,
+    )
 }
@@ -1,30 +1,14 @@
-use crate::dentry::{path_walk, LookUpFlags};
-use crate::file::{open_dentry, vfs_open_file, File, FileMode, OpenFlags};
+use crate::dentry::{path_walk, DirEntry, LookUpFlags};
+use crate::file::File;
 use crate::info::{ProcessFs, VfsTime};
-use crate::inode::{simple_statfs, Inode, InodeMode};
-use crate::superblock::StatFs;
+use crate::inode::{should_remove_suid, simple_statfs, DirtyFlags, Inode, InodeFlags, Mode};
+use crate::mount::{mnt_want_write, VfsMount};
+use crate::superblock::{major, makedev, minor, StatFs, StatFsFlags};
+use crate::writeback::mark_inode_dirty;
 use crate::{ddebug, StrResult};
 use alloc::sync::Arc;
 use bitflags::bitflags;
 
-#[derive(Debug, Clone)]
-#[repr(C)]
-pub struct FileAttribute {
-    pub dev: u32,
-    pub ino: usize,
-    pub i_mod: InodeMode,
-    pub nlink: u32,
-    pub uid: u32,
-    pub gid: u32,
-    // pub rdev: u32,
-    pub size: usize,
-    pub blksize: u32,
-    pub blocks: usize,
-    pub atime: VfsTime,
-    pub mtime: VfsTime,
-    pub ctime: VfsTime,
-}
-
 #[derive(Debug, Clone)]
 #[repr(C)]
 pub struct KStat {
@@ -49,82 +33,260 @@ pub struct KStat {
     unused: u64,
 } //128
 
-/// get file attribute
-pub fn vfs_getattr<T: ProcessFs>(file_name: &str, _flag: StatFlags) -> StrResult<KStat> {
-    // now we ignore flag
-    // assert!(flag.is_empty());
-    let file = vfs_open_file::<T>(file_name, OpenFlags::O_RDONLY, FileMode::FMODE_RDWR)?;
-    let inode = file.f_dentry.access_inner().d_inode.clone();
-    let attr = generic_get_file_attribute(inode);
-    Ok(attr)
+#[derive(Debug, Default, Clone, Copy)]
+#[repr(C)]
+pub struct StatxTimestamp {
+    pub tv_sec: i64,
+    pub tv_nsec: u32,
+    __reserved: i32,
+}
+
+impl StatxTimestamp {
+    pub fn new(tv_sec: i64, tv_nsec: u32) -> Self {
+        Self {
+            tv_sec,
+            tv_nsec,
+            __reserved: 0,
+        }
+    }
+}
+
+/// the extended file attribute, the same layout as linux
+#[derive(Debug, Default, Clone)]
+#[repr(C)]
+pub struct Statx {
+    /// which fields are valid
+    pub stx_mask: StatxMask,
+    pub stx_blksize: u32,
+    pub stx_attributes: StatxAttributes,
+    pub stx_nlink: u32,
+    pub stx_uid: u32,
+    pub stx_gid: u32,
+    pub stx_mode: u16,
+    __spare0: u16,
+    pub stx_ino: u64,
+    pub stx_size: u64,
+    pub stx_blocks: u64,
+    /// which attributes are supported by the file system
+    pub stx_attributes_mask: StatxAttributes,
+    pub stx_atime: StatxTimestamp,
+    pub stx_btime: StatxTimestamp,
+    pub stx_ctime: StatxTimestamp,
+    pub stx_mtime: StatxTimestamp,
+    /// the device number of a device file
+    pub stx_rdev_major: u32,
+    pub stx_rdev_minor: u32,
+    /// the device number of the file system
+    pub stx_dev_major: u32,
+    pub stx_dev_minor: u32,
+    pub stx_mnt_id: u64,
+    __spare2: [u64; 13],
+} //256
+
+bitflags! {
+    #[derive(Default)]
+    pub struct StatxMask:u32{
+        const STATX_TYPE = 0x1;
+        const STATX_MODE = 0x2;
+        const STATX_NLINK = 0x4;
+        const STATX_UID = 0x8;
+        const STATX_GID = 0x10;
+        const STATX_ATIME = 0x20;
+        const STATX_MTIME = 0x40;
+        const STATX_CTIME = 0x80;
+        const STATX_INO = 0x100;
+        const STATX_SIZE = 0x200;
+        const STATX_BLOCKS = 0x400;
+        const STATX_BASIC_STATS = 0x7ff;
+        const STATX_BTIME = 0x800;
+        const STATX_MNT_ID = 0x1000;
+    }
+    #[derive(Default)]
+    pub struct StatxAttributes:u64{
+        const STATX_ATTR_COMPRESSED = 0x4;
+        const STATX_ATTR_IMMUTABLE = 0x10;
+        const STATX_ATTR_APPEND = 0x20;
+        const STATX_ATTR_NODUMP = 0x40;
+        const STATX_ATTR_ENCRYPTED = 0x800;
+        const STATX_ATTR_VERITY = 0x100000;
+    }
+}
+
+impl From<&Statx> for KStat {
+    fn from(stx: &Statx) -> Self {
+        KStat {
+            st_dev: makedev(stx.stx_dev_major, stx.stx_dev_minor) as u64,
+            st_ino: stx.stx_ino,
+            st_mode: stx.stx_mode as u32,
+            st_nlink: stx.stx_nlink,
+            st_uid: stx.stx_uid,
+            st_gid: stx.stx_gid,
+            st_rdev: makedev(stx.stx_rdev_major, stx.stx_rdev_minor) as u64,
+            __pad: 0,
+            st_size: stx.stx_size,
+            st_blksize: stx.stx_blksize,
+            __pad2: 0,
+            st_blocks: stx.stx_blocks,
+            st_atime_sec: stx.stx_atime.tv_sec as u64,
+            st_atime_nsec: stx.stx_atime.tv_nsec as u64,
+            st_mtime_sec: stx.stx_mtime.tv_sec as u64,
+            st_mtime_nsec: stx.stx_mtime.tv_nsec as u64,
+            st_ctime_sec: stx.stx_ctime.tv_sec as u64,
+            st_ctime_nsec: stx.stx_ctime.tv_nsec as u64,
+            unused: 0,
+        }
+    }
+}
+
+/// get file attribute, the file is not opened
+/// * flag: AT_SYMLINK_NOFOLLOW gets the attribute of the symlink itself,
+///   AT_EMPTY_PATH allows an empty path which refers to the current directory
+pub fn vfs_getattr<T: ProcessFs>(file_name: &str, flag: StatFlags) -> StrResult<KStat> {
+    ddebug!("vfs_getattr");
+    let stx = vfs_statx::<T>(file_name, flag, StatxMask::STATX_BASIC_STATS)?;
+    ddebug!("vfs_getattr end");
+    Ok(KStat::from(&stx))
 }
 
 pub fn vfs_getattr_by_file(file: Arc<File>) -> StrResult<KStat> {
-    let inode = file.f_dentry.access_inner().d_inode.clone();
-    let attr = generic_get_file_attribute(inode);
-    Ok(attr)
+    let stx = vfs_statx_by_file(file, StatFlags::empty(), StatxMask::STATX_BASIC_STATS)?;
+    Ok(KStat::from(&stx))
+}
+
+/// get the extended file attribute, the file is not opened
+/// * flags: AT_SYMLINK_NOFOLLOW/AT_EMPTY_PATH like [vfs_getattr], AT_STATX_FORCE_SYNC and
+///   AT_STATX_DONT_SYNC are passed to the file system
+/// * mask: the fields the caller wants, the file system may return more or less fields,
+///   `stx_mask` tells which fields are valid
+pub fn vfs_statx<T: ProcessFs>(
+    file_name: &str,
+    flags: StatFlags,
+    mask: StatxMask,
+) -> StrResult<Statx> {
+    ddebug!("vfs_statx");
+    if flags.contains(StatFlags::AT_STATX_SYNC_TYPE) {
+        return Err("Invalid argument");
+    }
+    if file_name.is_empty() && !flags.contains(StatFlags::AT_EMPTY_PATH) {
+        return Err("No such file or directory");
+    }
+    let lookup_flags = if flags.contains(StatFlags::AT_SYMLINK_NOFOLLOW) {
+        LookUpFlags::empty()
+    } else {
+        LookUpFlags::READ_LINK
+    };
+    let lookup_data = path_walk::<T>(file_name, lookup_flags)?;
+    let stx = __vfs_statx(lookup_data.mnt, lookup_data.dentry, flags, mask)?;
+    ddebug!("vfs_statx end");
+    Ok(stx)
+}
+
+pub fn vfs_statx_by_file(file: Arc<File>, flags: StatFlags, mask: StatxMask) -> StrResult<Statx> {
+    if flags.contains(StatFlags::AT_STATX_SYNC_TYPE) {
+        return Err("Invalid argument");
+    }
+    __vfs_statx(file.f_mnt.clone(), file.f_dentry.clone(), flags, mask)
 }
 
-fn generic_get_file_attribute(inode: Arc<Inode>) -> KStat {
+fn __vfs_statx(
+    mnt: Arc<VfsMount>,
+    dentry: Arc<DirEntry>,
+    flags: StatFlags,
+    mask: StatxMask,
+) -> StrResult<Statx> {
+    let inode = dentry.access_inner().d_inode.clone();
+    let mut stx = generic_fillattr(&inode);
+    stx.stx_mnt_id = mnt.mnt_id as u64;
+    stx.stx_mask |= StatxMask::STATX_MNT_ID;
+    // the file system may update the attribute
+    let getattr = inode.inode_ops.getattr;
+    match getattr(dentry, &mut stx, mask, flags) {
+        Ok(()) | Err("Not support") => Ok(stx),
+        Err(e) => Err(e),
+    }
+}
+
+/// fill the attribute with the information in the inode
+fn generic_fillattr(inode: &Arc<Inode>) -> Statx {
     let inner = inode.access_inner();
 
     // TODO！ update dir size
     const PER_DIR_ENTRY_SIZE: usize = 256;
 
-    let size = match inode.mode {
-        InodeMode::S_DIR => inner.file_size * PER_DIR_ENTRY_SIZE,
-        _ => inner.file_size,
+    let size = if inner.mode.is_dir() {
+        inner.file_size * PER_DIR_ENTRY_SIZE
+    } else {
+        inner.file_size
     };
-    let st_blocks = if inode.blk_size == 0 {
+    let blocks = if inode.blk_size == 0 {
         0
     } else {
         (inner.file_size / inode.blk_size as usize) as u64
     };
+    let dev = inode
+        .super_blk
+        .upgrade()
+        .map(|sb| sb.dev_desc)
+        .unwrap_or_default();
+    // the device number of a device file is kept in the inode
+    let rdev = if inner.mode.is_chr() || inner.mode.is_blk() {
+        inode.dev_desc
+    } else {
+        0
+    };
+    let mut attributes = StatxAttributes::empty();
+    if inner.flags.contains(InodeFlags::S_IMMUTABLE) {
+        attributes |= StatxAttributes::STATX_ATTR_IMMUTABLE;
+    }
+    if inner.flags.contains(InodeFlags::S_APPEND) {
+        attributes |= StatxAttributes::STATX_ATTR_APPEND;
+    }
 
-    KStat {
-        st_dev: inode.dev_desc as u64,
-        st_ino: inode.number as u64,
-        st_mode: inode.mode.bits(),
-        st_nlink: inner.hard_links,
-        st_uid: inner.uid,
-        st_gid: inner.gid,
-        st_rdev: 0,
-        __pad: 0,
-        st_size: size as u64,
-        st_blksize: inode.blk_size,
-        __pad2: 0,
-        st_blocks,
-        st_atime_sec: 0,
-        st_atime_nsec: 0,
-        st_mtime_sec: 0,
-        st_mtime_nsec: 0,
-        st_ctime_sec: 0,
-        st_ctime_nsec: 0,
-        unused: 0,
+    Statx {
+        stx_mask: StatxMask::STATX_BASIC_STATS,
+        stx_blksize: inode.blk_size,
+        stx_attributes: attributes,
+        stx_nlink: inner.hard_links,
+        stx_uid: inner.uid,
+        stx_gid: inner.gid,
+        stx_mode: inner.mode.bits() as u16,
+        stx_ino: inode.number as u64,
+        stx_size: size as u64,
+        stx_blocks: blocks,
+        stx_attributes_mask: StatxAttributes::STATX_ATTR_IMMUTABLE
+            | StatxAttributes::STATX_ATTR_APPEND,
+        stx_rdev_major: major(rdev),
+        stx_rdev_minor: minor(rdev),
+        stx_dev_major: major(dev),
+        stx_dev_minor: minor(dev),
+        ..Default::default()
     }
 }
 
 /// get file system info according to file name
 pub fn vfs_statfs<T: ProcessFs>(file_name: &str) -> StrResult<StatFs> {
-    let lookup_data = path_walk::<T>(file_name, LookUpFlags::empty())?;
-    let sb_blk = lookup_data.mnt.super_block.clone();
-    let statfs = sb_blk.super_block_ops.stat_fs;
-    let res = statfs(sb_blk.clone());
-    if res.is_ok() {
-        return res;
-    }
-    simple_statfs(sb_blk)
+    ddebug!("vfs_statfs");
+    let lookup_data = path_walk::<T>(file_name, LookUpFlags::READ_LINK)?;
+    let res = __vfs_statfs(lookup_data.mnt);
+    ddebug!("vfs_statfs end");
+    res
 }
 
 pub fn vfs_statfs_by_file(file: Arc<File>) -> StrResult<StatFs> {
-    let sb_blk = file
-        .f_dentry
-        .access_inner()
-        .d_inode
-        .super_blk
-        .upgrade()
-        .unwrap();
-    simple_statfs(sb_blk)
+    __vfs_statfs(file.f_mnt.clone())
+}
+
+/// ask the file system first and fall back to `simple_statfs`,
+/// the mount flags come from the mount point
+fn __vfs_statfs(mnt: Arc<VfsMount>) -> StrResult<StatFs> {
+    let sb_blk = mnt.super_block.clone();
+    let statfs = sb_blk.super_block_ops.stat_fs;
+    let mut stat = match statfs(sb_blk.clone()) {
+        Ok(stat) => stat,
+        Err(_) => simple_statfs(sb_blk.clone())?,
+    };
+    stat.f_flags = StatFsFlags::from(mnt.flag);
+    Ok(stat)
 }
 
 // set file attribute
@@ -132,6 +294,8 @@ pub fn vfs_setxattr<T: ProcessFs>(file_name: &str, key: &str, value: &[u8]) -> S
     ddebug!("vfs_setxattr");
     let lookup_data = path_walk::<T>(file_name, LookUpFlags::empty())?;
     let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let set_attr = inode.inode_ops.set_attr;
     set_attr(lookup_data.dentry, key, value)?;
     ddebug!("vfs_setxattr end");
@@ -141,6 +305,8 @@ pub fn vfs_setxattr<T: ProcessFs>(file_name: &str, key: &str, value: &[u8]) -> S
 pub fn vfs_setxattr_by_file(file: Arc<File>, key: &str, value: &[u8]) -> StrResult<()> {
     ddebug!("vfs_setxattr_by_file");
     let inode = file.f_dentry.access_inner().d_inode.clone();
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let set_attr = inode.inode_ops.set_attr;
     set_attr(file.f_dentry.clone(), key, value)?;
     ddebug!("vfs_setxattr_by_file end");
@@ -174,6 +340,8 @@ pub fn vfs_removexattr<T: ProcessFs>(file_name: &str, key: &str) -> StrResult<()
     ddebug!("vfs_removexattr");
     let lookup_data = path_walk::<T>(file_name, LookUpFlags::empty())?;
     let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let remove_attr = inode.inode_ops.remove_attr;
     remove_attr(lookup_data.dentry, key)?;
     ddebug!("vfs_removexattr end");
@@ -183,6 +351,8 @@ pub fn vfs_removexattr<T: ProcessFs>(file_name: &str, key: &str) -> StrResult<()
 pub fn vfs_removexattr_by_file(file: Arc<File>, key: &str) -> StrResult<()> {
     ddebug!("vfs_removexattr_by_file");
     let inode = file.f_dentry.access_inner().d_inode.clone();
+    let sb = file.f_mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
     let remove_attr = inode.inode_ops.remove_attr;
     remove_attr(file.f_dentry.clone(), key)?;
     ddebug!("vfs_removexattr_by_file end");
@@ -208,17 +378,115 @@ pub fn vfs_listxattr_by_file(file: Arc<File>, buf: &mut [u8]) -> StrResult<usize
     Ok(len)
 }
 
+/// change the times of a file
+///
+/// Only the owner of the file or a privileged user can do it. The inode doesn't keep
+/// the times, it is marked dirty so the filesystem updates them when it is written back.
 pub fn vfs_set_time<T: ProcessFs>(file_name: &str, _time: [VfsTime; 3]) -> StrResult<()> {
     ddebug!("vfs_set_time");
-    let _lookup_data = open_dentry::<T>(file_name, OpenFlags::O_RDONLY, FileMode::FMODE_READ)?;
+    let lookup_data = path_walk::<T>(file_name, LookUpFlags::READ_LINK)?;
+    if !mnt_want_write(&lookup_data.mnt) {
+        return Err("read only file system");
+    }
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let cred = T::get_cred();
+    if !cred.is_privileged() && cred.euid != inode.access_inner().uid {
+        return Err("Operation not permitted");
+    }
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
     ddebug!("vfs_set_time end");
     Ok(())
 }
 
+/// change the permission bits of a file
+///
+/// Only the owner of the file or a privileged user can do it. The setgid bit is
+/// cleared if the caller is not in the group of the file.
+pub fn vfs_chmod<T: ProcessFs>(file_name: &str, mode: Mode) -> StrResult<()> {
+    ddebug!("vfs_chmod");
+    let lookup_data = path_walk::<T>(file_name, LookUpFlags::READ_LINK)?;
+    if !mnt_want_write(&lookup_data.mnt) {
+        return Err("read only file system");
+    }
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    let cred = T::get_cred();
+    let mut inner = inode.access_inner();
+    let mut perm = mode.permission();
+    if !cred.is_privileged() {
+        if cred.euid != inner.uid {
+            return Err("Operation not permitted");
+        }
+        if cred.egid != inner.gid {
+            perm -= Mode::S_ISGID;
+        }
+    }
+    inner.mode = inner.mode.file_type() | perm;
+    drop(inner);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
+    ddebug!("vfs_chmod end");
+    Ok(())
+}
+
+/// change the owner and group of a file
+/// * uid/gid: the new owner and group, None means unchanged
+///
+/// Only a privileged user can change the owner, the owner of the file can change the group
+/// to its own group. The setuid and setgid bits of a non-directory are cleared.
+pub fn vfs_chown<T: ProcessFs>(
+    file_name: &str,
+    uid: Option<u32>,
+    gid: Option<u32>,
+) -> StrResult<()> {
+    ddebug!("vfs_chown");
+    let lookup_data = path_walk::<T>(file_name, LookUpFlags::READ_LINK)?;
+    if !mnt_want_write(&lookup_data.mnt) {
+        return Err("read only file system");
+    }
+    let inode = lookup_data.dentry.access_inner().d_inode.clone();
+    let sb = lookup_data.mnt.super_block.clone();
+    let _sb_guard = sb.start_write();
+    let cred = T::get_cred();
+    let mut inner = inode.access_inner();
+    if !cred.is_privileged() {
+        let uid_ok = uid.is_none_or(|uid| uid == inner.uid) && cred.euid == inner.uid;
+        let gid_ok = gid.is_none_or(|gid| gid == inner.gid || gid == cred.egid);
+        if !uid_ok || !gid_ok {
+            return Err("Operation not permitted");
+        }
+    }
+    if let Some(uid) = uid {
+        inner.uid = uid;
+    }
+    if let Some(gid) = gid {
+        inner.gid = gid;
+    }
+    if !inner.mode.is_dir() {
+        let kill = should_remove_suid(inner.mode);
+        inner.mode -= kill;
+    }
+    drop(inner);
+    mark_inode_dirty(&inode, DirtyFlags::I_DIRTY_SYNC);
+    ddebug!("vfs_chown end");
+    Ok(())
+}
+
 bitflags! {
     pub struct StatFlags:u32{
         const AT_EMPTY_PATH = 0x1000;
         const AT_NO_AUTOMOUNT = 0x800;
         const AT_SYMLINK_NOFOLLOW = 0x100;
+        /// use the effective ids to check the permission
+        const AT_EACCESS = 0x200;
+        /// do whatever stat does
+        const AT_STATX_SYNC_AS_STAT = 0x0000;
+        /// force the attributes to be synchronized with the server
+        const AT_STATX_FORCE_SYNC = 0x2000;
+        /// don't synchronize anything, use the cached attributes
+        const AT_STATX_DONT_SYNC = 0x4000;
+        const AT_STATX_SYNC_TYPE = 0x6000;
     }
 }
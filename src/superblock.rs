@@ -1,9 +1,10 @@
 use crate::dentry::DirEntry;
 use crate::file::File;
-use crate::info::VfsError;
+use crate::info::{ProcessFs, VfsError, WaitQueue};
 use crate::inode::Inode;
 use crate::mount::MountFlags;
-use crate::{StrResult, ALL_FS};
+use crate::writeback::vfs_syncfs;
+use crate::{ddebug, StrResult, ALL_FS};
 use alloc::boxed::Box;
 use alloc::string::String;
 use alloc::sync::{Arc, Weak};
@@ -11,11 +12,60 @@ use alloc::vec::Vec;
 use bitflags::bitflags;
 use core::fmt::{Debug, Formatter};
 use core::ptr::null;
+use core::sync::atomic::{AtomicU8, AtomicUsize, Ordering};
 use downcast::Any;
-use spin::{Mutex, MutexGuard};
+use spin::{Mutex, MutexGuard, Once};
 
 pub type DevDesc = u32;
 
+/// make a device number from the major and minor number, the same encoding as linux
+pub const fn makedev(major: u32, minor: u32) -> DevDesc {
+    ((major & 0xfff) << 8) | (minor & 0xff) | ((minor & !0xff) << 12)
+}
+
+/// the major number of the device number
+pub const fn major(dev: DevDesc) -> u32 {
+    (dev >> 8) & 0xfff
+}
+
+/// the minor number of the device number
+pub const fn minor(dev: DevDesc) -> u32 {
+    (dev & 0xff) | ((dev >> 12) & 0xfff00)
+}
+
+/// 为没有块设备的文件系统分配设备号
+struct AnonDevAllocator {
+    next: u32,
+    free: Vec<u32>,
+}
+
+static ANON_DEV: Mutex<AnonDevAllocator> = Mutex::new(AnonDevAllocator {
+    next: 1,
+    free: Vec::new(),
+});
+
+/// allocate a unique device number with major 0 for a super block without block device
+pub fn get_anon_dev() -> DevDesc {
+    let mut allocator = ANON_DEV.lock();
+    let minor = match allocator.free.pop() {
+        Some(minor) => minor,
+        None => {
+            let minor = allocator.next;
+            allocator.next += 1;
+            minor
+        }
+    };
+    makedev(0, minor)
+}
+
+/// release the device number allocated by [get_anon_dev]
+pub fn free_anon_dev(dev: DevDesc) {
+    if major(dev) != 0 || minor(dev) == 0 {
+        return;
+    }
+    ANON_DEV.lock().free.push(minor(dev));
+}
+
 #[derive(Debug)]
 pub struct SuperBlock {
     /// 块设备描述符
@@ -39,8 +89,85 @@ pub struct SuperBlock {
     pub blk_dev_name: String,
     /// 其它数据
     pub data: Option<Box<dyn DataOps>>,
+    /// 冻结状态与正在修改文件系统的写者
+    pub writers: SbWriters,
     pub inner: Mutex<SuperBlockInner>,
 }
+
+/// the super block is not frozen
+pub const SB_UNFROZEN: u8 = 0;
+/// the new writers are blocked, waiting for the writers to drain
+pub const SB_FREEZE_WRITE: u8 = 1;
+/// the filesystem is frozen
+pub const SB_FREEZE_COMPLETE: u8 = 2;
+
+/// the freeze state of a super block and the number of writers modifying it
+pub struct SbWriters {
+    frozen: AtomicU8,
+    count: AtomicUsize,
+    /// the writers sleep here until the super block is thawed
+    wait_unfrozen: Once<Box<dyn WaitQueue>>,
+    /// the freezer sleeps here until the last writer is gone
+    wait_drained: Once<Box<dyn WaitQueue>>,
+}
+
+impl SbWriters {
+    pub const fn new() -> Self {
+        SbWriters {
+            frozen: AtomicU8::new(SB_UNFROZEN),
+            count: AtomicUsize::new(0),
+            wait_unfrozen: Once::new(),
+            wait_drained: Once::new(),
+        }
+    }
+    /// the freeze level, one of SB_UNFROZEN, SB_FREEZE_WRITE and SB_FREEZE_COMPLETE
+    pub fn frozen(&self) -> u8 {
+        self.frozen.load(Ordering::SeqCst)
+    }
+    /// the number of writers
+    pub fn count(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+    fn thaw(&self) {
+        self.frozen.store(SB_UNFROZEN, Ordering::SeqCst);
+        if let Some(wait) = self.wait_unfrozen.get() {
+            wait.wake_up();
+        }
+    }
+    fn end_write(&self) {
+        if self.count.fetch_sub(1, Ordering::SeqCst) == 1 && self.frozen() != SB_UNFROZEN {
+            if let Some(wait) = self.wait_drained.get() {
+                wait.wake_up();
+            }
+        }
+    }
+}
+
+impl Debug for SbWriters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SbWriters")
+            .field("frozen", &self.frozen())
+            .field("count", &self.count())
+            .finish()
+    }
+}
+
+impl Default for SbWriters {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// a writer of the super block, the write ends when it is dropped
+pub struct SbWriteGuard<'a> {
+    writers: &'a SbWriters,
+}
+
+impl Drop for SbWriteGuard<'_> {
+    fn drop(&mut self) {
+        self.writers.end_write();
+    }
+}
 #[derive(Debug)]
 pub struct SuperBlockInner {
     /// 脏inode
@@ -51,6 +178,8 @@ pub struct SuperBlockInner {
     pub files: Vec<Arc<File>>,
     /// 文件系统根节点
     pub root: Arc<DirEntry>,
+    /// 超级块需要通过write_super写回
+    pub dirty: bool,
 }
 
 impl SuperBlockInner {
@@ -60,6 +189,7 @@ impl SuperBlockInner {
             sync_inode: Vec::new(),
             files: Vec::new(),
             root: Arc::new(DirEntry::empty()),
+            dirty: false,
         }
     }
 }
@@ -79,6 +209,7 @@ impl SuperBlock {
             super_block_ops: SuperBlockOps::empty(),
             blk_dev_name: String::new(),
             data: None,
+            writers: SbWriters::new(),
             inner: Mutex::new(SuperBlockInner::empty()),
         }
     }
@@ -88,8 +219,12 @@ impl SuperBlock {
 }
 
 impl SuperBlock {
+    /// put the inode on the dirty list if it isn't there
     pub fn insert_dirty_inode(&self, inode: Arc<Inode>) {
-        self.access_inner().dirty_inode.push(inode);
+        let mut inner = self.access_inner();
+        if !inner.dirty_inode.iter().any(|i| Arc::ptr_eq(i, &inode)) {
+            inner.dirty_inode.push(inode);
+        }
     }
     pub fn insert_sync_inode(&self, inode: Arc<Inode>) {
         self.access_inner().sync_inode.push(inode);
@@ -107,6 +242,37 @@ impl SuperBlock {
             .find(|f| Arc::ptr_eq(&f.f_dentry, dentry))
             .cloned()
     }
+    /// start a modification of the filesystem, sleep while the super block is frozen
+    pub fn start_write(&self) -> SbWriteGuard<'_> {
+        loop {
+            if let Some(guard) = self.try_start_write() {
+                return guard;
+            }
+            // the queue is installed by the freezer before the super block is frozen
+            if let Some(wait) = self.writers.wait_unfrozen.get() {
+                // the write can't be interrupted, the frozen state is checked again anyway
+                let _ = wait.wait_event(&mut || self.writers.frozen() == SB_UNFROZEN);
+            }
+        }
+    }
+    /// start a modification of the filesystem, return None if the super block is frozen
+    pub fn try_start_write(&self) -> Option<SbWriteGuard<'_>> {
+        let writers = &self.writers;
+        if writers.frozen() != SB_UNFROZEN {
+            return None;
+        }
+        writers.count.fetch_add(1, Ordering::SeqCst);
+        // the freezer may come between the check and the increment
+        if writers.frozen() != SB_UNFROZEN {
+            writers.end_write();
+            return None;
+        }
+        Some(SbWriteGuard { writers })
+    }
+    /// the super block will be written back by write_super on the next sync
+    pub fn mark_dirty(&self) {
+        self.access_inner().dirty = true;
+    }
     pub fn remove_inode(&self, inode: Arc<Inode>) {
         let mut inner = self.inner.lock();
         inner.dirty_inode.retain(|i| !Arc::ptr_eq(i, &inode));
@@ -175,16 +341,68 @@ impl SuperBlockOps {
     }
 }
 
-#[derive(Debug, Default)]
+/// the max length of a file name
+pub const NAME_MAX: u64 = 255;
+
+bitflags! {
+    /// mount flags reported by statfs, the same value as linux
+    #[derive(Default)]
+    pub struct StatFsFlags: u64 {
+        const ST_RDONLY = 0x1;
+        const ST_NOSUID = 0x2;
+        const ST_NODEV = 0x4;
+        const ST_NOEXEC = 0x8;
+        /// f_flags is valid
+        const ST_VALID = 0x20;
+    }
+}
+
+impl From<MountFlags> for StatFsFlags {
+    fn from(value: MountFlags) -> Self {
+        let mut flags = StatFsFlags::ST_VALID;
+        if value.contains(MountFlags::MNT_READ_ONLY) {
+            flags |= StatFsFlags::ST_RDONLY;
+        }
+        if value.contains(MountFlags::MNT_NOSUID) {
+            flags |= StatFsFlags::ST_NOSUID;
+        }
+        if value.contains(MountFlags::MNT_NO_DEV) {
+            flags |= StatFsFlags::ST_NODEV;
+        }
+        if value.contains(MountFlags::MNT_NO_EXEC) {
+            flags |= StatFsFlags::ST_NOEXEC;
+        }
+        flags
+    }
+}
+
+/// file system statistics, the same layout as linux `struct statfs`
+#[derive(Debug, Default, Clone, Copy)]
 #[repr(C)]
 pub struct StatFs {
-    pub fs_type: u32,
-    pub block_size: u64,
-    pub total_blocks: u64,
-    pub free_blocks: u64,
-    pub total_inodes: u64,
-    pub name_len: u32,
-    pub name: [u8; 32],
+    /// magic number of the file system
+    pub f_type: u64,
+    /// optimal transfer block size
+    pub f_bsize: u64,
+    /// total data blocks in file system
+    pub f_blocks: u64,
+    /// free blocks in file system
+    pub f_bfree: u64,
+    /// free blocks available to unprivileged user
+    pub f_bavail: u64,
+    /// total inodes in file system
+    pub f_files: u64,
+    /// free inodes in file system
+    pub f_ffree: u64,
+    /// file system id
+    pub f_fsid: [u32; 2],
+    /// maximum length of file names
+    pub f_namelen: u64,
+    /// fragment size
+    pub f_frsize: u64,
+    /// mount flags of the file system
+    pub f_flags: StatFsFlags,
+    pub f_spare: [u64; 4],
 }
 
 /// 文件系统类型
@@ -260,6 +478,60 @@ bitflags! {
     }
 }
 
+/// freeze the filesystem for a consistent snapshot of the device
+///
+/// The new writers are blocked, then the writers in progress are waited for and the
+/// filesystem is synced before `freeze_fs` is called.
+pub fn vfs_freeze_super<T: ProcessFs>(sb: Arc<SuperBlock>) -> StrResult<()> {
+    ddebug!("vfs_freeze_super");
+    let writers = &sb.writers;
+    // the writers and the freezer sleep on the wait queues of the kernel
+    writers.wait_unfrozen.call_once(T::new_wait_queue);
+    let wait = writers.wait_drained.call_once(T::new_wait_queue);
+    writers
+        .frozen
+        .compare_exchange(
+            SB_UNFROZEN,
+            SB_FREEZE_WRITE,
+            Ordering::SeqCst,
+            Ordering::SeqCst,
+        )
+        .map_err(|_| "Device or resource busy")?;
+    while writers.count() != 0 {
+        let _ = wait.wait_event(&mut || writers.count() == 0);
+    }
+    let res = vfs_syncfs(sb.clone()).and_then(|_| {
+        let freeze_fs = sb.super_block_ops.freeze_fs;
+        match freeze_fs(sb.clone()) {
+            Err("Not support") => Ok(()),
+            res => res,
+        }
+    });
+    if let Err(e) = res {
+        writers.thaw();
+        return Err(e);
+    }
+    writers.frozen.store(SB_FREEZE_COMPLETE, Ordering::SeqCst);
+    ddebug!("vfs_freeze_super end");
+    Ok(())
+}
+
+/// thaw the filesystem frozen by [vfs_freeze_super], the blocked writers continue
+pub fn vfs_thaw_super(sb: Arc<SuperBlock>) -> StrResult<()> {
+    ddebug!("vfs_thaw_super");
+    if sb.writers.frozen() != SB_FREEZE_COMPLETE {
+        return Err("Invalid argument");
+    }
+    let unfreeze_fs = sb.super_block_ops.unfreeze_fs;
+    match unfreeze_fs(sb.clone()) {
+        Ok(()) | Err("Not support") => {}
+        Err(e) => return Err(e),
+    }
+    sb.writers.thaw();
+    ddebug!("vfs_thaw_super end");
+    Ok(())
+}
+
 /// 注册文件系统
 pub fn register_filesystem(fs: FileSystemType) -> Result<(), &'static str> {
     // 检查此文件系统类型是否已经注册
@@ -313,3 +585,17 @@ pub fn find_super_blk(
         Some(super_blk) => Ok(super_blk.clone()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::superblock::{major, makedev, minor};
+
+    #[test]
+    fn test_makedev() {
+        let dev = makedev(4, 1);
+        assert_eq!(dev, 0x401);
+        assert_eq!((major(dev), minor(dev)), (4, 1));
+        let dev = makedev(259, 0x12345);
+        assert_eq!((major(dev), minor(dev)), (259, 0x12345));
+    }
+}
@@ -0,0 +1,200 @@
+//! 脏inode与超级块的写回
+//!
+//! The VFS marks an inode dirty with [mark_inode_dirty], which puts it on the
+//! `dirty_inode` list of its super block. The writeback moves the inodes to the
+//! `sync_inode` list while writing them:
+//! * the dirty pages are written by [filemap_write]
+//! * the metadata is written by `SuperBlockOps::write_inode`
+//!
+//! [vfs_syncfs] and [vfs_sync] write all dirty inodes, then call `write_super`
+//! and `sync_fs`. [vfs_writeback_periodic] is driven by a timer of the kernel
+//! and writes the inodes that have been dirty for a whole period.
+use crate::buffer_cache::BUFFER_CACHE;
+use crate::inode::{DirtyFlags, Inode};
+use crate::page_cache::filemap_write;
+use crate::superblock::SuperBlock;
+use crate::{ddebug, StrResult, GLOBAL_HASH_MOUNT};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+/// the flag of write_inode, don't wait for the I/O
+pub const WB_SYNC_NONE: u32 = 0;
+/// the flag of write_inode, wait until the inode is on the disk
+pub const WB_SYNC_ALL: u32 = 1;
+
+/// the number of the periodic writeback, the age of the dirty inodes
+static WB_TICK: AtomicU64 = AtomicU64::new(0);
+
+/// mark the inode dirty and put it on the dirty list of its super block
+///
+/// The caller must not hold the lock of the inode.
+pub fn mark_inode_dirty(inode: &Arc<Inode>, flags: DirtyFlags) {
+    let was = {
+        let mut inner = inode.access_inner();
+        let was = inner.dirty;
+        inner.dirty |= flags;
+        if was.is_empty() {
+            inner.dirtied_when = WB_TICK.load(Ordering::SeqCst);
+        }
+        was
+    };
+    if let Some(sb) = inode.super_blk.upgrade() {
+        if flags.intersects(DirtyFlags::I_DIRTY_SYNC | DirtyFlags::I_DIRTY_DATASYNC) {
+            // the filesystem may record the change in its journal
+            let dirty_inode = sb.super_block_ops.dirty_inode;
+            let _ = dirty_inode(inode.clone());
+        }
+        if was.is_empty() {
+            sb.insert_dirty_inode(inode.clone());
+        }
+    }
+}
+
+/// write the dirty pages and the metadata of the inode
+/// * datasync: only write the metadata needed to read the data, like fdatasync
+fn writeback_single_inode(inode: &Arc<Inode>, sync_mode: u32, datasync: bool) -> StrResult<()> {
+    let dirty = inode.access_inner().dirty;
+    if dirty.contains(DirtyFlags::I_DIRTY_PAGES) {
+        // clear the flag first, the pages dirtied during the write set it again
+        inode.access_inner().dirty -= DirtyFlags::I_DIRTY_PAGES;
+        filemap_write(inode).inspect_err(|_| {
+            inode.access_inner().dirty |= DirtyFlags::I_DIRTY_PAGES;
+        })?;
+    }
+    let meta = DirtyFlags::I_DIRTY_SYNC | DirtyFlags::I_DIRTY_DATASYNC;
+    let need = if datasync {
+        dirty.contains(DirtyFlags::I_DIRTY_DATASYNC)
+    } else {
+        dirty.intersects(meta)
+    };
+    if need {
+        inode.access_inner().dirty -= meta;
+        let write_inode = inode
+            .super_blk
+            .upgrade()
+            .map(|sb| sb.super_block_ops.write_inode);
+        if let Some(write_inode) = write_inode {
+            match write_inode(inode.clone(), sync_mode) {
+                Ok(()) | Err("Not support") => {}
+                Err(e) => {
+                    inode.access_inner().dirty |= dirty & meta;
+                    return Err(e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// write the inode now and take it off the dirty list if it is clean, for fsync/fdatasync
+pub fn write_inode_now(inode: &Arc<Inode>, datasync: bool) -> StrResult<()> {
+    ddebug!("write_inode_now");
+    writeback_single_inode(inode, WB_SYNC_ALL, datasync)?;
+    if inode.access_inner().dirty.is_empty() {
+        if let Some(sb) = inode.super_blk.upgrade() {
+            sb.access_inner()
+                .dirty_inode
+                .retain(|i| !Arc::ptr_eq(i, inode));
+        }
+    }
+    ddebug!("write_inode_now end");
+    Ok(())
+}
+
+/// write the dirty inodes of the super block
+/// * expire: only write the inodes dirtied before this tick
+fn writeback_sb_inodes(sb: &Arc<SuperBlock>, sync_mode: u32, expire: Option<u64>) -> StrResult<()> {
+    // move the inodes to the sync list
+    let inodes = {
+        let mut inner = sb.access_inner();
+        let (inodes, keep): (Vec<_>, Vec<_>) = core::mem::take(&mut inner.dirty_inode)
+            .into_iter()
+            .partition(|inode| expire.is_none_or(|tick| inode.access_inner().dirtied_when < tick));
+        inner.dirty_inode = keep;
+        inner.sync_inode.extend(inodes.iter().cloned());
+        inodes
+    };
+    let mut res = Ok(());
+    for inode in inodes {
+        let r = writeback_single_inode(&inode, sync_mode, false);
+        let mut inner = sb.access_inner();
+        inner.sync_inode.retain(|i| !Arc::ptr_eq(i, &inode));
+        // the inode failed or dirtied again goes back to the dirty list
+        if !inode.access_inner().dirty.is_empty()
+            && !inner.dirty_inode.iter().any(|i| Arc::ptr_eq(i, &inode))
+        {
+            inner.dirty_inode.push(inode.clone());
+        }
+        if res.is_ok() {
+            res = r;
+        }
+    }
+    res
+}
+
+/// write all dirty inodes and the super block, then sync the filesystem
+pub fn vfs_syncfs(sb: Arc<SuperBlock>) -> StrResult<()> {
+    ddebug!("vfs_syncfs");
+    let mut res = writeback_sb_inodes(&sb, WB_SYNC_ALL, None);
+    let dirty = core::mem::take(&mut sb.access_inner().dirty);
+    if dirty {
+        let write_super = sb.super_block_ops.write_super;
+        match write_super(sb.clone()) {
+            Ok(()) | Err("Not support") => {}
+            Err(e) => {
+                sb.mark_dirty();
+                res = res.and(Err(e));
+            }
+        }
+    }
+    let sync_fs = sb.super_block_ops.sync_fs;
+    match sync_fs(sb.clone()) {
+        Ok(()) | Err("Not support") => {}
+        Err(e) => res = res.and(Err(e)),
+    }
+    if let Some(device) = &sb.device {
+        res = res.and(BUFFER_CACHE.sync_dirty_buffers(Some(device)));
+    }
+    ddebug!("vfs_syncfs end");
+    res
+}
+
+/// the super blocks of all mounted filesystems
+fn mounted_super_blocks() -> Vec<Arc<SuperBlock>> {
+    let mut sbs: Vec<Arc<SuperBlock>> = Vec::new();
+    for mnt in GLOBAL_HASH_MOUNT.read().iter() {
+        if !sbs.iter().any(|sb| Arc::ptr_eq(sb, &mnt.super_block)) {
+            sbs.push(mnt.super_block.clone());
+        }
+    }
+    sbs
+}
+
+/// sync all mounted filesystems, the first error is returned after all of them are tried
+pub fn vfs_sync() -> StrResult<()> {
+    ddebug!("vfs_sync");
+    let mut res = Ok(());
+    for sb in mounted_super_blocks() {
+        res = res.and(vfs_syncfs(sb));
+    }
+    res = res.and(BUFFER_CACHE.sync_dirty_buffers(None));
+    ddebug!("vfs_sync end");
+    res
+}
+
+/// the periodic writeback, the kernel calls it from a timer
+///
+/// The inodes dirtied before the previous call are written without waiting, so an
+/// inode is written at most two periods after it became dirty.
+pub fn vfs_writeback_periodic() -> StrResult<()> {
+    ddebug!("vfs_writeback_periodic");
+    let tick = WB_TICK.fetch_add(1, Ordering::SeqCst);
+    let mut res = Ok(());
+    for sb in mounted_super_blocks() {
+        res = res.and(writeback_sb_inodes(&sb, WB_SYNC_NONE, Some(tick)));
+    }
+    res = res.and(BUFFER_CACHE.sync_dirty_buffers(None));
+    ddebug!("vfs_writeback_periodic end");
+    res
+}